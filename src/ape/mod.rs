@@ -0,0 +1,151 @@
+pub const APE_TAG_FOOTER_SIZE: usize = 32;
+
+const FLAG_HAS_HEADER: u32 = 1 << 31;
+const FLAG_IS_HEADER: u32 = 1 << 29;
+
+pub enum ApeValue {
+    // UTF-8文本，多个值以空字符分隔
+    Text(Vec<String>),
+    // 二进制数据，封面图片为以0结尾的文件名加图片数据
+    Binary(Vec<u8>),
+    // 指向外部资源的UTF-8链接
+    Link(String),
+}
+
+pub struct ApeItem {
+    pub key: String,
+    pub value: ApeValue,
+}
+
+// APEv1/APEv2标签，foobar2000等程序写在文件末尾ID3v1之前
+pub struct ApeTag {
+    // 1000为APEv1，2000为APEv2
+    pub version: u32,
+    pub items: Vec<ApeItem>,
+}
+
+fn le_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes(data[..4].try_into().unwrap())
+}
+
+// 解析32字节的footer，返回版本、不含header的标签长度、项目数和标志
+pub fn parse_footer(footer: &[u8]) -> Option<(u32, usize, usize, u32)> {
+    if footer.len() < APE_TAG_FOOTER_SIZE || footer[..8] != *b"APETAGEX" {
+        return None;
+    }
+    let flags = le_u32(&footer[20..]);
+    if flags & FLAG_IS_HEADER != 0 {
+        return None;
+    }
+    let size = le_u32(&footer[12..]) as usize;
+    if size < APE_TAG_FOOTER_SIZE {
+        return None;
+    }
+    Some((le_u32(&footer[8..]), size, le_u32(&footer[16..]) as usize, flags))
+}
+
+impl ApeTag {
+    // 在end之前查找标签，返回标签和它在文件中的起始位置，read_at(pos, len)读取指定区域
+    pub fn find(read_at: &mut dyn FnMut(u64, usize) -> Option<Vec<u8>>, end: u64) -> Option<(Self, u64)> {
+        let footer_pos = end.checked_sub(APE_TAG_FOOTER_SIZE as u64)?;
+        let (version, size, count, flags) = parse_footer(&read_at(footer_pos, APE_TAG_FOOTER_SIZE)?)?;
+        let items_pos = end.checked_sub(size as u64)?;
+        let header = if flags & FLAG_HAS_HEADER != 0 { APE_TAG_FOOTER_SIZE as u64 } else { 0 };
+        let start = items_pos.checked_sub(header)?;
+        let items = read_at(items_pos, size - APE_TAG_FOOTER_SIZE)?;
+        Some((Self::parse_items(version, &items, count), start))
+    }
+
+    // 解析footer之前的所有项目，数据不完整时保留已经解析的部分
+    pub fn parse_items(version: u32, mut data: &[u8], count: usize) -> Self {
+        let mut items = Vec::new();
+        while items.len() < count && data.len() >= 9 {
+            let size = le_u32(data) as usize;
+            let flags = le_u32(&data[4..]);
+            let Some(key_end) = data[8..].iter().position(|&b| b == 0) else {
+                break;
+            };
+            let key = String::from_utf8_lossy(&data[8..8 + key_end]).to_string();
+            let value_start = 8 + key_end + 1;
+            let Some(value) = data.get(value_start..value_start + size) else {
+                break;
+            };
+            let value = match (flags >> 1) & 0x03 {
+                1 => ApeValue::Binary(value.to_vec()),
+                2 => ApeValue::Link(String::from_utf8_lossy(value).to_string()),
+                _ => ApeValue::Text(
+                    value
+                        .split(|&b| b == 0)
+                        .map(|s| String::from_utf8_lossy(s).to_string())
+                        .collect(),
+                ),
+            };
+            items.push(ApeItem { key, value });
+            data = &data[value_start + size..];
+        }
+        Self { version, items }
+    }
+
+    // 键不区分大小写
+    pub fn get(&self, key: &str) -> Option<&ApeValue> {
+        self.items
+            .iter()
+            .find(|item| item.key.eq_ignore_ascii_case(key))
+            .map(|item| &item.value)
+    }
+
+    pub fn text(&self, key: &str) -> Option<&[String]> {
+        match self.get(key)? {
+            ApeValue::Text(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    // 封面等二进制项目去掉文件名后的数据
+    pub fn binary(&self, key: &str) -> Option<(&str, &[u8])> {
+        match self.get(key)? {
+            ApeValue::Binary(data) => {
+                let end = data.iter().position(|&b| b == 0)?;
+                Some((std::str::from_utf8(&data[..end]).ok()?, &data[end + 1..]))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(key: &str, flags: u32, value: &[u8]) -> Vec<u8> {
+        let mut data = (value.len() as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(&flags.to_le_bytes());
+        data.extend_from_slice(key.as_bytes());
+        data.push(0);
+        data.extend_from_slice(value);
+        data
+    }
+
+    #[test]
+    fn items_before_footer() {
+        let items = [
+            item("Title", 0, "Caf\u{e9}".as_bytes()),
+            item("Artist", 0, b"A\x00B"),
+            item("Cover Art (Front)", 2, b"cover.jpg\x00\xff\xd8"),
+        ]
+        .concat();
+        let mut footer = b"APETAGEX".to_vec();
+        for value in [2000, items.len() as u32 + 32, 3, 0] {
+            footer.extend_from_slice(&value.to_le_bytes());
+        }
+        footer.extend_from_slice(&[0; 8]);
+        let data = [&[0xffu8; 20][..], &items, &footer].concat();
+
+        let mut read_at = |pos: u64, len: usize| data.get(pos as usize..pos as usize + len).map(<[u8]>::to_vec);
+        let (tag, start) = ApeTag::find(&mut read_at, data.len() as u64).unwrap();
+        assert_eq!(start, 20);
+        assert_eq!(tag.text("TITLE").unwrap(), ["Café"]);
+        assert_eq!(tag.text("artist").unwrap(), ["A", "B"]);
+        assert_eq!(tag.binary("Cover Art (Front)"), Some(("cover.jpg", &[0xff, 0xd8][..])));
+    }
+}
@@ -166,3 +166,64 @@ impl<'a, R: BitReader> BitStream<'a, R> {
 }
 
 
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    buf: Vec<u8>,
+    acc: u64,
+    acc_len: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            acc: 0,
+            acc_len: 0,
+        }
+    }
+
+    // 写入value的低n位，高位在前
+    pub fn write(&mut self, value: usize, n: usize) {
+        if n == 0 {
+            return;
+        }
+        if n > 32 {
+            self.write(value >> 32, n - 32);
+            self.write(value & 0xffff_ffff, 32);
+            return;
+        }
+        self.acc = (self.acc << n) | (value as u64 & ((1u64 << n) - 1));
+        self.acc_len += n;
+        while self.acc_len >= 8 {
+            self.acc_len -= 8;
+            self.buf.push((self.acc >> self.acc_len) as u8);
+        }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write(byte as usize, 8);
+        }
+    }
+
+    pub fn get_bit_offset(&self) -> usize {
+        self.buf.len() * 8 + self.acc_len
+    }
+
+    // 用0补齐到字节边界
+    pub fn align_byte(&mut self) {
+        if self.acc_len > 0 {
+            self.write(0, 8 - self.acc_len);
+        }
+    }
+
+    // 已经写满的字节，不包括未对齐的部分
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        self.align_byte();
+        self.buf
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    id3::date::Date,
+    metadata::{Metadata, ReplayGain},
+    playlist::read_text,
+};
+
+// CUE中时间的最小单位，每秒75帧（CD扇区）
+const FRAMES_PER_SECOND: u64 = 75;
+
+// CUE中的一首，start和end为在文件中的位置，end为None时播放到文件结尾
+#[derive(Debug, Clone)]
+pub struct CueTrack {
+    pub file: PathBuf,
+    pub number: u32,
+    pub start: Duration,
+    pub end: Option<Duration>,
+    pub metadata: Metadata,
+}
+
+// 按空白分隔，双引号中的内容作为一项
+fn tokens(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let (token, after) = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+        };
+        tokens.push(token.to_string());
+        rest = after.trim_start();
+    }
+    tokens
+}
+
+// mm:ss:ff，分钟可以超过99
+fn parse_time(s: &str) -> Option<Duration> {
+    let mut parts = s.split(':').map(|p| p.parse::<u64>().ok());
+    let (minute, second, frame) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || second >= 60 || frame >= FRAMES_PER_SECOND {
+        return None;
+    }
+    let seconds = minute.checked_mul(60)?.checked_add(second)?;
+    Duration::from_secs(seconds).checked_add(Duration::from_nanos(frame * 1_000_000_000 / FRAMES_PER_SECOND))
+}
+
+// 专辑或单曲的字段，REM中的键保存在rem里
+#[derive(Default)]
+struct Fields {
+    title: Option<String>,
+    performer: Option<String>,
+    rem: Vec<(String, String)>,
+}
+
+impl Fields {
+    fn rem(&self, key: &str) -> Option<String> {
+        self.rem.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.clone())
+    }
+}
+
+struct Pending {
+    file: PathBuf,
+    number: u32,
+    start: Option<Duration>,
+    fields: Fields,
+}
+
+impl CueTrack {
+    // base为FILE中相对路径的起点，通常是CUE文件所在的目录，没有INDEX 01的曲目被忽略
+    pub fn parse(text: &str, base: &Path) -> Vec<Self> {
+        let text = text.trim_start_matches('\u{feff}');
+        let mut album = Fields::default();
+        let mut file = None;
+        let mut pending: Vec<Pending> = Vec::new();
+        for line in text.lines() {
+            let tokens = tokens(line);
+            let Some(command) = tokens.first() else {
+                continue;
+            };
+            let arg = |i: usize| tokens.get(i).cloned().unwrap_or_default();
+            // TRACK之后的字段属于该曲目
+            let fields = match pending.last_mut() {
+                Some(track) => &mut track.fields,
+                None => &mut album,
+            };
+            match command.to_ascii_uppercase().as_str() {
+                "FILE" => {
+                    let name = arg(1);
+                    let name = if cfg!(windows) { name } else { name.replace('\\', "/") };
+                    file = Some(base.join(name));
+                }
+                "TRACK" => {
+                    let (Some(file), Ok(number)) = (&file, arg(1).parse()) else {
+                        continue;
+                    };
+                    pending.push(Pending {
+                        file: file.clone(),
+                        number,
+                        start: None,
+                        fields: Fields::default(),
+                    });
+                }
+                "INDEX" => {
+                    if let Some(track) = pending.last_mut() {
+                        if arg(1).parse() == Ok(1) {
+                            track.start = parse_time(&arg(2));
+                        }
+                    }
+                }
+                "TITLE" => fields.title = Some(arg(1)),
+                "PERFORMER" => fields.performer = Some(arg(1)),
+                // REM的值可能带引号也可能由多个词组成
+                "REM" if tokens.len() > 2 => fields.rem.push((arg(1), tokens[2..].join(" "))),
+                _ => {}
+            }
+        }
+
+        let pending: Vec<(Pending, Duration)> =
+            pending.into_iter().filter_map(|track| track.start.map(|start| (track, start))).collect();
+        let total = pending.len() as u32;
+        let mut tracks: Vec<Self> = Vec::new();
+        for (i, (track, start)) in pending.iter().enumerate() {
+            // 同一文件中下一首的开始就是这一首的结尾
+            let end = pending.get(i + 1).filter(|(next, _)| next.file == track.file).map(|(_, start)| *start);
+            let fields = &track.fields;
+            let rem = |key: &str| fields.rem(key).or_else(|| album.rem(key));
+            let metadata = Metadata {
+                title: fields.title.clone(),
+                artists: fields.performer.clone().or_else(|| album.performer.clone()).into_iter().collect(),
+                album_artist: album.performer.clone(),
+                album: album.title.clone(),
+                track: Some(track.number),
+                track_total: Some(total),
+                date: rem("DATE").as_deref().and_then(Date::parse),
+                genres: rem("GENRE").into_iter().collect(),
+                comments: rem("COMMENT").into_iter().collect(),
+                replay_gain: ReplayGain::from_fields(rem),
+                ..Default::default()
+            };
+            tracks.push(Self {
+                file: track.file.clone(),
+                number: track.number,
+                start: *start,
+                end,
+                metadata,
+            });
+        }
+        tracks
+    }
+
+    pub fn load(path: &Path) -> io::Result<Vec<Self>> {
+        let text = read_text(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        Ok(Self::parse(&text, base))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tracks_and_index_points() {
+        let text = "\u{feff}REM GENRE Rock\r\n\
+                    REM DATE 1999\r\n\
+                    REM REPLAYGAIN_ALBUM_GAIN -7.50 dB\r\n\
+                    PERFORMER \"The Band\"\r\n\
+                    TITLE \"Live Album\"\r\n\
+                    FILE \"disc one.mp3\" MP3\r\n\
+                    \x20 TRACK 01 AUDIO\r\n\
+                    \x20   TITLE \"Intro\"\r\n\
+                    \x20   REM REPLAYGAIN_TRACK_GAIN -6.25 dB\r\n\
+                    \x20   INDEX 01 00:00:00\r\n\
+                    \x20 TRACK 02 AUDIO\r\n\
+                    \x20   TITLE \"Song\"\r\n\
+                    \x20   PERFORMER \"Guest\"\r\n\
+                    \x20   INDEX 00 01:58:00\r\n\
+                    \x20   INDEX 01 02:00:37\r\n\
+                    FILE \"disc two.mp3\" MP3\r\n\
+                    \x20 TRACK 03 AUDIO\r\n\
+                    \x20   INDEX 01 00:00:00\r\n";
+        let tracks = CueTrack::parse(text, Path::new("/music"));
+        assert_eq!(tracks.len(), 3);
+
+        let first = &tracks[0];
+        assert_eq!(first.file, Path::new("/music/disc one.mp3"));
+        assert_eq!(first.end, Some(Duration::from_nanos(120_493_333_333)));
+        assert_eq!(first.metadata.title.as_deref(), Some("Intro"));
+        assert_eq!(first.metadata.artists, ["The Band"]);
+        assert_eq!(first.metadata.album.as_deref(), Some("Live Album"));
+        assert_eq!(first.metadata.genres, ["Rock"]);
+        assert_eq!(first.metadata.date.map(|d| d.year), Some(1999));
+        assert_eq!(first.metadata.replay_gain.track_gain, Some(-6.25));
+        assert_eq!(first.metadata.replay_gain.album_gain, Some(-7.5));
+
+        let second = &tracks[1];
+        assert_eq!(second.start, first.end.unwrap());
+        assert_eq!(second.end, None);
+        assert_eq!(second.metadata.artists, ["Guest"]);
+        assert_eq!(second.metadata.track, Some(2));
+        assert_eq!(second.metadata.track_total, Some(3));
+
+        assert_eq!(tracks[2].file, Path::new("/music/disc two.mp3"));
+        assert_eq!(tracks[2].start, Duration::ZERO);
+
+        assert_eq!(parse_time("4294967296:00:00"), Some(Duration::from_secs(4294967296 * 60)));
+        assert_eq!(parse_time("307445734561825860:15:74"), Some(Duration::new(u64::MAX, 986_666_666)));
+        assert_eq!(parse_time("307445734561825861:00:00"), None);
+        assert_eq!(parse_time("00:60:00"), None);
+    }
+}
@@ -2,7 +2,7 @@ use std::f32::consts::PI;
 
 use crate::mpeg_frame::types::{Channel, Granule};
 
-const SINE_BLOCK: [[f32; 36]; 4] = [
+pub const SINE_BLOCK: [[f32; 36]; 4] = [
 	[
 		0.043619387365336, 0.13052619222005157, 0.21643961393810288, 0.3007057995042731, 0.3826834323650898, 0.4617486132350339, 0.5372996083468238, 0.6087614290087207, 0.6755902076156601, 0.737277336810124, 0.7933533402912352, 0.8433914458128856, 0.8870108331782216, 0.9238795325112867, 0.9537169507482268, 0.9762960071199334, 0.9914448613738104, 0.9990482215818578, 0.9990482215818578, 0.9914448613738104, 0.9762960071199334, 0.9537169507482269, 0.9238795325112867, 0.8870108331782218, 0.8433914458128858, 0.7933533402912352, 0.7372773368101241, 0.6755902076156604, 0.6087614290087209, 0.5372996083468241, 0.4617486132350339, 0.3826834323650899, 0.30070579950427334, 0.21643961393810318, 0.13052619222005157, 0.04361938736533607, 
 	],[
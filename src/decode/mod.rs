@@ -5,11 +5,11 @@ pub mod imdct;
 pub mod synthesis;
 pub mod synth_window;
 
-const CS: [f32; 8] = [
+pub const CS: [f32; 8] = [
     0.8574929257, 0.8817419973, 0.9496286491, 0.9833145925,
     0.9955178161, 0.9991605582, 0.9998991952, 0.9999931551
 ];
-const CA: [f32; 8] = [
+pub const CA: [f32; 8] = [
     -0.5144957554, -0.4717319686, -0.3133774542, -0.1819131996,
     -0.0945741925, -0.0409655829, -0.0141985686, -0.0036999747
 ];
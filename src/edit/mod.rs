@@ -0,0 +1,430 @@
+use crate::{
+    bitstream::BitStream,
+    id3::id3v2_tag_size,
+    mpeg_frame::{
+        frame_at, frame_crc, parse_header, parse_side_info,
+        types::{MpegProtection, MpegVersion},
+        xing::{lame_crc16, LameTag, XingHeader},
+        MpegHeader,
+    },
+    tags::FileTags,
+    DecodeError,
+};
+
+const MAX_MAIN_DATA_BEGIN: usize = 511;
+pub const DECODER_DELAY: usize = 529;
+const SAMPLES_PER_FRAME: usize = 1152;
+const MAX_PRIMING_FRAMES: usize = 4;
+
+#[derive(thiserror::Error, Debug)]
+pub enum EditError {
+    #[error("没有找到MPEG音频帧")]
+    NoFrames,
+    #[error("音频流的采样率或声道数不一致，无法拼接")]
+    Incompatible,
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+}
+
+// 排好的各帧及其main_data_begin，以及所有帧的主数据区
+type Placement = (Vec<(AudioFrame, usize)>, Vec<u8>);
+
+// 去掉比特池依赖后的一帧：帧头、side info和这一帧自己的主数据
+#[derive(Clone)]
+pub struct AudioFrame {
+    pub header: [u8; 4],
+    pub side_info: Vec<u8>,
+    pub main_data: Vec<u8>,
+}
+
+impl AudioFrame {
+    fn mpeg_header(&self) -> MpegHeader {
+        parse_header(&self.header).unwrap()
+    }
+
+    fn slot_len(&self) -> usize {
+        let header = self.mpeg_header();
+        header.frame_size() - header.side_info_offset() - header.side_info_size()
+    }
+
+    // 与当前帧格式相同的静音帧，所有granule的part2_3_length为0
+    fn silence(&self) -> Self {
+        let mut header = self.header;
+        header[1] |= 0x01;
+        header[2] &= !0x02;
+        Self {
+            header,
+            side_info: vec![0; self.side_info.len()],
+            main_data: Vec::new(),
+        }
+    }
+
+    fn to_bytes(&self, main_data_begin: usize, slots: &[u8]) -> Vec<u8> {
+        let header = self.mpeg_header();
+        let mut frame = Vec::with_capacity(header.frame_size());
+        frame.extend_from_slice(&self.header);
+        if header.protection == MpegProtection::Protected {
+            frame.extend_from_slice(&[0, 0]);
+        }
+        frame.extend_from_slice(&self.side_info);
+        let pos = header.side_info_offset();
+        frame[pos] = (main_data_begin >> 1) as u8;
+        frame[pos + 1] = frame[pos + 1] & 0x7f | ((main_data_begin & 1) << 7) as u8;
+        if header.protection == MpegProtection::Protected {
+            let crc = frame_crc(&frame, header.side_info_size());
+            frame[4..6].copy_from_slice(&crc.to_be_bytes());
+        }
+        frame.extend_from_slice(slots);
+        frame
+    }
+}
+
+// 按帧编辑的MPEG-1 Layer III流，delay和padding与LAME标签的含义相同
+pub struct Mp3Stream {
+    pub id3v2: Vec<u8>,
+    pub trailer: Vec<u8>,
+    pub frames: Vec<AudioFrame>,
+    pub lame: Option<LameTag>,
+    pub delay: usize,
+    pub padding: usize,
+    pub sample_rate: usize,
+    pub channels: usize,
+}
+
+impl Mp3Stream {
+    pub fn parse(data: &[u8]) -> Result<Self, EditError> {
+        let audio_start = id3v2_tag_size(data);
+        // 末尾的ID3v1、APEv2和Lyrics3标签原样保留
+        let audio_end = FileTags::from_bytes(data).audio_end as usize;
+        let audio = &data[..audio_end];
+
+        let mut frames = Vec::new();
+        let mut xing = None;
+        let mut format = None;
+        // 所有帧的主数据区连接成的字节流，用于按main_data_begin取出每帧的主数据
+        let mut slots: Vec<u8> = Vec::new();
+        let mut pos = audio_start;
+        let mut synced = true;
+        while pos < audio_end {
+            // 流中间重复的ID3v2标签直接跳过
+            let tag_size = id3v2_tag_size(&audio[pos..]);
+            if tag_size > 0 {
+                pos += tag_size;
+                continue;
+            }
+            let Some((header, size)) = frame_at(audio, pos) else {
+                pos += 1;
+                synced = false;
+                continue;
+            };
+            // 丢失同步后要求下一帧也能解析，避免把垃圾数据误认为帧头
+            if !synced && pos + size < audio_end && frame_at(audio, pos + size).is_none() {
+                pos += 1;
+                continue;
+            }
+            synced = true;
+            if header.version != MpegVersion::V1 {
+                return Err(DecodeError::UnsupportedMpegVersion(header.version.to_value()).into());
+            }
+            let frame = &audio[pos..pos + size];
+            pos += size;
+
+            if frames.is_empty() && xing.is_none() {
+                if let Some(header) = XingHeader::parse(frame, &header) {
+                    xing = Some(header);
+                    continue;
+                }
+            }
+            format.get_or_insert((header.sample_rate.get_rate(), header.channel.to_channel_num()));
+
+            let side_start = header.side_info_offset();
+            let side_end = side_start + header.side_info_size();
+            let mut side_info = frame[side_start..side_end].to_vec();
+            let (main_data_begin, bits) = {
+                let mut buf = side_info.clone();
+                let mut bs = BitStream::new(&mut buf);
+                let info = parse_side_info(&header, &mut bs);
+                let channels = header.channel.to_channel_num();
+                let bits: usize = info
+                    .granule
+                    .iter()
+                    .flat_map(|granule| granule.channel[..channels].iter())
+                    .map(|channel| channel.part2_3_length)
+                    .sum();
+                (info.main_data_end, bits)
+            };
+
+            // side info的CRC错误时不能信任这一帧的主数据
+            let crc_error = header.protection == MpegProtection::Protected
+                && frame_crc(frame, header.side_info_size()) != u16::from_be_bytes([frame[4], frame[5]]);
+
+            let slot_start = slots.len();
+            slots.extend_from_slice(&frame[side_end..]);
+            let len = bits.div_ceil(8);
+            let mut header_bytes: [u8; 4] = frame[..4].try_into().unwrap();
+            let main_data = if !crc_error
+                && main_data_begin <= slot_start
+                && slot_start - main_data_begin + len <= slots.len()
+            {
+                let start = slot_start - main_data_begin;
+                slots[start..start + len].to_vec()
+            } else {
+                // 主数据在流开始之前（例如从文件中间截取的流）或CRC错误，只能当作静音帧
+                header_bytes[1] |= 0x01;
+                side_info.iter_mut().for_each(|b| *b = 0);
+                Vec::new()
+            };
+            frames.push(AudioFrame {
+                header: header_bytes,
+                side_info,
+                main_data,
+            });
+        }
+
+        let Some((sample_rate, channels)) = format else {
+            return Err(EditError::NoFrames);
+        };
+        let lame = xing.and_then(|xing| xing.lame);
+        let (delay, padding) = lame
+            .as_ref()
+            .map(|lame| (lame.delay as usize, lame.padding as usize))
+            .unwrap_or((0, 0));
+        Ok(Self {
+            id3v2: data[..audio_start].to_vec(),
+            trailer: data[audio_end..].to_vec(),
+            frames,
+            lame,
+            delay,
+            padding,
+            sample_rate,
+            channels,
+        })
+    }
+
+    // 去掉编码延迟和结尾补充后的有效样本数
+    pub fn samples(&self) -> u64 {
+        (self.frames.len() * SAMPLES_PER_FRAME).saturating_sub(self.delay + self.padding) as u64
+    }
+
+    // 截取有效样本中[start, end)的部分，实际按帧边界切割，多出的样本记录在delay和padding中
+    pub fn cut(&self, start: u64, end: u64) -> Self {
+        let end = end.min(self.samples()) as usize;
+        let start = (start as usize).min(end);
+        let offset = self.delay + DECODER_DELAY;
+
+        // 多保留一帧，使切点处的IMDCT重叠和合成滤波器的状态得以恢复
+        let first = if start == 0 {
+            0
+        } else {
+            (start + offset).saturating_sub(SAMPLES_PER_FRAME) / SAMPLES_PER_FRAME
+        };
+        let last = (end + offset).div_ceil(SAMPLES_PER_FRAME).min(self.frames.len()).max(first);
+        let delay = start + self.delay - first * SAMPLES_PER_FRAME;
+        let padding = ((last - first) * SAMPLES_PER_FRAME).saturating_sub(delay + end - start);
+
+        Self {
+            id3v2: self.id3v2.clone(),
+            trailer: self.trailer.clone(),
+            frames: self.frames[first..last].to_vec(),
+            lame: self.lame.clone(),
+            delay,
+            padding,
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+        }
+    }
+
+    // 依次拼接多个流，标签取自第一个流
+    // 只能在帧边界拼接，中间各流的编码延迟和结尾补充会保留在结果中，数量由join_gap给出
+    pub fn join(streams: &[Mp3Stream]) -> Result<Self, EditError> {
+        let (first, last) = match (streams.first(), streams.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(EditError::NoFrames),
+        };
+        if streams
+            .iter()
+            .any(|s| s.sample_rate != first.sample_rate || s.channels != first.channels)
+        {
+            return Err(EditError::Incompatible);
+        }
+        Ok(Self {
+            id3v2: first.id3v2.clone(),
+            trailer: first.trailer.clone(),
+            frames: streams.iter().flat_map(|s| s.frames.iter().cloned()).collect(),
+            lame: first.lame.clone(),
+            delay: first.delay,
+            padding: last.padding,
+            sample_rate: first.sample_rate,
+            channels: first.channels,
+        })
+    }
+
+    // 拼接后各接缝处残留的样本数：前一个流的结尾补充加上后一个流的编码延迟
+    pub fn join_gap(streams: &[Mp3Stream]) -> u64 {
+        streams
+            .windows(2)
+            .map(|pair| (pair[0].padding + pair[1].delay) as u64)
+            .sum()
+    }
+
+    // 重新排列比特池并生成Xing/LAME头，返回完整的文件内容
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EditError> {
+        if self.frames.is_empty() {
+            return Err(EditError::NoFrames);
+        }
+        let (audio, offsets, priming) = self.layout();
+        let xing_frame = self.xing_frame(&audio, &offsets, self.delay + priming * SAMPLES_PER_FRAME);
+
+        let mut data = self.id3v2.clone();
+        data.extend_from_slice(&xing_frame);
+        data.extend_from_slice(&audio);
+        data.extend_from_slice(&self.trailer);
+        Ok(data)
+    }
+
+    // 开头的帧依赖被切掉的比特池时，在前面插入最少的静音帧为其提供空间
+    // 返回音频数据、每帧的起始位置和插入的静音帧数
+    fn layout(&self) -> (Vec<u8>, Vec<usize>, usize) {
+        let priming = (0..MAX_PRIMING_FRAMES)
+            .find(|&priming| self.place(priming, false).is_some())
+            .unwrap_or(MAX_PRIMING_FRAMES);
+        let (placed, stream) = self.place(priming, true).unwrap();
+
+        let mut audio = Vec::new();
+        let mut offsets = Vec::with_capacity(placed.len());
+        let mut pos = 0;
+        for (frame, main_data_begin) in placed {
+            let slot_len = frame.slot_len();
+            offsets.push(audio.len());
+            audio.extend(frame.to_bytes(main_data_begin, &stream[pos..pos + slot_len]));
+            pos += slot_len;
+        }
+        (audio, offsets, priming)
+    }
+
+    // 把每帧的主数据尽量向前放入比特池，返回各帧及其main_data_begin和主数据流
+    // 中途有帧放不下时，insert为true则在它之前插入静音帧，否则返回None
+    fn place(&self, priming: usize, insert: bool) -> Option<Placement> {
+        let mut placed: Vec<(AudioFrame, usize)> = Vec::new();
+        let mut stream: Vec<u8> = Vec::new();
+        let mut slot_start = 0usize;
+        let mut data_end = 0;
+
+        let silence = self.frames[0].silence();
+        for _ in 0..priming {
+            placed.push((silence.clone(), slot_start - data_end));
+            slot_start += silence.slot_len();
+        }
+        for frame in &self.frames {
+            loop {
+                let begin = data_end.max(slot_start.saturating_sub(MAX_MAIN_DATA_BEGIN));
+                let slot_len = frame.slot_len();
+                if begin + frame.main_data.len() <= slot_start + slot_len {
+                    stream.resize(begin, 0);
+                    stream.extend_from_slice(&frame.main_data);
+                    data_end = stream.len();
+                    placed.push((frame.clone(), slot_start - begin));
+                    slot_start += slot_len;
+                    break;
+                }
+                if !insert {
+                    return None;
+                }
+                // 静音帧没有主数据，只为后面的帧提供比特池空间
+                let silence = frame.silence();
+                placed.push((silence.clone(), slot_start - begin));
+                slot_start += silence.slot_len();
+            }
+        }
+        stream.resize(slot_start, 0);
+        Some((placed, stream))
+    }
+
+    fn xing_frame(&self, audio: &[u8], offsets: &[usize], delay: usize) -> Vec<u8> {
+        let template = self.frames[0].header;
+        let vbr = self.frames.iter().any(|f| f.header[2] >> 4 != template[2] >> 4);
+        // 原来没有LAME标签且不需要记录延迟时只写Xing头
+        let lame = if self.lame.is_some() || delay + self.padding > 0 {
+            let mut lame = self.lame.clone().unwrap_or_else(|| LameTag::new(0, 0));
+            lame.delay = delay as u16;
+            lame.padding = self.padding as u16;
+            Some(lame)
+        } else {
+            None
+        };
+        let mut xing = XingHeader {
+            vbr,
+            frames: Some(offsets.len() as u32),
+            bytes: Some(0),
+            toc: Some([0u8; 100]),
+            quality: None,
+            lame,
+        };
+
+        // 选择能放下Xing头的最小比特率
+        let mut bytes = template;
+        bytes[1] |= 0x01;
+        bytes[2] &= 0x0d;
+        let (header, size) = (1..15)
+            .map(|index| {
+                bytes[2] = bytes[2] & 0x0f | (index << 4);
+                let header = parse_header(&bytes).unwrap();
+                let size = header.frame_size();
+                (header, size)
+            })
+            .find(|(header, size)| *size >= xing.size(header))
+            .unwrap();
+        let total = size + audio.len();
+
+        let mut toc = [0u8; 100];
+        for (i, value) in toc.iter_mut().enumerate() {
+            let offset = size + offsets[i * offsets.len() / 100];
+            *value = (offset * 256 / total).min(255) as u8;
+        }
+        xing.bytes = Some(total as u32);
+        xing.toc = Some(toc);
+        if let Some(lame) = xing.lame.as_mut() {
+            lame.music_length = total as u32;
+            lame.music_crc = lame_crc16(audio);
+        }
+
+        let mut frame = vec![0u8; size];
+        frame[..4].copy_from_slice(&bytes);
+        xing.write(&mut frame, &header);
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{Encoder, ENCODER_DELAY};
+
+    #[test]
+    fn cut_keeps_sample_count() {
+        let pcm: Vec<f32> = (0..44100 * 2)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let mut encoder = Encoder::new(44100, 1, 128).unwrap();
+        let mut data = encoder.encode(&pcm);
+        data.extend(encoder.flush());
+
+        let mut stream = Mp3Stream::parse(&data).unwrap();
+        stream.delay = ENCODER_DELAY;
+        stream.padding = stream.frames.len() * SAMPLES_PER_FRAME - ENCODER_DELAY - pcm.len();
+        let cut = Mp3Stream::parse(&stream.cut(30000, 70000).to_bytes().unwrap()).unwrap();
+        assert_eq!(cut.samples(), 40000);
+        assert!(cut.lame.is_some());
+
+        let parts = [stream.cut(0, 1000), cut];
+        let gap = Mp3Stream::join_gap(&parts);
+        assert_eq!(gap, (parts[0].padding + parts[1].delay) as u64);
+        assert!(gap > 0);
+        let joined = Mp3Stream::join(&parts).unwrap();
+        let joined = Mp3Stream::parse(&joined.to_bytes().unwrap()).unwrap();
+        assert_eq!(joined.delay, ENCODER_DELAY);
+        // 有效样本中包含接缝处残留的样本
+        assert_eq!(joined.samples(), 41000 + gap);
+        assert_eq!(Mp3Stream::join_gap(&parts[..1]), 0);
+    }
+}
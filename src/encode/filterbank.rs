@@ -0,0 +1,115 @@
+use std::f64::consts::PI;
+
+use crate::decode::{imdct::SINE_BLOCK, synth_window::SYNTH_WINDOW, CA, CS};
+
+// 分析多相滤波器组，窗函数C[i]与合成窗D[i]之间满足D[i] = 32 * C[i]
+pub struct AnalysisFilter {
+    x: [f32; 512],
+    m: [[f32; 64]; 32],
+}
+
+impl AnalysisFilter {
+    pub fn new() -> Self {
+        let mut m = [[0f32; 64]; 32];
+        for (k, row) in m.iter_mut().enumerate() {
+            for (i, value) in row.iter_mut().enumerate() {
+                let f = (2.0 * k as f64 + 1.0) * (i as f64 - 16.0) * (PI / 64.0);
+                *value = f64::cos(f) as f32;
+            }
+        }
+        Self { x: [0f32; 512], m }
+    }
+
+    // 输入32个新的PCM样本，输出32个子带各一个样本
+    pub fn filter(&mut self, input: &[f32]) -> [f32; 32] {
+        self.x.copy_within(0..480, 32);
+        for (i, &sample) in input.iter().enumerate() {
+            self.x[31 - i] = sample;
+        }
+
+        let mut y = [0f32; 64];
+        for (i, value) in y.iter_mut().enumerate() {
+            for j in 0..8 {
+                *value += self.x[i + 64 * j] * SYNTH_WINDOW[i + 64 * j] / 32.0;
+            }
+        }
+
+        let mut s = [0f32; 32];
+        for (k, value) in s.iter_mut().enumerate() {
+            *value = self.m[k].iter().zip(y.iter()).map(|(m, y)| m * y).sum();
+        }
+        s
+    }
+}
+
+impl Default for AnalysisFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 长块MDCT，是解码端imdct的逆过程
+pub struct Mdct {
+    cos_table: [[f32; 36]; 18],
+}
+
+impl Mdct {
+    pub fn new() -> Self {
+        let mut cos_table = [[0f32; 36]; 18];
+        for (k, row) in cos_table.iter_mut().enumerate() {
+            for (n, value) in row.iter_mut().enumerate() {
+                let f = PI / 72.0 * (2.0 * n as f64 + 1.0 + 18.0) * (2.0 * k as f64 + 1.0);
+                *value = f64::cos(f) as f32;
+            }
+        }
+        Self { cos_table }
+    }
+
+    // prev和cur分别为上一个和当前granule的子带样本，按[子带][时间]存放
+    pub fn transform(&self, prev: &[[f32; 18]; 32], cur: &[[f32; 18]; 32]) -> [f32; 576] {
+        let mut xr = [0f32; 576];
+        for sb in 0..32 {
+            let mut block = [0f32; 36];
+            block[..18].copy_from_slice(&prev[sb]);
+            block[18..].copy_from_slice(&cur[sb]);
+
+            // 解码端在IMDCT后对奇数子带的奇数样本取反，这里先做相同的处理
+            if sb % 2 == 1 {
+                for value in block.iter_mut().skip(1).step_by(2) {
+                    *value = -*value;
+                }
+            }
+            for (value, w) in block.iter_mut().zip(SINE_BLOCK[0].iter()) {
+                *value *= w;
+            }
+
+            for k in 0..18 {
+                let sum: f32 = block
+                    .iter()
+                    .zip(self.cos_table[k].iter())
+                    .map(|(x, c)| x * c)
+                    .sum();
+                xr[sb * 18 + k] = sum / 9.0;
+            }
+        }
+
+        // 混叠消除蝶形运算，与解码端anti_alias互逆
+        for sb in 1..32 {
+            for i in 0..8 {
+                let pos1 = sb * 18 - i - 1;
+                let pos2 = sb * 18 + i;
+                let s1 = xr[pos1];
+                let s2 = xr[pos2];
+                xr[pos1] = s1 * CS[i] + s2 * CA[i];
+                xr[pos2] = s2 * CS[i] - s1 * CA[i];
+            }
+        }
+        xr
+    }
+}
+
+impl Default for Mdct {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    bitstream::BitWriter,
+    huffman::tables::{
+        BAND_INDEX_L, HUFFMAN_CODE_TABLE, HUFFMAN_CODE_TABLE_QUAD_A, HUFFMAN_CODE_TABLE_QUAD_B,
+        HUFFMAN_CODE_TABLE_SIZE, HUFFMAN_LINBITS,
+    },
+    mpeg_frame::types::Channel,
+};
+
+// 量化值的最大值，15 + 2^13 - 1
+pub const MAX_QUANTIZED_VALUE: usize = 8206;
+
+// 按big value区覆盖的缩放因子带数量划分region0和region1，取自LAME的经验值
+const SUBDV_TABLE: [(usize, usize); 23] = [
+    (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 1), (1, 1), (1, 1),
+    (1, 2), (2, 2), (2, 3), (2, 3), (3, 4), (3, 4), (3, 4), (4, 5),
+    (4, 5), (4, 6), (5, 6), (5, 6), (5, 7), (6, 7), (6, 7),
+];
+
+// 不含linbits的表，按可表示的最大值从小到大排列
+const SMALL_TABLES: [usize; 13] = [1, 2, 3, 5, 6, 7, 8, 9, 10, 11, 12, 13, 15];
+
+fn pair_bits(table_num: usize, x: usize, y: usize) -> usize {
+    let linbits = HUFFMAN_LINBITS[table_num];
+    let table = HUFFMAN_CODE_TABLE[table_num];
+    let mut bits = table[x.min(15)][y.min(15)].bit_length;
+    for v in [x, y] {
+        if linbits != 0 && v >= 15 {
+            bits += linbits;
+        }
+        if v != 0 {
+            bits += 1;
+        }
+    }
+    bits
+}
+
+fn region_bits(ix: &[usize], table_num: usize) -> usize {
+    if table_num == 0 {
+        return 0;
+    }
+    ix.chunks(2).map(|pair| pair_bits(table_num, pair[0], pair[1])).sum()
+}
+
+// 为一个区域选择编码后最短的哈夫曼表，返回(表号, 比特数)
+fn choose_table(ix: &[usize]) -> (usize, usize) {
+    let max = ix.iter().copied().max().unwrap_or(0);
+    if max == 0 {
+        return (0, 0);
+    }
+
+    let candidates: Vec<usize> = if max < 16 {
+        SMALL_TABLES
+            .iter()
+            .copied()
+            .filter(|&t| HUFFMAN_CODE_TABLE_SIZE[t] > max)
+            .collect()
+    } else {
+        // 两组linbits表中各取linbits足够的最小一张
+        let fits = |t: &usize| 15 + (1 << HUFFMAN_LINBITS[*t]) > max;
+        [(16..24).find(fits), (24..32).find(fits)]
+            .into_iter()
+            .flatten()
+            .collect()
+    };
+
+    candidates
+        .into_iter()
+        .map(|t| (t, region_bits(ix, t)))
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap()
+}
+
+fn quad_index(quad: &[usize]) -> usize {
+    quad[0] << 3 | quad[1] << 2 | quad[2] << 1 | quad[3]
+}
+
+fn quad_sign_bits(quad: &[usize]) -> usize {
+    quad.iter().filter(|&&v| v != 0).count()
+}
+
+// 统计量化结果的big value区、count1区并选出哈夫曼表，返回编码所需的比特数
+pub fn count_bits(ix: &[usize; 576], sample_rate: usize, channel: &mut Channel) -> usize {
+    let mut end = 576;
+    while end > 1 && ix[end - 1] == 0 && ix[end - 2] == 0 {
+        end -= 2;
+    }
+    let rzero = end;
+    while end > 3 && ix[end - 4..end].iter().all(|&v| v <= 1) {
+        end -= 4;
+    }
+    // 解码端的count1区最多解码到第572个样本，覆盖到结尾时全部归入big value区
+    if rzero == 576 {
+        end = 576;
+    }
+    channel.big_values = end / 2;
+
+    let count1 = &ix[end..rzero];
+    let bits_a: usize = count1
+        .chunks(4)
+        .map(|q| HUFFMAN_CODE_TABLE_QUAD_A[quad_index(q)].bit_length + quad_sign_bits(q))
+        .sum();
+    let bits_b: usize = count1.chunks(4).map(|q| 4 + quad_sign_bits(q)).sum();
+    channel.count1table_select = if bits_b < bits_a { 1 } else { 0 };
+    let mut bits = bits_a.min(bits_b);
+
+    let band_index = &BAND_INDEX_L[sample_rate];
+    let bands = band_index.iter().position(|&b| b >= end).unwrap_or(22);
+    let (r0, r1) = SUBDV_TABLE[bands];
+    channel.region_address1 = r0;
+    channel.region_address2 = r1;
+
+    let region0_end = band_index[r0 + 1].min(end);
+    let region1_end = band_index[r0 + r1 + 2].min(end);
+    let bounds = [(0, region0_end), (region0_end, region1_end), (region1_end, end)];
+    for (i, &(start, stop)) in bounds.iter().enumerate() {
+        let (table, region) = choose_table(&ix[start..stop]);
+        channel.table_select[i] = table;
+        bits += region;
+    }
+    bits
+}
+
+// 按count_bits选出的参数写入哈夫曼编码数据，sign中为对应样本的符号
+// count1的B表是4位定长码，码字为vwxy按位取反，正好等于QUAD_B[vwxy]
+pub fn write_huffman(bw: &mut BitWriter, ix: &[usize; 576], sign: &[bool; 576], sample_rate: usize, channel: &Channel) {
+    let band_index = &BAND_INDEX_L[sample_rate];
+    let region0_end = band_index[channel.region_address1 + 1];
+    let region1_end = band_index[channel.region_address1 + channel.region_address2 + 2];
+
+    let write_value = |bw: &mut BitWriter, v: usize, negative: bool, linbits: usize| {
+        if linbits != 0 && v >= 15 {
+            bw.write(v - 15, linbits);
+        }
+        if v != 0 {
+            bw.write(negative as usize, 1);
+        }
+    };
+
+    let big_end = channel.big_values * 2;
+    for i in (0..big_end).step_by(2) {
+        let table_num = if i < region0_end {
+            channel.table_select[0]
+        } else if i < region1_end {
+            channel.table_select[1]
+        } else {
+            channel.table_select[2]
+        };
+        if table_num == 0 {
+            continue;
+        }
+        let linbits = HUFFMAN_LINBITS[table_num];
+        let (x, y) = (ix[i], ix[i + 1]);
+        let code = HUFFMAN_CODE_TABLE[table_num][x.min(15)][y.min(15)];
+        bw.write(code.value, code.bit_length);
+        write_value(bw, x, sign[i], linbits);
+        write_value(bw, y, sign[i + 1], linbits);
+    }
+
+    let mut rzero = 576;
+    while rzero > big_end && ix[rzero - 1] == 0 {
+        rzero -= 1;
+    }
+    let mut i = big_end;
+    while i < rzero {
+        let idx = quad_index(&ix[i..i + 4]);
+        let code = if channel.count1table_select == 1 {
+            HUFFMAN_CODE_TABLE_QUAD_B[idx]
+        } else {
+            HUFFMAN_CODE_TABLE_QUAD_A[idx]
+        };
+        bw.write(code.value, code.bit_length);
+        for j in i..i + 4 {
+            if ix[j] != 0 {
+                bw.write(sign[j] as usize, 1);
+            }
+        }
+        i += 4;
+    }
+}
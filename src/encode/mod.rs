@@ -0,0 +1,466 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+};
+
+use filterbank::{AnalysisFilter, Mdct};
+use psy::PsyModel;
+use quantize::{quantize_granule, QuantizeResult, MAX_GRANULE_BITS};
+
+use crate::{bitstream::BitWriter, output::PcmSink};
+
+pub mod filterbank;
+pub mod huffman;
+pub mod psy;
+pub mod quantize;
+
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+const BIT_RATES: [usize; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+const MAX_MAIN_DATA_BEGIN: usize = 511;
+// 编码端滤波器组和MDCT引入的延迟，加上解码端的529个样本即为总延迟
+pub const ENCODER_DELAY: usize = 528;
+
+#[derive(thiserror::Error, Debug)]
+pub enum EncodeError {
+    #[error("不支持的采样率: {0}")]
+    UnsupportedSampleRate(u32),
+    #[error("不支持的比特率: {0}kbps")]
+    UnsupportedBitRate(usize),
+    #[error("不支持的声道数: {0}")]
+    UnsupportedChannels(u16),
+}
+
+// 等待主数据填满的帧
+struct PendingFrame {
+    header: Vec<u8>,
+    slot_start: usize,
+    slot_len: usize,
+}
+
+// MPEG-1 Layer III CBR编码器，只使用长块
+pub struct Encoder {
+    sample_rate: usize,
+    sample_rate_index: usize,
+    channels: usize,
+    bit_rate_index: usize,
+    frame_size_frac: usize,
+    padding_acc: usize,
+
+    filters: [AnalysisFilter; 2],
+    mdct: Mdct,
+    psy: PsyModel,
+    prev_subband: [[[f32; 18]; 32]; 2],
+    input: Vec<f32>,
+
+    // 主数据流，main_data[0]在流中的绝对位置为main_data_offset
+    main_data: Vec<u8>,
+    main_data_offset: usize,
+    data_end: usize,
+    slots_end: usize,
+    pending: VecDeque<PendingFrame>,
+
+    total_samples: u64,
+    frames: u64,
+    padding: usize,
+}
+
+impl Encoder {
+    pub fn new(sample_rate: u32, channels: u16, bit_rate: usize) -> Result<Self, EncodeError> {
+        let sample_rate_index = SAMPLE_RATES
+            .iter()
+            .position(|&r| r == sample_rate)
+            .ok_or(EncodeError::UnsupportedSampleRate(sample_rate))?;
+        let bit_rate_index = BIT_RATES
+            .iter()
+            .skip(1)
+            .position(|&r| r == bit_rate)
+            .ok_or(EncodeError::UnsupportedBitRate(bit_rate))?
+            + 1;
+        if channels != 1 && channels != 2 {
+            return Err(EncodeError::UnsupportedChannels(channels));
+        }
+
+        Ok(Self {
+            sample_rate: sample_rate as usize,
+            sample_rate_index,
+            channels: channels as usize,
+            bit_rate_index,
+            frame_size_frac: 144 * bit_rate * 1000 % sample_rate as usize,
+            padding_acc: 0,
+            filters: [AnalysisFilter::new(), AnalysisFilter::new()],
+            mdct: Mdct::new(),
+            psy: PsyModel::new(sample_rate as usize, sample_rate_index),
+            prev_subband: [[[0f32; 18]; 32]; 2],
+            input: Vec::new(),
+            main_data: Vec::new(),
+            main_data_offset: 0,
+            data_end: 0,
+            slots_end: 0,
+            pending: VecDeque::new(),
+            total_samples: 0,
+            frames: 0,
+            padding: 0,
+        })
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.total_samples
+    }
+
+    // flush时在结尾补充的静音样本数
+    pub fn padding(&self) -> usize {
+        self.padding
+    }
+
+    // 输入交替存放的PCM样本，返回已经完整的帧
+    pub fn encode(&mut self, pcm: &[f32]) -> Vec<u8> {
+        self.total_samples += (pcm.len() / self.channels) as u64;
+        self.input.extend_from_slice(pcm);
+
+        let frame_len = 1152 * self.channels;
+        let mut output = Vec::new();
+        while self.input.len() >= frame_len {
+            let frame: Vec<f32> = self.input.drain(..frame_len).collect();
+            self.encode_frame(&frame);
+            self.emit_frames(&mut output);
+        }
+        output
+    }
+
+    // 用静音补齐最后一帧并多编码一帧，使延迟之后的样本全部输出
+    pub fn flush(&mut self) -> Vec<u8> {
+        let frame_len = 1152 * self.channels;
+        let pad = (frame_len - self.input.len() % frame_len) % frame_len + frame_len;
+        self.padding = pad / self.channels;
+        self.input.extend(std::iter::repeat_n(0.0, pad));
+
+        let mut output = Vec::new();
+        while !self.input.is_empty() {
+            let frame: Vec<f32> = self.input.drain(..frame_len).collect();
+            self.encode_frame(&frame);
+            self.emit_frames(&mut output);
+        }
+
+        // 剩余的空间用0填充
+        self.main_data.resize(self.slots_end - self.main_data_offset, 0);
+        self.data_end = self.slots_end;
+        self.emit_frames(&mut output);
+        output
+    }
+
+    fn side_info_len(&self) -> usize {
+        if self.channels == 1 {
+            17
+        } else {
+            32
+        }
+    }
+
+    // 多相滤波和MDCT，返回[granule][channel]的频谱
+    fn analyze(&mut self, pcm: &[f32]) -> [[[f32; 576]; 2]; 2] {
+        let mut xr = [[[0f32; 576]; 2]; 2];
+        for (gr, granule) in xr.iter_mut().enumerate() {
+            for ch in 0..self.channels {
+                let mut subband = [[0f32; 18]; 32];
+                for t in 0..18 {
+                    let mut input = [0f32; 32];
+                    for (i, value) in input.iter_mut().enumerate() {
+                        *value = pcm[(gr * 576 + t * 32 + i) * self.channels + ch];
+                    }
+                    let s = self.filters[ch].filter(&input);
+                    for sb in 0..32 {
+                        subband[sb][t] = s[sb];
+                    }
+                }
+                granule[ch] = self.mdct.transform(&self.prev_subband[ch], &subband);
+                self.prev_subband[ch] = subband;
+            }
+        }
+        xr
+    }
+
+    fn encode_frame(&mut self, pcm: &[f32]) {
+        let mut xr = self.analyze(pcm);
+
+        // 左右声道相关性较强时使用M/S立体声
+        let mut ms_stereo = false;
+        if self.channels == 2 {
+            let (mut mid, mut side) = (0f32, 0f32);
+            for [left, right] in xr.iter() {
+                for (l, r) in left.iter().zip(right.iter()) {
+                    mid += (l + r).powi(2);
+                    side += (l - r).powi(2);
+                }
+            }
+            ms_stereo = side < mid * 0.25;
+            if ms_stereo {
+                for [left, right] in xr.iter_mut() {
+                    for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                        (*l, *r) = ((*l + *r) / std::f32::consts::SQRT_2, (*l - *r) / std::f32::consts::SQRT_2);
+                    }
+                }
+            }
+        }
+
+        // 帧长与比特池
+        let padding = {
+            self.padding_acc += self.frame_size_frac;
+            if self.padding_acc >= self.sample_rate {
+                self.padding_acc -= self.sample_rate;
+                1
+            } else {
+                0
+            }
+        };
+        let frame_size = 144 * BIT_RATES[self.bit_rate_index] * 1000 / self.sample_rate + padding;
+        let slot_len = frame_size - 4 - self.side_info_len();
+
+        let mut reservoir = self.slots_end - self.data_end;
+        if reservoir > MAX_MAIN_DATA_BEGIN {
+            // 比特池过大时插入填充字节
+            let fill = reservoir - MAX_MAIN_DATA_BEGIN;
+            self.main_data.extend(std::iter::repeat_n(0, fill));
+            self.data_end += fill;
+            reservoir = MAX_MAIN_DATA_BEGIN;
+        }
+
+        // 按感知熵把比特池中的比特分配给各granule
+        let count = 2 * self.channels;
+        let mean_bits = slot_len * 8 / count;
+        // 比特池接近填满时说明比特有富余，降低允许的噪声以免比特被浪费在填充字节上
+        let noise_scale = 1.0 - 0.99 * reservoir as f32 / MAX_MAIN_DATA_BEGIN as f32;
+        let mut xmin = [[[0f32; 22]; 2]; 2];
+        let mut pe = [[0f32; 2]; 2];
+        for gr in 0..2 {
+            for ch in 0..self.channels {
+                xmin[gr][ch] = self.psy.allowed_distortion(&xr[gr][ch]);
+                for value in xmin[gr][ch].iter_mut() {
+                    *value *= noise_scale;
+                }
+                pe[gr][ch] = perceptual_entropy(&xr[gr][ch], &xmin[gr][ch], self.sample_rate_index);
+            }
+        }
+        let pe_total: f32 = pe.iter().flatten().sum::<f32>().max(1.0);
+        let mut budget = (reservoir + slot_len) * 8;
+        let mut pool = reservoir * 8;
+
+        let mut results: Vec<Vec<QuantizeResult>> = Vec::new();
+        let mut index = 0;
+        for gr in 0..2 {
+            let mut granule = Vec::new();
+            for ch in 0..self.channels {
+                let extra = (pool as f32 * pe[gr][ch] / pe_total) as usize;
+                let reserve = mean_bits * (count - index - 1);
+                let max_bits = (mean_bits + extra)
+                    .min(budget - reserve)
+                    .min(MAX_GRANULE_BITS);
+                let result = quantize_granule(&xr[gr][ch], &xmin[gr][ch], self.sample_rate_index, max_bits);
+
+                budget -= result.bits;
+                pool = budget.saturating_sub(mean_bits * (count - index - 1));
+                index += 1;
+                granule.push(result);
+            }
+            results.push(granule);
+        }
+
+        // 写入主数据
+        let mut bw = BitWriter::new();
+        for granule in results.iter() {
+            for result in granule.iter() {
+                let slen = quantize_slen(result.channel.scalefac_compress);
+                for sfb in 0..21 {
+                    let len = if sfb < 11 { slen.0 } else { slen.1 };
+                    bw.write(result.sf.sf_l[sfb], len);
+                }
+                huffman::write_huffman(&mut bw, &result.ix, &result.sign, self.sample_rate_index, &result.channel);
+            }
+        }
+        let main_data = bw.into_bytes();
+
+        let header = self.write_header_and_side_info(padding, ms_stereo, reservoir, &results);
+        self.main_data.extend_from_slice(&main_data);
+        self.data_end += main_data.len();
+        self.pending.push_back(PendingFrame {
+            header,
+            slot_start: self.slots_end,
+            slot_len,
+        });
+        self.slots_end += slot_len;
+        self.frames += 1;
+    }
+
+    fn write_header_and_side_info(&self, padding: usize, ms_stereo: bool, main_data_begin: usize, results: &[Vec<QuantizeResult>]) -> Vec<u8> {
+        let mut bw = BitWriter::new();
+        bw.write(0x7ff, 11); // 帧同步
+        bw.write(0b11, 2); // MPEG-1
+        bw.write(0b01, 2); // Layer III
+        bw.write(1, 1); // 无CRC
+        bw.write(self.bit_rate_index, 4);
+        bw.write(self.sample_rate_index, 2);
+        bw.write(padding, 1);
+        bw.write(0, 1);
+        if self.channels == 1 {
+            bw.write(0b11, 2);
+            bw.write(0, 2);
+        } else {
+            bw.write(0b01, 2);
+            bw.write((ms_stereo as usize) << 1, 2);
+        }
+        bw.write(0, 1); // copyright
+        bw.write(1, 1); // original
+        bw.write(0, 2); // emphasis
+
+        bw.write(main_data_begin, 9);
+        bw.write(0, if self.channels == 1 { 5 } else { 3 });
+        for _ in 0..self.channels {
+            bw.write(0, 4); // scfsi
+        }
+        for granule in results {
+            for result in granule {
+                let channel = &result.channel;
+                bw.write(channel.part2_3_length, 12);
+                bw.write(channel.big_values, 9);
+                bw.write(channel.global_gain, 8);
+                bw.write(channel.scalefac_compress, 4);
+                bw.write(0, 1); // blocksplit_flag
+                for table in channel.table_select {
+                    bw.write(table, 5);
+                }
+                bw.write(channel.region_address1, 4);
+                bw.write(channel.region_address2, 3);
+                bw.write(0, 1); // preflag
+                bw.write(0, 1); // scalefac_scale
+                bw.write(channel.count1table_select, 1);
+            }
+        }
+        bw.into_bytes()
+    }
+
+    // 输出主数据已经填满的帧
+    fn emit_frames(&mut self, output: &mut Vec<u8>) {
+        while let Some(frame) = self.pending.front() {
+            let slot_end = frame.slot_start + frame.slot_len;
+            if slot_end > self.data_end {
+                break;
+            }
+            let start = frame.slot_start - self.main_data_offset;
+            output.extend_from_slice(&frame.header);
+            output.extend_from_slice(&self.main_data[start..start + frame.slot_len]);
+            self.pending.pop_front();
+
+            self.main_data.drain(..slot_end - self.main_data_offset);
+            self.main_data_offset = slot_end;
+        }
+    }
+}
+
+fn quantize_slen(scalefac_compress: usize) -> (usize, usize) {
+    const SLEN: [(usize, usize); 16] = [
+        (0, 0), (0, 1), (0, 2), (0, 3), (3, 0), (1, 1), (1, 2), (1, 3),
+        (2, 1), (2, 2), (2, 3), (3, 1), (3, 2), (3, 3), (4, 2), (4, 3),
+    ];
+    SLEN[scalefac_compress]
+}
+
+// 感知熵的粗略估计，用于在granule之间分配比特池
+fn perceptual_entropy(xr: &[f32; 576], xmin: &[f32; 22], sample_rate_index: usize) -> f32 {
+    let band_index = &crate::huffman::tables::BAND_INDEX_L[sample_rate_index];
+    let mut pe = 0.0;
+    for sfb in 0..22 {
+        let energy: f32 = xr[band_index[sfb]..band_index[sfb + 1]].iter().map(|x| x * x).sum();
+        let width = (band_index[sfb + 1] - band_index[sfb]) as f32;
+        pe += width * (1.0 + energy / xmin[sfb].max(1e-20)).log2() / 2.0;
+    }
+    pe
+}
+
+// 编码为MP3的输出端
+pub struct Mp3Writer<W: Write> {
+    writer: W,
+    bit_rate: usize,
+    encoder: Option<Encoder>,
+}
+
+impl<W: Write> Mp3Writer<W> {
+    pub fn new(writer: W, bit_rate: usize) -> Self {
+        Self {
+            writer,
+            bit_rate,
+            encoder: None,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> PcmSink for Mp3Writer<W> {
+    fn begin(&mut self, sample_rate: u32, channels: u16) -> io::Result<()> {
+        let encoder = Encoder::new(sample_rate, channels, self.bit_rate)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.encoder = Some(encoder);
+        Ok(())
+    }
+
+    fn write_samples(&mut self, pcm: &[f32]) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.as_mut() {
+            let data = encoder.encode(pcm);
+            self.writer.write_all(&data)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.as_mut() {
+            let data = encoder.flush();
+            self.writer.write_all(&data)?;
+        }
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::BufReader};
+
+    use super::*;
+    use crate::{DecodeError, Decoder};
+
+    #[test]
+    fn round_trip() {
+        let pcm: Vec<f32> = (0..44100 * 2)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * (i / 2) as f32 / 44100.0).sin())
+            .collect();
+        let mut encoder = Encoder::new(44100, 2, 128).unwrap();
+        let mut data = encoder.encode(&pcm);
+        data.extend(encoder.flush());
+        assert_eq!(data.len(), encoder.frames() as usize * 144 * 128000 / 44100);
+
+        let path = std::env::temp_dir().join(format!("tiny_mp3_player_round_trip_{}.mp3", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let mut decoder = Decoder::new();
+        decoder.calculate_mp3_duration(&mut reader).unwrap();
+        let mut decoded = Vec::new();
+        loop {
+            match decoder.decode_mp3(&mut reader) {
+                Ok(frame) => decoded.extend(frame),
+                Err(DecodeError::EndOfFile) => break,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let delay = (ENCODER_DELAY + 529) * 2;
+        let (signal, noise) = pcm.iter().zip(&decoded[delay..]).fold((0f64, 0f64), |(s, n), (&a, &b)| {
+            (s + (a as f64).powi(2), n + (a as f64 - b as f64).powi(2))
+        });
+        assert!(10.0 * (signal / noise).log10() > 30.0);
+    }
+}
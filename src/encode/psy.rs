@@ -0,0 +1,79 @@
+use crate::huffman::tables::BAND_INDEX_L;
+
+// 满幅正弦信号经过滤波器组和MDCT后每个granule的能量约为1，用于把绝对听阈换算到MDCT域
+const FULL_SCALE_ENERGY: f32 = 1.0;
+const FULL_SCALE_DB: f32 = 96.0;
+
+fn bark(freq: f32) -> f32 {
+    13.0 * (0.00076 * freq).atan() + 3.5 * (freq / 7500.0).powi(2).atan()
+}
+
+// Terhardt的绝对听阈近似公式，单位dB SPL
+fn ath_db(freq: f32) -> f32 {
+    let f = (freq / 1000.0).max(0.02);
+    3.64 * f.powf(-0.8) - 6.5 * (-0.6 * (f - 3.3).powi(2)).exp() + 0.001 * f.powi(4)
+}
+
+// 简化的心理声学模型：按缩放因子带计算能量和频谱平坦度，估计允许的量化噪声
+pub struct PsyModel {
+    sample_rate_index: usize,
+    ath: [f32; 22],
+    bark: [f32; 22],
+}
+
+impl PsyModel {
+    pub fn new(sample_rate: usize, sample_rate_index: usize) -> Self {
+        let band_index = &BAND_INDEX_L[sample_rate_index];
+        let line_freq = sample_rate as f32 / 2.0 / 576.0;
+
+        let mut ath = [0f32; 22];
+        let mut bark_center = [0f32; 22];
+        for sfb in 0..22 {
+            let (start, end) = (band_index[sfb], band_index[sfb + 1]);
+            let min_db = (start..end)
+                .map(|i| ath_db((i as f32 + 0.5) * line_freq))
+                .fold(f32::MAX, f32::min);
+            ath[sfb] = FULL_SCALE_ENERGY * 10f32.powf((min_db - FULL_SCALE_DB) / 10.0)
+                * (end - start) as f32;
+            bark_center[sfb] = bark((start + end) as f32 / 2.0 * line_freq);
+        }
+        Self {
+            sample_rate_index,
+            ath,
+            bark: bark_center,
+        }
+    }
+
+    // 返回每个缩放因子带允许的噪声能量
+    pub fn allowed_distortion(&self, xr: &[f32; 576]) -> [f32; 22] {
+        let band_index = &BAND_INDEX_L[self.sample_rate_index];
+        let mut energy = [0f32; 22];
+        let mut tonality = [0f32; 22];
+        for sfb in 0..22 {
+            let band = &xr[band_index[sfb]..band_index[sfb + 1]];
+            let n = band.len() as f32;
+            energy[sfb] = band.iter().map(|x| x * x).sum();
+
+            // 频谱平坦度越低越接近纯音，纯音对噪声的掩蔽能力较弱
+            let arith = energy[sfb] / n + 1e-12;
+            let geo = (band.iter().map(|x| (x * x + 1e-12).ln()).sum::<f32>() / n).exp();
+            let sfm_db = 10.0 * (geo / arith).log10();
+            tonality[sfb] = (sfm_db / -60.0).clamp(0.0, 1.0);
+        }
+
+        let mut xmin = [0f32; 22];
+        for sfb in 0..22 {
+            // 相邻频带之间的简单扩散
+            let mut spread = energy[sfb];
+            if sfb > 0 {
+                spread += energy[sfb - 1] * 0.1;
+            }
+            if sfb < 21 {
+                spread += energy[sfb + 1] * 0.03;
+            }
+            let smr_db = tonality[sfb] * (14.5 + self.bark[sfb]) + (1.0 - tonality[sfb]) * 5.5;
+            xmin[sfb] = (spread * 10f32.powf(-smr_db / 10.0)).max(self.ath[sfb]);
+        }
+        xmin
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    huffman::tables::BAND_INDEX_L,
+    mpeg_frame::types::{Channel, ScaleFactor},
+};
+
+use super::huffman::{count_bits, MAX_QUANTIZED_VALUE};
+
+const SLEN: [[usize; 2]; 16] = [
+    [0, 0], [0, 1], [0, 2], [0, 3], [3, 0], [1, 1], [1, 2], [1, 3],
+    [2, 1], [2, 2], [2, 3], [3, 1], [3, 2], [3, 3], [4, 2], [4, 3],
+];
+const MAX_OUTER_LOOPS: usize = 24;
+// part2_3_length字段只有12位
+pub const MAX_GRANULE_BITS: usize = 4095;
+
+pub struct QuantizeResult {
+    pub ix: [usize; 576],
+    pub sign: [bool; 576],
+    pub channel: Channel,
+    pub sf: ScaleFactor,
+    pub bits: usize,
+}
+
+fn step(global_gain: usize) -> f32 {
+    2f32.powf((global_gain as f32 - 210.0) / 4.0)
+}
+
+fn sfb_of_line(sample_rate: usize) -> [usize; 576] {
+    let band_index = &BAND_INDEX_L[sample_rate];
+    let mut table = [0usize; 576];
+    let mut sfb = 0;
+    for (i, value) in table.iter_mut().enumerate() {
+        while i >= band_index[sfb + 1] {
+            sfb += 1;
+        }
+        *value = sfb;
+    }
+    table
+}
+
+// 量化：ix = nint((|xr| * 2^(sf/2) / step)^0.75 - 0.0946)，xr34为预先算好的|xr|^0.75
+fn quantize(xr34: &[f32; 576], global_gain: usize, sf: &[usize; 23], sfb_of: &[usize; 576], ix: &mut [usize; 576]) -> bool {
+    let inv_step = 1.0 / step(global_gain);
+    let mut factor = [0f32; 22];
+    for (sfb, value) in factor.iter_mut().enumerate() {
+        *value = (2f32.powf(0.5 * sf[sfb] as f32) * inv_step).powf(0.75);
+    }
+    for i in 0..576 {
+        let value = xr34[i] * factor[sfb_of[i]] + 0.4054;
+        if value > MAX_QUANTIZED_VALUE as f32 {
+            return false;
+        }
+        ix[i] = value as usize;
+    }
+    true
+}
+
+fn distortion(xr: &[f32; 576], ix: &[usize; 576], global_gain: usize, sf: &[usize; 23], sfb_of: &[usize; 576]) -> [f32; 22] {
+    let step = step(global_gain);
+    let mut noise = [0f32; 22];
+    for i in 0..576 {
+        let sfb = sfb_of[i];
+        let restored = (ix[i] as f32).powf(4.0 / 3.0) * step * 2f32.powf(-0.5 * sf[sfb] as f32);
+        noise[sfb] += (xr[i].abs() - restored).powi(2);
+    }
+    noise
+}
+
+// 选择能容纳当前缩放因子的最短slen组合
+fn scalefac_compress(sf: &[usize; 23]) -> Option<(usize, usize)> {
+    let max1 = sf[0..11].iter().copied().max().unwrap_or(0);
+    let max2 = sf[11..21].iter().copied().max().unwrap_or(0);
+    SLEN.iter()
+        .enumerate()
+        .filter(|(_, slen)| max1 < (1 << slen[0]) && max2 < (1 << slen[1]))
+        .map(|(i, slen)| (i, slen[0] * 11 + slen[1] * 10))
+        .min_by_key(|&(_, bits)| bits)
+}
+
+struct InnerResult {
+    global_gain: usize,
+    channel: Channel,
+    bits: usize,
+}
+
+// 内层循环：二分查找满足比特数限制的最小global_gain
+fn inner_loop(xr34: &[f32; 576], sf: &[usize; 23], sfb_of: &[usize; 576], sample_rate: usize, max_bits: usize, ix: &mut [usize; 576]) -> Option<InnerResult> {
+    let try_gain = |gain: usize, ix: &mut [usize; 576]| -> Option<(Channel, usize)> {
+        if !quantize(xr34, gain, sf, sfb_of, ix) {
+            return None;
+        }
+        let mut channel = Channel::new();
+        let bits = count_bits(ix, sample_rate, &mut channel);
+        Some((channel, bits))
+    };
+
+    let (mut low, mut high) = (0usize, 255usize);
+    let mut best = None;
+    while low <= high {
+        let mid = (low + high) / 2;
+        match try_gain(mid, ix) {
+            Some((channel, bits)) if bits <= max_bits => {
+                best = Some(InnerResult {
+                    global_gain: mid,
+                    channel,
+                    bits,
+                });
+                if mid == 0 {
+                    break;
+                }
+                high = mid - 1;
+            }
+            _ => low = mid + 1,
+        }
+    }
+    let best = best?;
+    quantize(xr34, best.global_gain, sf, sfb_of, ix);
+    Some(best)
+}
+
+// 外层循环：放大噪声超出允许值的缩放因子带，直到噪声全部满足要求或无法继续
+pub fn quantize_granule(xr: &[f32; 576], xmin: &[f32; 22], sample_rate: usize, max_bits: usize) -> QuantizeResult {
+    let sfb_of = sfb_of_line(sample_rate);
+    let mut xr34 = [0f32; 576];
+    for (v, x) in xr34.iter_mut().zip(xr.iter()) {
+        *v = x.abs().powf(0.75);
+    }
+    let mut sf = [0usize; 23];
+    let mut ix = [0usize; 576];
+    let mut best: Option<(f32, QuantizeResult)> = None;
+
+    for _ in 0..MAX_OUTER_LOOPS {
+        let Some((compress, part2_bits)) = scalefac_compress(&sf) else {
+            break;
+        };
+        if part2_bits >= max_bits {
+            break;
+        }
+        let Some(mut inner) = inner_loop(&xr34, &sf, &sfb_of, sample_rate, max_bits - part2_bits, &mut ix) else {
+            break;
+        };
+        let mut noise = distortion(xr, &ix, inner.global_gain, &sf, &sfb_of);
+        if noise.iter().zip(xmin.iter()).all(|(n, x)| n <= x) {
+            // 噪声已经满足要求时尽量增大量化步长，把省下的比特留给比特池
+            let mut saved = inner.global_gain;
+            while saved < 255 {
+                let mut trial = [0usize; 576];
+                if !quantize(&xr34, saved + 1, &sf, &sfb_of, &mut trial) {
+                    break;
+                }
+                let trial_noise = distortion(xr, &trial, saved + 1, &sf, &sfb_of);
+                if trial_noise.iter().zip(xmin.iter()).any(|(n, x)| n > x) {
+                    break;
+                }
+                saved += 1;
+                ix = trial;
+                noise = trial_noise;
+            }
+            if saved != inner.global_gain {
+                inner.global_gain = saved;
+                inner.bits = count_bits(&ix, sample_rate, &mut inner.channel);
+            }
+        }
+
+        let mut over_noise = 0f32;
+        let mut over = Vec::new();
+        for sfb in 0..22 {
+            if noise[sfb] > xmin[sfb] {
+                over_noise += (noise[sfb] / xmin[sfb].max(1e-20)).log10();
+                over.push(sfb);
+            }
+        }
+
+        if best.as_ref().is_none_or(|(b, _)| over_noise < *b) {
+            let mut channel = inner.channel;
+            channel.global_gain = inner.global_gain;
+            channel.scalefac_compress = compress;
+            channel.part2_3_length = inner.bits + part2_bits;
+            let mut sign = [false; 576];
+            for (s, x) in sign.iter_mut().zip(xr.iter()) {
+                *s = *x < 0.0;
+            }
+            best = Some((
+                over_noise,
+                QuantizeResult {
+                    ix,
+                    sign,
+                    channel,
+                    sf: ScaleFactor {
+                        sf_l: sf,
+                        sf_s: [[0usize; 3]; 13],
+                    },
+                    bits: inner.bits + part2_bits,
+                },
+            ));
+        }
+
+        // 最后一个缩放因子带没有缩放因子，不能放大
+        over.retain(|&sfb| sfb < 21);
+        if over.is_empty() {
+            break;
+        }
+        for &sfb in &over {
+            sf[sfb] += 1;
+        }
+        if sf[0..11].iter().any(|&s| s > 15) || sf[11..21].iter().any(|&s| s > 7) {
+            break;
+        }
+    }
+
+    match best {
+        Some((_, result)) => result,
+        // 比特数连缩放因子都无法容纳时输出全零的granule
+        None => QuantizeResult {
+            ix: [0usize; 576],
+            sign: [false; 576],
+            channel: Channel::new(),
+            sf: ScaleFactor::new(),
+            bits: 0,
+        },
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use super::frames::{decode_text, split_terminated, ID3v2Frame, Picture};
+
+// CHAP帧，时间以毫秒为单位，偏移为0xffffffff时表示未使用
+pub struct ChapterFrame {
+    pub element_id: String,
+    pub start_time: u32,
+    pub end_time: u32,
+    pub start_offset: Option<u32>,
+    pub end_offset: Option<u32>,
+    // 内嵌的TIT2、TIT3、WXXX、APIC等子帧
+    pub frames: BTreeMap<String, Vec<ID3v2Frame>>,
+}
+
+// CTOC帧，子元素为CHAP或其他CTOC的元素ID
+pub struct TocFrame {
+    pub element_id: String,
+    pub top_level: bool,
+    pub ordered: bool,
+    pub children: Vec<String>,
+    pub frames: BTreeMap<String, Vec<ID3v2Frame>>,
+}
+
+fn be_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes(data[..4].try_into().unwrap())
+}
+
+// 子帧中的第一个文本值
+fn sub_text(frames: &BTreeMap<String, Vec<ID3v2Frame>>, id: &str) -> Option<String> {
+    frames.get(id)?.iter().find_map(|frame| match frame {
+        ID3v2Frame::Text(values) => values.first().cloned(),
+        _ => None,
+    })
+}
+
+impl ChapterFrame {
+    // parse_sub_frames用标签的版本解析内嵌的子帧
+    pub fn parse(
+        data: &[u8],
+        parse_sub_frames: impl Fn(&[u8]) -> BTreeMap<String, Vec<ID3v2Frame>>,
+    ) -> Option<Self> {
+        let (element_id, rest) = split_terminated(0, data);
+        let times = rest.get(..16)?;
+        let offset = |value: u32| (value != u32::MAX).then_some(value);
+        Some(Self {
+            element_id: decode_text(0, element_id),
+            start_time: be_u32(times),
+            end_time: be_u32(&times[4..]),
+            start_offset: offset(be_u32(&times[8..])),
+            end_offset: offset(be_u32(&times[12..])),
+            frames: parse_sub_frames(&rest[16..]),
+        })
+    }
+
+    pub fn title(&self) -> Option<String> {
+        sub_text(&self.frames, "TIT2")
+    }
+}
+
+impl TocFrame {
+    pub fn parse(
+        data: &[u8],
+        parse_sub_frames: impl Fn(&[u8]) -> BTreeMap<String, Vec<ID3v2Frame>>,
+    ) -> Option<Self> {
+        let (element_id, rest) = split_terminated(0, data);
+        let (&flags, rest) = rest.split_first()?;
+        let (&count, mut rest) = rest.split_first()?;
+        let mut children = Vec::new();
+        for _ in 0..count {
+            let (child, after) = split_terminated(0, rest);
+            children.push(decode_text(0, child));
+            rest = after;
+        }
+        Some(Self {
+            element_id: decode_text(0, element_id),
+            top_level: flags & 0x02 != 0,
+            ordered: flags & 0x01 != 0,
+            children,
+            frames: parse_sub_frames(rest),
+        })
+    }
+
+    pub fn title(&self) -> Option<String> {
+        sub_text(&self.frames, "TIT2")
+    }
+}
+
+// 章节树中的一个节点，CTOC节点的时间范围为其所有子节点的范围
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    pub id: String,
+    pub title: Option<String>,
+    pub start: Duration,
+    pub end: Duration,
+    pub url: Option<String>,
+    pub picture: Option<Picture>,
+    pub children: Vec<Chapter>,
+}
+
+impl Chapter {
+    fn from_frame(chapter: &ChapterFrame) -> Self {
+        let url = chapter.frames.values().flatten().find_map(|frame| match frame {
+            ID3v2Frame::Url(url) | ID3v2Frame::UserUrl { url, .. } => Some(url.clone()),
+            _ => None,
+        });
+        let picture = chapter.frames.get("APIC").into_iter().flatten().find_map(|frame| match frame {
+            ID3v2Frame::Picture(picture) => Some(picture.clone()),
+            _ => None,
+        });
+        Self {
+            id: chapter.element_id.clone(),
+            title: chapter.title(),
+            start: Duration::from_millis(chapter.start_time as u64),
+            end: Duration::from_millis(chapter.end_time as u64),
+            url,
+            picture,
+            children: Vec::new(),
+        }
+    }
+
+    // 按顺序列出所有没有子节点的章节
+    pub fn leaves(chapters: &[Chapter]) -> Vec<&Chapter> {
+        chapters
+            .iter()
+            .flat_map(|chapter| {
+                if chapter.children.is_empty() {
+                    vec![chapter]
+                } else {
+                    Self::leaves(&chapter.children)
+                }
+            })
+            .collect()
+    }
+}
+
+// 由CHAP和CTOC帧构建章节树
+// 有顶层CTOC时返回它的子节点，否则返回按开始时间排序的所有CHAP
+pub fn chapter_tree(chapters: &[&ChapterFrame], tocs: &[&TocFrame]) -> Vec<Chapter> {
+    fn build(id: &str, chapters: &[&ChapterFrame], tocs: &[&TocFrame], visited: &mut Vec<String>) -> Option<Chapter> {
+        if visited.iter().any(|v| v == id) {
+            return None;
+        }
+        visited.push(id.to_string());
+        if let Some(chapter) = chapters.iter().find(|c| c.element_id == id) {
+            return Some(Chapter::from_frame(chapter));
+        }
+        let toc = tocs.iter().find(|t| t.element_id == id)?;
+        let mut children: Vec<Chapter> = toc
+            .children
+            .iter()
+            .filter_map(|child| build(child, chapters, tocs, visited))
+            .collect();
+        if !toc.ordered {
+            children.sort_by_key(|c| c.start);
+        }
+        Some(Chapter {
+            id: toc.element_id.clone(),
+            title: toc.title(),
+            start: children.iter().map(|c| c.start).min().unwrap_or_default(),
+            end: children.iter().map(|c| c.end).max().unwrap_or_default(),
+            url: None,
+            picture: None,
+            children,
+        })
+    }
+
+    let mut visited = Vec::new();
+    if let Some(root) = tocs.iter().find(|t| t.top_level) {
+        if let Some(root) = build(&root.element_id, chapters, tocs, &mut visited) {
+            return root.children;
+        }
+    }
+    let mut result: Vec<Chapter> = chapters.iter().map(|c| Chapter::from_frame(c)).collect();
+    result.sort_by_key(|c| c.start);
+    result
+}
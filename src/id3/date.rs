@@ -0,0 +1,99 @@
+use std::fmt;
+
+// ID3v2中的时间，v2.4为ISO 8601格式的子集，精度可以只到年
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+    pub hour: Option<u8>,
+    pub minute: Option<u8>,
+    pub second: Option<u8>,
+}
+
+fn digits(s: &str, len: usize) -> Option<u16> {
+    let part = s.get(..len)?;
+    if part.bytes().all(|b| b.is_ascii_digit()) {
+        part.parse().ok()
+    } else {
+        None
+    }
+}
+
+impl Date {
+    pub fn from_year(year: u16) -> Self {
+        Self {
+            year,
+            month: None,
+            day: None,
+            hour: None,
+            minute: None,
+            second: None,
+        }
+    }
+
+    // 解析"yyyy[-MM[-dd[THH[:mm[:ss]]]]]"，日期和时间之间也可以是空格，无法解析的部分忽略
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let mut date = Self::from_year(digits(s, 4)?);
+        let mut rest = &s[4..];
+        let fields = [
+            ('-', &mut date.month, 1..=12),
+            ('-', &mut date.day, 1..=31),
+            ('T', &mut date.hour, 0..=23),
+            (':', &mut date.minute, 0..=59),
+            (':', &mut date.second, 0..=59),
+        ];
+        for (separator, field, range) in fields {
+            let Some(after) = rest.strip_prefix(separator).or_else(|| {
+                (separator == 'T').then(|| rest.strip_prefix(' ')).flatten()
+            }) else {
+                break;
+            };
+            let Some(value) = digits(after, 2).map(|v| v as u8).filter(|v| range.contains(v)) else {
+                break;
+            };
+            *field = Some(value);
+            rest = &after[2..];
+        }
+        Some(date)
+    }
+
+    // v2.3中年份、日期和时间分别保存在TYER、TDAT（DDMM）和TIME（HHMM）中
+    pub fn from_v23(year: &str, date: Option<&str>, time: Option<&str>) -> Option<Self> {
+        let mut result = Self::from_year(digits(year.trim(), 4)?);
+        if let Some(date) = date.map(str::trim).filter(|d| d.len() == 4) {
+            result.day = digits(date, 2).map(|d| d as u8).filter(|d| (1..=31).contains(d));
+            result.month = digits(&date[2..], 2).map(|m| m as u8).filter(|m| (1..=12).contains(m));
+            if result.day.is_none() || result.month.is_none() {
+                (result.day, result.month) = (None, None);
+            }
+        }
+        if let (Some(time), Some(_)) = (time.map(str::trim).filter(|t| t.len() == 4), result.day) {
+            result.hour = digits(time, 2).map(|h| h as u8).filter(|h| *h < 24);
+            result.minute = result.hour.and(digits(&time[2..], 2).map(|m| m as u8).filter(|m| *m < 60));
+        }
+        Some(result)
+    }
+}
+
+// 按精度输出ISO 8601格式
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}", self.year)?;
+        let fields = [
+            ('-', self.month),
+            ('-', self.day),
+            ('T', self.hour),
+            (':', self.minute),
+            (':', self.second),
+        ];
+        for (separator, value) in fields {
+            let Some(value) = value else {
+                break;
+            };
+            write!(f, "{}{:02}", separator, value)?;
+        }
+        Ok(())
+    }
+}
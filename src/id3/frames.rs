@@ -1,67 +1,330 @@
+use super::chapters::{ChapterFrame, TocFrame};
+
 pub enum ID3v2Frame {
-    Title(String),        // TIT2
-    Artist(String),       // TPE1
-    Album(String),        // TALB
-    Year(String),         // TYER
-    Comment(String),      // COMM
-    Genre(String),        // TCON
-    TrackNumber(String),  // TRCK
-    // 其他ID3v2标签类型可以在这里继续添加
-    Unknown,
+    // T***（TXXX除外），v2.4中多个值以空字符分隔
+    Text(Vec<String>),
+    // COMM
+    Comment {
+        language: String,
+        description: String,
+        text: String,
+    },
+    // APIC，v2.2中为PIC
+    Picture(Picture),
+    // TXXX，v2.4中值可以有多个
+    UserText {
+        description: String,
+        values: Vec<String>,
+    },
+    // W***（WXXX除外）
+    Url(String),
+    // WXXX
+    UserUrl {
+        description: String,
+        url: String,
+    },
+    // UFID，如MusicBrainz的录音ID
+    UniqueFileId {
+        owner: String,
+        identifier: Vec<u8>,
+    },
+    // PRIV
+    Private {
+        owner: String,
+        data: Vec<u8>,
+    },
+    // USLT
+    Lyrics {
+        language: String,
+        description: String,
+        text: String,
+    },
+    // SYLT
+    SyncedLyrics(SyncedLyrics),
+    // CHAP和CTOC，包含子帧
+    Chapter(ChapterFrame),
+    TableOfContents(TocFrame),
+    // 其他ID3v2标签类型可以在这里继续添加，未识别的帧保留原始数据
+    Unknown(Vec<u8>),
 }
 
-fn text_parse(data: &[u8], revision: u8) -> String {
-    let encode = data[0];
-    if encode == 0x01 {
-        // UCS-2(相当于UTF16的子集)
-        if (data[1] == 0xff && data[2] == 0xfe) || revision == 4 {
-            // 小段字节序
-            let mut result = String::new();
-            for i in (3..data.len()).step_by(2) {
-                let c = u16::from_le_bytes([data[i], data[i + 1]]);
-                result.push(char::from_u32(c as u32).unwrap());
-            }
-            return result;
-        } else if data[1] == 0xfe && data[2] == 0xff {
-            // 大端字节序
-            let mut result = String::new();
-            for i in (3..data.len()).step_by(2) {
-                let c = u16::from_be_bytes([data[i], data[i + 1]]);
-                result.push(char::from_u32(c as u32).unwrap());
-            }
-            return result;
+// 图片类型，0x03为封面
+pub const PICTURE_TYPES: [&str; 21] = [
+    "Other", "File icon", "Other file icon", "Cover (front)", "Cover (back)", "Leaflet page",
+    "Media", "Lead artist", "Artist", "Conductor", "Band", "Composer", "Lyricist",
+    "Recording location", "During recording", "During performance", "Screen capture",
+    "Bright coloured fish", "Illustration", "Band logotype", "Publisher logotype",
+];
+
+#[derive(Debug, Clone)]
+pub struct Picture {
+    pub mime_type: String,
+    pub picture_type: u8,
+    pub description: String,
+    pub data: Vec<u8>,
+}
+
+impl Picture {
+    // APIC：编码、以0结尾的MIME类型、图片类型、描述、图片数据
+    fn parse(data: &[u8]) -> Option<Self> {
+        let (&encoding, rest) = data.split_first()?;
+        let (mime_type, rest) = split_terminated(0, rest);
+        let (&picture_type, rest) = rest.split_first()?;
+        let (description, data) = split_terminated(encoding, rest);
+        Some(Self {
+            mime_type: decode_text(0, mime_type),
+            picture_type,
+            description: decode_text(encoding, description),
+            data: data.to_vec(),
+        })
+    }
+
+    // PIC：MIME类型换成三个字母的图片格式
+    fn parse_v22(data: &[u8]) -> Option<Self> {
+        let encoding = *data.first()?;
+        let format = data.get(1..4)?;
+        let picture_type = *data.get(4)?;
+        let (description, data) = split_terminated(encoding, &data[5..]);
+        let mime_type = match format.to_ascii_uppercase().as_slice() {
+            b"JPG" => "image/jpeg".to_string(),
+            b"PNG" => "image/png".to_string(),
+            b"-->" => "-->".to_string(),
+            other => format!("image/{}", String::from_utf8_lossy(other).to_lowercase()),
+        };
+        Some(Self {
+            mime_type,
+            picture_type,
+            description: decode_text(encoding, description),
+            data: data.to_vec(),
+        })
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        PICTURE_TYPES.get(self.picture_type as usize).copied().unwrap_or("Unknown")
+    }
+
+    // 按MIME类型或文件头推断扩展名，"-->"表示数据是图片的URL
+    pub fn extension(&self) -> &'static str {
+        match self.mime_type.to_ascii_lowercase().as_str() {
+            "image/jpeg" | "image/jpg" => "jpg",
+            "image/png" => "png",
+            "image/gif" => "gif",
+            "image/bmp" => "bmp",
+            "image/webp" => "webp",
+            "-->" => "url",
+            _ => match self.data.as_slice() {
+                [0xff, 0xd8, ..] => "jpg",
+                [0x89, b'P', b'N', b'G', ..] => "png",
+                [b'G', b'I', b'F', ..] => "gif",
+                _ => "bin",
+            },
+        }
+    }
+}
+
+// SYLT中的时间戳单位
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimestampFormat {
+    MpegFrames,
+    Milliseconds,
+}
+
+pub struct SyncedLyrics {
+    pub language: String,
+    pub timestamp_format: TimestampFormat,
+    // 0为其他，1为歌词，2为文字转录
+    pub content_type: u8,
+    pub description: String,
+    // 时间戳和从该时间开始的文本
+    pub lines: Vec<(u32, String)>,
+}
+
+impl SyncedLyrics {
+    // 编码、语言、时间戳格式、内容类型、描述，之后是若干以结束符结尾的文本和4字节时间戳
+    fn parse(data: &[u8]) -> Option<Self> {
+        let encoding = *data.first()?;
+        let language = String::from_utf8_lossy(data.get(1..4)?).to_string();
+        let timestamp_format = match data.get(4)? {
+            1 => TimestampFormat::MpegFrames,
+            _ => TimestampFormat::Milliseconds,
+        };
+        let content_type = *data.get(5)?;
+        let (description, mut rest) = split_terminated(encoding, &data[6..]);
+
+        let mut lines = Vec::new();
+        while !rest.is_empty() {
+            let (text, after) = split_terminated(encoding, rest);
+            let Some(timestamp) = after.get(..4) else {
+                break;
+            };
+            lines.push((u32::from_be_bytes(timestamp.try_into().unwrap()), decode_text(encoding, text)));
+            rest = &after[4..];
+        }
+        Some(Self {
+            language,
+            timestamp_format,
+            content_type,
+            description: decode_text(encoding, description),
+            lines,
+        })
+    }
+}
+
+// v2.2的三字母帧ID与v2.3/v2.4四字母帧ID的对应关系
+const V22_FRAME_IDS: [(&str, &str); 62] = [
+    ("BUF", "RBUF"), ("CNT", "PCNT"), ("COM", "COMM"), ("CRA", "AENC"), ("EQU", "EQUA"),
+    ("ETC", "ETCO"), ("GEO", "GEOB"), ("IPL", "IPLS"), ("LNK", "LINK"), ("MCI", "MCDI"),
+    ("MLL", "MLLT"), ("PIC", "APIC"), ("POP", "POPM"), ("REV", "RVRB"), ("RVA", "RVAD"),
+    ("SLT", "SYLT"), ("STC", "SYTC"), ("TAL", "TALB"), ("TBP", "TBPM"), ("TCM", "TCOM"),
+    ("TCO", "TCON"), ("TCR", "TCOP"), ("TDA", "TDAT"), ("TDY", "TDLY"), ("TEN", "TENC"),
+    ("TFT", "TFLT"), ("TIM", "TIME"), ("TKE", "TKEY"), ("TLA", "TLAN"), ("TLE", "TLEN"),
+    ("TMT", "TMED"), ("TOA", "TOPE"), ("TOF", "TOFN"), ("TOL", "TOLY"), ("TOR", "TORY"),
+    ("TOT", "TOAL"), ("TP1", "TPE1"), ("TP2", "TPE2"), ("TP3", "TPE3"), ("TP4", "TPE4"),
+    ("TPA", "TPOS"), ("TPB", "TPUB"), ("TRC", "TSRC"), ("TRD", "TRDA"), ("TRK", "TRCK"),
+    ("TSI", "TSIZ"), ("TSS", "TSSE"), ("TT1", "TIT1"), ("TT2", "TIT2"), ("TT3", "TIT3"),
+    ("TXT", "TEXT"), ("TXX", "TXXX"), ("TYE", "TYER"), ("UFI", "UFID"), ("ULT", "USLT"),
+    ("WAF", "WOAF"), ("WAR", "WOAR"), ("WAS", "WOAS"), ("WCM", "WCOM"), ("WCP", "WCOP"),
+    ("WPB", "WPUB"), ("WXX", "WXXX"),
+];
+
+// v2.2的帧ID转换为对应的四字母ID，没有对应关系的保持原样
+pub fn frame_id_from_v22(id: &str) -> String {
+    V22_FRAME_IDS
+        .iter()
+        .find(|(v22, _)| *v22 == id)
+        .map(|(_, id)| id.to_string())
+        .unwrap_or_else(|| id.to_string())
+}
+
+// 按文本编码解码一个字符串，0为ISO-8859-1，1为带BOM的UTF-16，2为UTF-16BE，3为UTF-8
+pub fn decode_text(encoding: u8, data: &[u8]) -> String {
+    match encoding {
+        1 | 2 => {
+            let (big_endian, data) = match data {
+                [0xff, 0xfe, rest @ ..] => (false, rest),
+                [0xfe, 0xff, rest @ ..] => (true, rest),
+                // 缺少BOM的UTF-16按小端处理
+                _ => (encoding == 2, data),
+            };
+            let units: Vec<u16> = data
+                .chunks_exact(2)
+                .map(|c| {
+                    if big_endian {
+                        u16::from_be_bytes([c[0], c[1]])
+                    } else {
+                        u16::from_le_bytes([c[0], c[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
         }
-    } else if encode == 3 {
-        return String::from_utf8_lossy(&data[1..]).to_string();
+        3 => String::from_utf8_lossy(data).to_string(),
+        _ => data.iter().map(|&b| b as char).collect(),
     }
-    return String::new();
+}
+
+// 字符串结束符的长度，UTF-16为两个字节
+fn terminator_len(encoding: u8) -> usize {
+    if encoding == 1 || encoding == 2 {
+        2
+    } else {
+        1
+    }
+}
+
+// 在data中找到第一个结束符，返回结束符之前和之后的部分
+pub fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    let len = terminator_len(encoding);
+    let end = (0..data.len().saturating_sub(len - 1))
+        .step_by(len)
+        .find(|&i| data[i..i + len].iter().all(|&b| b == 0));
+    match end {
+        Some(end) => (&data[..end], &data[end + len..]),
+        None => (data, &[]),
+    }
+}
+
+// 解码以结束符分隔的多个字符串，忽略结尾的结束符
+pub fn decode_strings(encoding: u8, mut data: &[u8]) -> Vec<String> {
+    let mut result = Vec::new();
+    while !data.is_empty() {
+        let (value, rest) = split_terminated(encoding, data);
+        result.push(decode_text(encoding, value));
+        data = rest;
+    }
+    result
 }
 
 impl ID3v2Frame {
-    pub fn new(frame_id: &[u8; 4], data: &[u8], revision: u8) -> Self {
+    // frame_id为四字母ID，v2.2的ID需先用frame_id_from_v22转换
+    pub fn new(frame_id: &str, data: &[u8]) -> Self {
         match frame_id {
-            [b'T', b'I', b'T', b'2'] => {
-                Self::Title(text_parse(data, revision))
-            },
-            [b'T', b'P', b'E', b'1'] => {
-                Self::Artist(text_parse(data, revision))
-            },
-            [b'T', b'A', b'L', b'B'] => {
-                Self::Album(text_parse(data, revision))
-            },
-            [b'T', b'Y', b'E', b'R'] => {
-                Self::Year(text_parse(data, revision))
-            },
-            [b'C', b'O', b'M', b'M'] => {
-                Self::Comment(text_parse(data, revision))
-            },
-            [b'T', b'C', b'O', b'N'] => {
-                Self::Genre(text_parse(data, revision))
-            },
-            [b'T', b'R', b'C', b'K'] => {
-                Self::TrackNumber(text_parse(data, revision))
-            },
-            _ => Self::Unknown,
+            "COMM" if data.len() >= 4 => {
+                let encoding = data[0];
+                let language = String::from_utf8_lossy(&data[1..4]).to_string();
+                let (description, text) = split_terminated(encoding, &data[4..]);
+                let (text, _) = split_terminated(encoding, text);
+                Self::Comment {
+                    language,
+                    description: decode_text(encoding, description),
+                    text: decode_text(encoding, text),
+                }
+            }
+            "TXXX" if !data.is_empty() => {
+                let encoding = data[0];
+                let (description, values) = split_terminated(encoding, &data[1..]);
+                Self::UserText {
+                    description: decode_text(encoding, description),
+                    values: decode_strings(encoding, values),
+                }
+            }
+            "WXXX" if !data.is_empty() => {
+                let encoding = data[0];
+                let (description, url) = split_terminated(encoding, &data[1..]);
+                Self::UserUrl {
+                    description: decode_text(encoding, description),
+                    url: decode_text(0, split_terminated(0, url).0),
+                }
+            }
+            _ if frame_id.starts_with('W') => Self::Url(decode_text(0, split_terminated(0, data).0)),
+            "UFID" => {
+                let (owner, identifier) = split_terminated(0, data);
+                Self::UniqueFileId {
+                    owner: decode_text(0, owner),
+                    identifier: identifier.to_vec(),
+                }
+            }
+            "PRIV" => {
+                let (owner, data) = split_terminated(0, data);
+                Self::Private {
+                    owner: decode_text(0, owner),
+                    data: data.to_vec(),
+                }
+            }
+            "USLT" if data.len() >= 4 => {
+                let encoding = data[0];
+                let (description, text) = split_terminated(encoding, &data[4..]);
+                let (text, _) = split_terminated(encoding, text);
+                Self::Lyrics {
+                    language: String::from_utf8_lossy(&data[1..4]).to_string(),
+                    description: decode_text(encoding, description),
+                    text: decode_text(encoding, text),
+                }
+            }
+            "SYLT" => SyncedLyrics::parse(data).map_or_else(|| Self::Unknown(data.to_vec()), Self::SyncedLyrics),
+            "APIC" => Picture::parse(data).map_or_else(|| Self::Unknown(data.to_vec()), Self::Picture),
+            _ if frame_id.starts_with('T') && frame_id != "TXXX" && !data.is_empty() => {
+                Self::Text(decode_strings(data[0], &data[1..]))
+            }
+            _ => Self::Unknown(data.to_vec()),
         }
     }
-}
\ No newline at end of file
+
+    // v2.2中格式与v2.3不同的帧，frame_id为转换后的四字母ID
+    pub fn new_v22(frame_id: &str, data: &[u8]) -> Self {
+        match frame_id {
+            "APIC" => Picture::parse_v22(data).map_or_else(|| Self::Unknown(data.to_vec()), Self::Picture),
+            _ => Self::new(frame_id, data),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use super::v1::genre_name;
+
+fn genre_reference(token: &str) -> Option<String> {
+    match token {
+        "RX" => Some("Remix".to_string()),
+        "CR" => Some("Cover".to_string()),
+        _ if !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit()) => {
+            token.parse().ok().and_then(genre_name).map(str::to_string)
+        }
+        _ => None,
+    }
+}
+
+// 解析TCON中的流派：v2.3的"(17)"、"(17)Rock"、"(RX)"和以"(("开头的转义文本，
+// v2.4中以空字符分隔的多个值，每个值可以是数字、"RX"、"CR"或文本
+pub fn resolve_genres(values: &[String]) -> Vec<String> {
+    let mut genres: Vec<String> = Vec::new();
+    let mut push = |genre: String| {
+        let genre = genre.trim().to_string();
+        if !genre.is_empty() && !genres.iter().any(|g| g.eq_ignore_ascii_case(&genre)) {
+            genres.push(genre);
+        }
+    };
+    for value in values {
+        if let Some(genre) = genre_reference(value.trim()) {
+            push(genre);
+            continue;
+        }
+        let mut rest = value.as_str();
+        while rest.starts_with('(') && !rest.starts_with("((") {
+            let Some(end) = rest.find(')') else {
+                break;
+            };
+            match genre_reference(&rest[1..end]) {
+                Some(genre) => push(genre),
+                None => push(rest[1..end].to_string()),
+            }
+            rest = &rest[end + 1..];
+        }
+        // 文本部分是对前面编号的细化，"(("表示文本本身以括号开头
+        let rest = rest.strip_prefix('(').filter(|r| r.starts_with('(')).unwrap_or(rest);
+        push(rest.to_string());
+    }
+    genres
+}
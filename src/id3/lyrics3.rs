@@ -0,0 +1,82 @@
+const BEGIN: &[u8] = b"LYRICSBEGIN";
+const END_V1: &[u8] = b"LYRICSEND";
+const END_V2: &[u8] = b"LYRICS200";
+// Lyrics3v1的歌词最长为5100字节
+const MAX_V1_SIZE: usize = 5100;
+
+// 位于ID3v1之前的Lyrics3v1/v2块
+pub struct Lyrics3 {
+    pub version: u8,
+    // 三个字母的字段ID和内容，v1只有LYR
+    pub fields: Vec<(String, String)>,
+}
+
+fn latin1(data: &[u8]) -> String {
+    data.iter().map(|&b| b as char).collect()
+}
+
+impl Lyrics3 {
+    // 在end之前查找，返回Lyrics3块和它在文件中的起始位置，read_at(pos, len)读取指定区域
+    pub fn find(read_at: &mut dyn FnMut(u64, usize) -> Option<Vec<u8>>, end: u64) -> Option<(Self, u64)> {
+        let trailer_pos = end.checked_sub(15)?;
+        let trailer = read_at(trailer_pos, 15)?;
+        if trailer[6..] == *END_V2 {
+            // 6位十进制长度包括LYRICSBEGIN，不包括长度本身和LYRICS200
+            let size: usize = std::str::from_utf8(&trailer[..6]).ok()?.parse().ok()?;
+            let start = trailer_pos.checked_sub(size as u64)?;
+            let data = read_at(start, size)?;
+            if !data.starts_with(BEGIN) {
+                return None;
+            }
+            return Some((Self::parse_v2(&data[BEGIN.len()..]), start));
+        }
+        if trailer[6..] == *END_V1 {
+            let lyrics_end = end - END_V1.len() as u64;
+            let search = (MAX_V1_SIZE + BEGIN.len()).min(lyrics_end as usize);
+            let data = read_at(lyrics_end - search as u64, search)?;
+            let begin = data.windows(BEGIN.len()).rposition(|w| w == BEGIN)?;
+            let lyrics = latin1(&data[begin + BEGIN.len()..]);
+            let start = lyrics_end - (search - begin) as u64;
+            return Some((Self { version: 1, fields: vec![("LYR".to_string(), lyrics)] }, start));
+        }
+        None
+    }
+
+    // 每个字段为3个字母的ID、5位十进制长度和内容
+    fn parse_v2(mut data: &[u8]) -> Self {
+        let mut fields = Vec::new();
+        while data.len() >= 8 {
+            let id = latin1(&data[..3]);
+            let Some(size) = std::str::from_utf8(&data[3..8]).ok().and_then(|s| s.parse::<usize>().ok()) else {
+                break;
+            };
+            let Some(value) = data.get(8..8 + size) else {
+                break;
+            };
+            fields.push((id, latin1(value)));
+            data = &data[8 + size..];
+        }
+        Self { version: 2, fields }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.fields.iter().find(|(f, _)| f == id).map(|(_, value)| value.as_str())
+    }
+
+    // 歌词，可能带有[mm:ss]时间标签
+    pub fn lyrics(&self) -> Option<&str> {
+        self.get("LYR")
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.get("ETT")
+    }
+
+    pub fn artist(&self) -> Option<&str> {
+        self.get("EAR")
+    }
+
+    pub fn album(&self) -> Option<&str> {
+        self.get("EAL")
+    }
+}
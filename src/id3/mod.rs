@@ -1,9 +1,21 @@
-use std::{fs::File, io::{BufReader, Read}};
+use std::{collections::BTreeMap, io::Read};
 
-use frames::ID3v2Frame;
+use flate2::read::ZlibDecoder;
+use chapters::{chapter_tree, Chapter, ChapterFrame, TocFrame};
+use date::Date;
+use frames::{frame_id_from_v22, ID3v2Frame, Picture, SyncedLyrics};
+use genre::resolve_genres;
+use v1::Id3v1;
 
+pub mod chapters;
+pub mod date;
 pub mod frames;
+pub mod genre;
+pub mod lyrics3;
+pub mod v1;
+pub mod writer;
 
+#[derive(Default)]
 pub struct Id3v2 {
     pub major_version: u8,
     pub revision: u8,
@@ -14,88 +26,664 @@ pub struct Id3v2 {
     pub comment: Option<String>,
     pub genre: Option<String>,
     pub track_number: Option<String>,
+    frames: BTreeMap<String, Vec<ID3v2Frame>>,
 }
 
-fn get_id3_size(header: &[u8; 10]) -> usize {
-    if header[0..3] == [b'I', b'D', b'3'] {
-        (header[6] as usize & 0x7f) << 21
-            | (header[7] as usize & 0x7f) << 14
-            | (header[8] as usize & 0x7f) << 7
-            | (header[9] as usize & 0x7f) + 10
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |size, &b| size << 7 | (b as usize & 0x7f))
+}
+
+fn big_endian(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |size, &b| size << 8 | b as usize)
+}
+
+// data开头的ID3v2标签的总长度，包括标签头和footer，没有标签时返回0
+pub fn id3v2_tag_size(data: &[u8]) -> usize {
+    if data.len() < 10 || data[0..3] != *b"ID3" {
+        return 0;
+    }
+    let size = syncsafe(&data[6..10]);
+    let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+    (size + 10 + footer).min(data.len())
+}
+
+fn is_frame_id(id: &[u8]) -> bool {
+    id.iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+// v2.4的帧长度为syncsafe整数，但有些编码器（如旧版iTunes）写入的是普通整数
+fn frame_size_v24(body: &[u8], pos: usize) -> usize {
+    let bytes = &body[pos + 4..pos + 8];
+    let plain = big_endian(bytes);
+    if bytes.iter().any(|&b| b & 0x80 != 0) {
+        return plain;
+    }
+    let size = syncsafe(bytes);
+    let valid_next = |size: usize| {
+        let next = pos + 10 + size;
+        next == body.len() || body.get(next) == Some(&0) || body.get(next..next + 4).is_some_and(is_frame_id)
+    };
+    if !valid_next(size) && valid_next(plain) {
+        plain
     } else {
-        0
+        size
+    }
+}
+
+fn text_values<'a>(frames: &'a BTreeMap<String, Vec<ID3v2Frame>>, id: &str) -> Option<&'a [String]> {
+    frames.get(id)?.iter().find_map(|frame| match frame {
+        ID3v2Frame::Text(values) => Some(values.as_slice()),
+        _ => None,
+    })
+}
+
+// 标签头中的标志位
+const TAG_UNSYNCHRONISATION: u8 = 0x80;
+const TAG_EXTENDED_HEADER: u8 = 0x40;
+const TAG_FOOTER: u8 = 0x10;
+
+// 去除反同步，将0xff 0x00还原为0xff
+fn remove_unsynchronisation(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    for (i, &b) in data.iter().enumerate() {
+        if b == 0 && i > 0 && data[i - 1] == 0xff {
+            continue;
+        }
+        result.push(b);
+    }
+    result
+}
+
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut result).ok()?;
+    Some(result)
+}
+
+// 按帧头中的格式标志还原帧数据，加密或无法解压的帧返回None
+fn frame_data(major_version: u8, flags: u16, unsync: bool, mut data: &[u8]) -> Option<Vec<u8>> {
+    match major_version {
+        3 => {
+            let compressed = flags & 0x0080 != 0;
+            // 附加字节依次为解压后长度、加密方法和分组ID
+            if compressed {
+                data = data.get(4..)?;
+            }
+            if flags & 0x0040 != 0 {
+                return None;
+            }
+            if flags & 0x0020 != 0 {
+                data = data.get(1..)?;
+            }
+            if compressed {
+                inflate(data)
+            } else {
+                Some(data.to_vec())
+            }
+        }
+        4 => {
+            // 附加字节依次为分组ID、加密方法和数据长度
+            if flags & 0x0040 != 0 {
+                data = data.get(1..)?;
+            }
+            if flags & 0x0004 != 0 {
+                return None;
+            }
+            if flags & 0x0001 != 0 {
+                data = data.get(4..)?;
+            }
+            let data = if unsync || flags & 0x0002 != 0 {
+                remove_unsynchronisation(data)
+            } else {
+                data.to_vec()
+            };
+            if flags & 0x0008 != 0 {
+                inflate(&data)
+            } else {
+                Some(data)
+            }
+        }
+        _ => Some(data.to_vec()),
+    }
+}
+
+// 解析标签头之后的所有帧，v2.2的帧ID转换为四字母ID
+// unsync为v2.4标签头中的反同步标志，此时每一帧都需要去除反同步
+fn parse_frames(major_version: u8, body: &[u8], unsync: bool) -> BTreeMap<String, Vec<ID3v2Frame>> {
+    let header_len = if major_version == 2 { 6 } else { 10 };
+    let mut frames: BTreeMap<String, Vec<ID3v2Frame>> = BTreeMap::new();
+    let mut pos = 0;
+    while pos + header_len <= body.len() {
+        let header = &body[pos..pos + header_len];
+        // 遇到填充区
+        if header[0] == 0 {
+            break;
+        }
+        let (id, size) = match major_version {
+            2 => (&header[..3], big_endian(&header[3..6])),
+            3 => (&header[..4], big_endian(&header[4..8])),
+            _ => (&header[..4], frame_size_v24(body, pos)),
+        };
+        let flags = if major_version == 2 { 0 } else { u16::from_be_bytes([header[8], header[9]]) };
+        if !is_frame_id(id) {
+            break;
+        }
+        let id = String::from_utf8_lossy(id).to_string();
+        let id = if major_version == 2 { frame_id_from_v22(&id) } else { id };
+
+        let start = pos + header_len;
+        let Some(data) = body.get(start..start + size) else {
+            break;
+        };
+        pos = start + size;
+        // 无法解码的帧直接跳过
+        let Some(data) = frame_data(major_version, flags, unsync, data) else {
+            continue;
+        };
+        let sub_frames = |data: &[u8]| parse_frames(major_version, data, false);
+        let frame = match id.as_str() {
+            "CHAP" => ChapterFrame::parse(&data, sub_frames).map(ID3v2Frame::Chapter),
+            "CTOC" => TocFrame::parse(&data, sub_frames).map(ID3v2Frame::TableOfContents),
+            _ => None,
+        };
+        let frame = frame.unwrap_or_else(|| {
+            if major_version == 2 {
+                ID3v2Frame::new_v22(&id, &data)
+            } else {
+                ID3v2Frame::new(&id, &data)
+            }
+        });
+        frames.entry(id).or_default().push(frame);
+    }
+    frames
+}
+
+// 只有ID3v1时转换为没有帧的ID3v2，版本号为1
+impl From<&Id3v1> for Id3v2 {
+    fn from(v1: &Id3v1) -> Self {
+        let mut tag = Id3v2 {
+            major_version: 1,
+            ..Default::default()
+        };
+        tag.merge_v1(v1);
+        tag
     }
 }
 
 impl Id3v2 {
-    pub fn new(reader: &mut BufReader<File>) -> Option<Id3v2> {
+    // 读取reader开头的标签（包括footer），没有标签时返回None，此时已经读取了10个字节
+    pub fn new<R: Read>(reader: &mut R) -> Option<Id3v2> {
         let mut header = [0; 10];
-        reader.read_exact(&mut header).unwrap();
-        let size = get_id3_size(&header);
+        reader.read_exact(&mut header).ok()?;
+        if header[0..3] != *b"ID3" {
+            return None;
+        }
+        let mut body = vec![0; syncsafe(&header[6..10])];
+        reader.read_exact(&mut body).ok()?;
+        if header[3] == 4 && header[5] & TAG_FOOTER != 0 {
+            reader.read_exact(&mut [0; 10]).ok()?;
+        }
+        Some(Self::parse(&header, &body))
+    }
 
-        if size == 0 {
+    // 从完整的标签数据解析
+    pub fn from_bytes(data: &[u8]) -> Option<Id3v2> {
+        if data.len() < 10 || data[0..3] != *b"ID3" {
             return None;
         }
+        let end = (10 + syncsafe(&data[6..10])).min(data.len());
+        Some(Self::parse(data[..10].try_into().unwrap(), &data[10..end]))
+    }
+
+    fn parse(header: &[u8; 10], body: &[u8]) -> Id3v2 {
         let major_version = header[3];
         let revision = header[4];
+        let flags = header[5];
+        let unsync = flags & TAG_UNSYNCHRONISATION != 0;
 
-        let mut title = None;
-        let mut artist = None;
-        let mut album = None;
-        let mut year = None;
-        let mut comment = None;
-        let mut genre = None;
-        let mut track_number = None;
-
-        let mut count = 0;
-        while count < (size - 10) {
-            reader.read_exact(&mut header).unwrap();
-
-            let frame_size = (header[4] as usize & 0x7f) << 21
-                | (header[5] as usize & 0x7f) << 14
-                | (header[6] as usize & 0x7f) << 7
-                | (header[7] as usize & 0x7f);
-            let mut buffer = vec![0; frame_size as usize];
-            reader.read_exact(&mut buffer).unwrap();
-
-            let frame = ID3v2Frame::new(header[0..4].try_into().unwrap(), &buffer, revision);
-            count += frame_size as usize + 10;
-
-            match frame {
-                ID3v2Frame::Title(str) => {
-                    title = Some(str);
-                }
-                ID3v2Frame::Artist(str) => {
-                    artist = Some(str);
-                }
-                ID3v2Frame::Album(str) => {
-                    album = Some(str);
-                }
-                ID3v2Frame::Year(str) => {
-                    year = Some(str);
-                }
-                ID3v2Frame::Comment(str) => {
-                    comment = Some(str);
-                }
-                ID3v2Frame::Genre(str) => {
-                    genre = Some(str);
-                }
-                ID3v2Frame::TrackNumber(str) => {
-                    track_number = Some(str);
-                }
-                _ => {}
-            }
+        // v2.2和v2.3的反同步作用于整个标签，v2.4则作用于每一帧
+        let body = if unsync && major_version < 4 {
+            remove_unsynchronisation(body)
+        } else {
+            body.to_vec()
+        };
+        let frames_start = match major_version {
+            // v2.2的压缩标志没有定义压缩方式，忽略所有帧
+            2 if flags & 0x40 != 0 => body.len(),
+            // v2.3扩展头的长度不包括长度字段本身
+            3 if flags & TAG_EXTENDED_HEADER != 0 && body.len() >= 4 => 4 + big_endian(&body[..4]),
+            4 if flags & TAG_EXTENDED_HEADER != 0 && body.len() >= 4 => syncsafe(&body[..4]),
+            _ => 0,
+        };
+        let frames = parse_frames(
+            major_version,
+            body.get(frames_start..).unwrap_or_default(),
+            unsync && major_version == 4,
+        );
+        let mut tag = Self {
+            major_version,
+            revision,
+            frames,
+            ..Default::default()
+        };
+        tag.update_fields();
+        tag
+    }
+
+    // 由帧重新计算各个字段
+    fn update_fields(&mut self) {
+        let frames = &self.frames;
+        let joined = |id: &str| text_values(frames, id).map(|values| values.join("/"));
+        // 优先选择没有描述的注释，带描述的通常是iTunNORM等程序数据
+        let comments: Vec<(&String, &String)> = frames
+            .get("COMM")
+            .into_iter()
+            .flatten()
+            .filter_map(|frame| match frame {
+                ID3v2Frame::Comment { description, text, .. } => Some((description, text)),
+                _ => None,
+            })
+            .collect();
+        self.comment = comments
+            .iter()
+            .find(|(description, _)| description.is_empty())
+            .or(comments.first())
+            .map(|(_, text)| text.to_string());
+        self.title = joined("TIT2");
+        self.artist = joined("TPE1");
+        self.album = joined("TALB");
+        self.year = self.recording_date().map(|date| date.year.to_string());
+        let genres = self.genres();
+        self.genre = (!genres.is_empty()).then(|| genres.join("; "));
+        self.track_number = joined("TRCK");
+    }
+
+    fn first_text(&self, id: &str) -> Option<&str> {
+        self.text(id)?.first().map(String::as_str)
+    }
+
+    // 录音时间，v2.4为TDRC，v2.3由TYER、TDAT和TIME组合而成
+    pub fn recording_date(&self) -> Option<Date> {
+        self.first_text("TDRC").and_then(Date::parse).or_else(|| {
+            Date::from_v23(self.first_text("TYER")?, self.first_text("TDAT"), self.first_text("TIME"))
+        })
+    }
+
+    // 原始发行时间，v2.4为TDOR，v2.3为TORY
+    pub fn original_date(&self) -> Option<Date> {
+        self.first_text("TDOR")
+            .and_then(Date::parse)
+            .or_else(|| Date::from_v23(self.first_text("TORY")?, None, None))
+    }
+
+    // TCON中的流派，编号转换为名称
+    pub fn genres(&self) -> Vec<String> {
+        self.text("TCON").map(resolve_genres).unwrap_or_default()
+    }
+
+    // 设置文本帧的值，替换已有的同ID帧
+    // 录音时间在v2.3和v2.4中是不同的帧，同时删除另一版本的帧，转换版本时以新设置的值为准
+    pub fn set_text(&mut self, id: &str, value: &str) {
+        let replaced: &[&str] = match id {
+            "TDRC" => &["TYER", "TDAT", "TIME"],
+            "TYER" => &["TDRC"],
+            _ => &[],
+        };
+        for old in replaced {
+            self.frames.remove(*old);
         }
-        Some(Self {
-            major_version: major_version,
-            revision: revision,
-            title: title,
-            artist: artist,
-            album: album,
-            year: year,
-            comment: comment,
-            genre: genre,
-            track_number: track_number,
+        self.frames.insert(id.to_string(), vec![ID3v2Frame::Text(vec![value.to_string()])]);
+        self.update_fields();
+    }
+
+    // 设置TXXX的值，替换描述相同（不区分大小写）的TXXX
+    pub fn set_user_text(&mut self, description: &str, value: &str) {
+        let frames = self.frames.entry("TXXX".to_string()).or_default();
+        frames.retain(|frame| !matches!(frame, ID3v2Frame::UserText { description: d, .. } if d.eq_ignore_ascii_case(description)));
+        frames.push(ID3v2Frame::UserText {
+            description: description.to_string(),
+            values: vec![value.to_string()],
+        });
+    }
+
+    // 删除某个ID的所有帧
+    pub fn remove(&mut self, id: &str) {
+        self.frames.remove(id);
+        self.update_fields();
+    }
+
+    // 添加图片，替换已有的同类型图片
+    pub fn set_picture(&mut self, picture: Picture) {
+        let pictures = self.frames.entry("APIC".to_string()).or_default();
+        pictures.retain(|frame| !matches!(frame, ID3v2Frame::Picture(p) if p.picture_type == picture.picture_type));
+        pictures.push(ID3v2Frame::Picture(picture));
+    }
+
+    // 用ID3v1的值补充ID3v2中没有的字段
+    pub fn merge_v1(&mut self, v1: &Id3v1) {
+        let fill = |field: &mut Option<String>, value: Option<String>| {
+            if field.is_none() {
+                *field = value;
+            }
+        };
+        fill(&mut self.title, v1.title.clone());
+        fill(&mut self.artist, v1.artist.clone());
+        fill(&mut self.album, v1.album.clone());
+        fill(&mut self.year, v1.year.clone());
+        fill(&mut self.comment, v1.comment.clone());
+        fill(&mut self.genre, v1.genre_name());
+        fill(&mut self.track_number, v1.track.map(|track| track.to_string()));
+    }
+
+    // 按四字母帧ID分组的所有帧
+    pub fn frames(&self) -> &BTreeMap<String, Vec<ID3v2Frame>> {
+        &self.frames
+    }
+
+    // 文本帧的所有值
+    pub fn text(&self, id: &str) -> Option<&[String]> {
+        text_values(&self.frames, id)
+    }
+
+    // 所有图片，按标签中出现的顺序
+    pub fn pictures(&self) -> Vec<&Picture> {
+        self.frames
+            .get("APIC")
+            .into_iter()
+            .flatten()
+            .filter_map(|frame| match frame {
+                ID3v2Frame::Picture(picture) => Some(picture),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // 描述匹配的TXXX的值，不区分大小写，如"REPLAYGAIN_TRACK_GAIN"
+    pub fn user_text(&self, description: &str) -> Option<&[String]> {
+        self.frames.get("TXXX")?.iter().find_map(|frame| match frame {
+            ID3v2Frame::UserText { description: d, values } if d.eq_ignore_ascii_case(description) => {
+                Some(values.as_slice())
+            }
+            _ => None,
+        })
+    }
+
+    // W***帧中的URL
+    pub fn url(&self, id: &str) -> Option<&str> {
+        self.frames.get(id)?.iter().find_map(|frame| match frame {
+            ID3v2Frame::Url(url) => Some(url.as_str()),
+            _ => None,
         })
     }
+
+    // 描述匹配的WXXX中的URL
+    pub fn user_url(&self, description: &str) -> Option<&str> {
+        self.frames.get("WXXX")?.iter().find_map(|frame| match frame {
+            ID3v2Frame::UserUrl { description: d, url } if d.eq_ignore_ascii_case(description) => Some(url.as_str()),
+            _ => None,
+        })
+    }
+
+    // 所有者匹配的UFID，MusicBrainz的所有者为"http://musicbrainz.org"
+    pub fn unique_file_id(&self, owner: &str) -> Option<&[u8]> {
+        self.frames.get("UFID")?.iter().find_map(|frame| match frame {
+            ID3v2Frame::UniqueFileId { owner: o, identifier } if o == owner => Some(identifier.as_slice()),
+            _ => None,
+        })
+    }
+
+    // 所有者匹配的PRIV中的数据
+    pub fn private(&self, owner: &str) -> Option<&[u8]> {
+        self.frames.get("PRIV")?.iter().find_map(|frame| match frame {
+            ID3v2Frame::Private { owner: o, data } if o == owner => Some(data.as_slice()),
+            _ => None,
+        })
+    }
+
+    // 第一个USLT帧中的不同步歌词
+    pub fn lyrics(&self) -> Option<&str> {
+        self.frames.get("USLT")?.iter().find_map(|frame| match frame {
+            ID3v2Frame::Lyrics { text, .. } => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    // 同步歌词，优先选择内容类型为歌词的SYLT帧
+    pub fn synced_lyrics(&self) -> Option<&SyncedLyrics> {
+        let all: Vec<&SyncedLyrics> = self
+            .frames
+            .get("SYLT")?
+            .iter()
+            .filter_map(|frame| match frame {
+                ID3v2Frame::SyncedLyrics(lyrics) => Some(lyrics),
+                _ => None,
+            })
+            .collect();
+        all.iter().find(|l| l.content_type == 1).or(all.first()).copied()
+    }
+
+    // CHAP和CTOC构成的章节树
+    pub fn chapters(&self) -> Vec<Chapter> {
+        let chapters: Vec<&ChapterFrame> = self
+            .frames
+            .get("CHAP")
+            .into_iter()
+            .flatten()
+            .filter_map(|frame| match frame {
+                ID3v2Frame::Chapter(chapter) => Some(chapter),
+                _ => None,
+            })
+            .collect();
+        let tocs: Vec<&TocFrame> = self
+            .frames
+            .get("CTOC")
+            .into_iter()
+            .flatten()
+            .filter_map(|frame| match frame {
+                ID3v2Frame::TableOfContents(toc) => Some(toc),
+                _ => None,
+            })
+            .collect();
+        chapter_tree(&chapters, &tocs)
+    }
+
+    // 封面图片，没有封面类型时取第一张
+    pub fn cover(&self) -> Option<&Picture> {
+        let pictures = self.pictures();
+        pictures.iter().find(|p| p.picture_type == 3).or(pictures.first()).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn tag(version: u8, frames: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let frames: Vec<_> = frames.iter().map(|&(id, data)| (id, 0, data)).collect();
+        tag_with_flags(version, 0, &frames)
+    }
+
+    fn tag_with_flags(version: u8, flags: u8, frames: &[(&[u8], u16, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (id, frame_flags, data) in frames {
+            body.extend_from_slice(id);
+            let size = data.len() as u32;
+            match version {
+                2 => body.extend_from_slice(&size.to_be_bytes()[1..]),
+                3 => body.extend_from_slice(&size.to_be_bytes()),
+                _ => body.extend((0..4).rev().map(|i| (size >> (7 * i)) as u8 & 0x7f)),
+            }
+            if version > 2 {
+                body.extend_from_slice(&frame_flags.to_be_bytes());
+            }
+            body.extend_from_slice(data);
+        }
+        body.extend_from_slice(&[0; 16]);
+        let size = body.len() as u32;
+        let mut data = vec![b'I', b'D', b'3', version, 0, flags];
+        data.extend((0..4).rev().map(|i| (size >> (7 * i)) as u8 & 0x7f));
+        data.extend(body);
+        data
+    }
+
+    #[test]
+    fn text_encodings_and_versions() {
+        let v22 = Id3v2::from_bytes(&tag(2, &[(b"TT2", b"\x00Caf\xe9"), (b"TP1", b"\x03A")])).unwrap();
+        assert_eq!(v22.title.as_deref(), Some("Café"));
+        assert_eq!(v22.text("TPE1"), Some(&["A".to_string()][..]));
+
+        let v23 = Id3v2::from_bytes(&tag(3, &[(b"TIT2", b"\x01\xff\xfeH\x00i\x00\x00\x00"), (b"TALB", b"\x02\x00O\x00K")])).unwrap();
+        assert_eq!(v23.title.as_deref(), Some("Hi"));
+        assert_eq!(v23.album.as_deref(), Some("OK"));
+
+        let long = [b"\x03".as_slice(), &[b'x'; 200]].concat();
+        let v24 = Id3v2::from_bytes(&tag(4, &[(b"TPE1", b"\x03A\x00B\x00"), (b"TIT2", &long), (b"COMM", b"\x00engdesc\x00text")])).unwrap();
+        assert_eq!(v24.text("TPE1").unwrap(), ["A", "B"]);
+        assert_eq!(v24.title.as_ref().map(|t| t.len()), Some(200));
+        assert_eq!(v24.comment.as_deref(), Some("text"));
+        assert_eq!(v24.frames().len(), 3);
+    }
+
+    #[test]
+    fn dates_and_genres() {
+        let frames: &[(&[u8], &[u8])] = &[
+            (b"TYER", b"\x001999"),
+            (b"TDAT", b"\x002512"),
+            (b"TIME", b"\x002030"),
+            (b"TORY", b"\x001975"),
+            (b"TCON", b"\x00(RX)(17)(4)Eurodisco"),
+        ];
+        let v23 = Id3v2::from_bytes(&tag(3, frames)).unwrap();
+        assert_eq!(v23.recording_date().unwrap().to_string(), "1999-12-25T20:30");
+        assert_eq!(v23.original_date().unwrap().to_string(), "1975");
+        assert_eq!(v23.genres(), ["Remix", "Rock", "Disco", "Eurodisco"]);
+        assert_eq!(v23.year.as_deref(), Some("1999"));
+
+        let frames: &[(&[u8], &[u8])] = &[(b"TDRC", b"\x032004-07-01T12"), (b"TCON", b"\x0317\x00CR\x00Shoegaze\x00((Odd)")];
+        let v24 = Id3v2::from_bytes(&tag(4, frames)).unwrap();
+        assert_eq!(v24.recording_date().unwrap().month, Some(7));
+        assert_eq!(v24.year.as_deref(), Some("2004"));
+        assert_eq!(v24.genres(), ["Rock", "Cover", "Shoegaze", "(Odd)"]);
+    }
+
+    #[test]
+    fn chapter_tree() {
+        let chapter = |id: &[u8], start: u32, end: u32, title: &[u8]| {
+            let mut data = [id, b"\x00"].concat();
+            for value in [start, end, u32::MAX, u32::MAX] {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            data.extend_from_slice(&tag(3, &[(b"TIT2", title)])[10..]);
+            data
+        };
+        let intro = chapter(b"c1", 0, 5000, b"\x00Intro");
+        let part1 = chapter(b"c2", 5000, 60000, b"\x00Part 1");
+        let part2 = chapter(b"c3", 60000, 90000, b"\x00Part 2");
+        let sub_toc = [&b"toc2\x00\x01\x02c2\x00c3\x00"[..], &tag(3, &[(b"TIT2", b"\x00Main")])[10..]].concat();
+        let frames: &[(&[u8], &[u8])] = &[
+            (b"CTOC", b"toc1\x00\x03\x02c1\x00toc2\x00"),
+            (b"CTOC", &sub_toc),
+            (b"CHAP", &part2),
+            (b"CHAP", &intro),
+            (b"CHAP", &part1),
+        ];
+        let chapters = Id3v2::from_bytes(&tag(3, frames)).unwrap().chapters();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].title.as_deref(), Some("Intro"));
+        assert_eq!(chapters[1].title.as_deref(), Some("Main"));
+        assert_eq!(chapters[1].end, Duration::from_secs(90));
+        let leaves: Vec<&str> = Chapter::leaves(&chapters).iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(leaves, ["c1", "c2", "c3"]);
+    }
+
+    #[test]
+    fn pictures() {
+        let v22 = Id3v2::from_bytes(&tag(2, &[(b"PIC", b"\x00PNG\x03\x00\x89PNG")])).unwrap();
+        let cover = v22.cover().unwrap();
+        assert_eq!(cover.mime_type, "image/png");
+        assert_eq!(cover.data, b"\x89PNG");
+
+        let apic: &[(&[u8], &[u8])] = &[
+            (b"APIC", b"\x01image/jpeg\x00\x04\xff\xfeB\x00\x00\x00\xff\xd8"),
+            (b"APIC", b"\x00\x00\x03front\x00\xff\xd8\xff"),
+        ];
+        let v23 = Id3v2::from_bytes(&tag(3, apic)).unwrap();
+        let pictures = v23.pictures();
+        assert_eq!(pictures.len(), 2);
+        assert_eq!(pictures[0].description, "B");
+        assert_eq!(pictures[0].type_name(), "Cover (back)");
+        assert_eq!(v23.cover().unwrap().description, "front");
+        assert_eq!(v23.cover().unwrap().extension(), "jpg");
+    }
+
+    #[test]
+    fn user_defined_frames() {
+        let frames: &[(&[u8], &[u8])] = &[
+            (b"COMM", b"\x00engiTunNORM\x00 0000\x00"),
+            (b"COMM", b"\x03eng\x00Nice"),
+            (b"TXXX", b"\x03MusicBrainz Album Id\x00abc"),
+            (b"TXXX", b"\x00replaygain_track_gain\x00-6.5 dB"),
+            (b"WOAF", b"http://example.com/a.mp3\x00"),
+            (b"WXXX", b"\x01\xff\xfeP\x00\x00\x00http://example.com/feed"),
+            (b"UFID", b"http://musicbrainz.org\x00id-123"),
+            (b"PRIV", b"WM/MediaClassPrimaryID\x00\x01\x02"),
+        ];
+        let v24 = Id3v2::from_bytes(&tag(4, frames)).unwrap();
+        assert_eq!(v24.comment.as_deref(), Some("Nice"));
+        assert_eq!(v24.user_text("musicbrainz album id").unwrap(), ["abc"]);
+        assert_eq!(v24.user_text("REPLAYGAIN_TRACK_GAIN").unwrap(), ["-6.5 dB"]);
+        assert_eq!(v24.url("WOAF"), Some("http://example.com/a.mp3"));
+        assert_eq!(v24.user_url("P"), Some("http://example.com/feed"));
+        assert_eq!(v24.unique_file_id("http://musicbrainz.org"), Some(&b"id-123"[..]));
+        assert_eq!(v24.private("WM/MediaClassPrimaryID"), Some(&[1u8, 2][..]));
+    }
+
+    #[test]
+    fn lyrics_frames() {
+        let frames: &[(&[u8], &[u8])] = &[
+            (b"USLT", b"\x00engdesc\x00line one\nline two"),
+            (b"SYLT", b"\x03eng\x02\x01\x00One\x00\x00\x00\x03\xe8\nTwo\x00\x00\x00\x07\xd0"),
+        ];
+        let v24 = Id3v2::from_bytes(&tag(4, frames)).unwrap();
+        assert_eq!(v24.lyrics(), Some("line one\nline two"));
+        let sylt = v24.synced_lyrics().unwrap();
+        assert_eq!(sylt.lines, [(1000, "One".to_string()), (2000, "\nTwo".to_string())]);
+    }
+
+    #[test]
+    fn tag_and_frame_flags() {
+        use flate2::{write::ZlibEncoder, Compression};
+        use std::io::{Cursor, Write};
+
+        // v2.3：整个标签反同步，带扩展头
+        let ext = [0, 0, 0, 6, 0, 0, 0, 0, 0, 0];
+        let frames = tag_with_flags(3, 0, &[(b"TIT2", 0, b"\x00\xff\xe0")]);
+        let mut body = ext.to_vec();
+        body.extend_from_slice(&frames[10..]);
+        let body: Vec<u8> = body
+            .iter()
+            .flat_map(|&b| if b == 0xff { vec![0xff, 0] } else { vec![b] })
+            .collect();
+        let mut v23 = vec![b'I', b'D', b'3', 3, 0, 0xc0];
+        v23.extend((0..4).rev().map(|i| (body.len() >> (7 * i)) as u8 & 0x7f));
+        v23.extend(body);
+        let v23 = Id3v2::from_bytes(&v23).unwrap();
+        assert_eq!(v23.title.as_deref(), Some("\u{ff}\u{e0}"));
+
+        // v2.4：压缩帧带数据长度，加密帧被跳过，footer之后是音频
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"\x03Compressed").unwrap();
+        let compressed = [&[0, 0, 0, 11][..], &encoder.finish().unwrap()].concat();
+        let mut v24 = tag_with_flags(4, 0x10, &[(b"TIT2", 0x0009, &compressed), (b"TPE1", 0x0004, b"\x01\x03A")]);
+        v24.extend_from_slice(b"3DI\x04\x00\x10");
+        let size: [u8; 4] = v24[6..10].try_into().unwrap();
+        v24.extend_from_slice(&size);
+        v24.push(0xff);
+        let mut reader = Cursor::new(&v24);
+        let v24 = Id3v2::new(&mut reader).unwrap();
+        assert_eq!(v24.title.as_deref(), Some("Compressed"));
+        assert!(v24.artist.is_none());
+        assert_eq!(reader.position() as usize, reader.get_ref().len() - 1);
+    }
 }
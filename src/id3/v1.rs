@@ -0,0 +1,189 @@
+use std::io::{Read, Seek, SeekFrom};
+
+// Winamp扩展后的ID3v1流派列表，0~79为ID3v1标准定义
+pub const GENRES: [&str; 192] = [
+    "Blues", "Classic Rock", "Country", "Dance", "Disco", "Funk", "Grunge", "Hip-Hop", "Jazz",
+    "Metal", "New Age", "Oldies", "Other", "Pop", "R&B", "Rap", "Reggae", "Rock", "Techno",
+    "Industrial", "Alternative", "Ska", "Death Metal", "Pranks", "Soundtrack", "Euro-Techno",
+    "Ambient", "Trip-Hop", "Vocal", "Jazz+Funk", "Fusion", "Trance", "Classical", "Instrumental",
+    "Acid", "House", "Game", "Sound Clip", "Gospel", "Noise", "AlternRock", "Bass", "Soul", "Punk",
+    "Space", "Meditative", "Instrumental Pop", "Instrumental Rock", "Ethnic", "Gothic", "Darkwave",
+    "Techno-Industrial", "Electronic", "Pop-Folk", "Eurodance", "Dream", "Southern Rock", "Comedy",
+    "Cult", "Gangsta", "Top 40", "Christian Rap", "Pop/Funk", "Jungle", "Native American",
+    "Cabaret", "New Wave", "Psychadelic", "Rave", "Showtunes", "Trailer", "Lo-Fi", "Tribal",
+    "Acid Punk", "Acid Jazz", "Polka", "Retro", "Musical", "Rock & Roll", "Hard Rock",
+    // Winamp扩展
+    "Folk", "Folk-Rock", "National Folk", "Swing", "Fast Fusion", "Bebob", "Latin", "Revival",
+    "Celtic", "Bluegrass", "Avantgarde", "Gothic Rock", "Progressive Rock", "Psychedelic Rock",
+    "Symphonic Rock", "Slow Rock", "Big Band", "Chorus", "Easy Listening", "Acoustic", "Humour",
+    "Speech", "Chanson", "Opera", "Chamber Music", "Sonata", "Symphony", "Booty Bass", "Primus",
+    "Porn Groove", "Satire", "Slow Jam", "Club", "Tango", "Samba", "Folklore", "Ballad",
+    "Power Ballad", "Rhythmic Soul", "Freestyle", "Duet", "Punk Rock", "Drum Solo", "A capella",
+    "Euro-House", "Dance Hall", "Goa", "Drum & Bass", "Club-House", "Hardcore", "Terror", "Indie",
+    "BritPop", "Afro-Punk", "Polsk Punk", "Beat", "Christian Gangsta Rap", "Heavy Metal",
+    "Black Metal", "Crossover", "Contemporary Christian", "Christian Rock", "Merengue", "Salsa",
+    "Thrash Metal", "Anime", "JPop", "Synthpop", "Abstract", "Art Rock", "Baroque", "Bhangra",
+    "Big Beat", "Breakbeat", "Chillout", "Downtempo", "Dub", "EBM", "Eclectic", "Electro",
+    "Electroclash", "Emo", "Experimental", "Garage", "Global", "IDM", "Illbient", "Industro-Goth",
+    "Jam Band", "Krautrock", "Leftfield", "Lounge", "Math Rock", "New Romantic", "Nu-Breakz",
+    "Post-Punk", "Post-Rock", "Psytrance", "Shoegaze", "Space Rock", "Trop Rock", "World Music",
+    "Neoclassical", "Audiobook", "Audio Theatre", "Neue Deutsche Welle", "Podcast", "Indie Rock",
+    "G-Funk", "Dubstep", "Garage Rock", "Psybient",
+];
+
+pub const ID3V1_SIZE: usize = 128;
+pub const TAG_PLUS_SIZE: usize = 227;
+
+pub fn genre_name(index: u8) -> Option<&'static str> {
+    GENRES.get(index as usize).copied()
+}
+
+// ISO-8859-1字符串，去掉结尾的空字符和空格
+fn latin1(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    data[..end].iter().map(|&b| b as char).collect::<String>().trim_end().to_string()
+}
+
+fn non_empty(s: String) -> Option<String> {
+    (!s.is_empty()).then_some(s)
+}
+
+// 文件末尾128字节的ID3v1/v1.1标签，以及其前面可选的227字节TAG+扩展
+pub struct Id3v1 {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub year: Option<String>,
+    pub comment: Option<String>,
+    // v1.1中注释的最后一个字节
+    pub track: Option<u8>,
+    pub genre: Option<u8>,
+    // 是否带有TAG+，以下字段来自TAG+
+    pub extended: bool,
+    pub speed: Option<u8>,
+    pub genre_text: Option<String>,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+}
+
+impl Id3v1 {
+    // tag为128字节的ID3v1标签，tag_plus为其前面的227字节
+    pub fn parse(tag: &[u8], tag_plus: Option<&[u8]>) -> Option<Self> {
+        if tag.len() != ID3V1_SIZE || tag[..3] != *b"TAG" {
+            return None;
+        }
+        let (comment, track) = if tag[125] == 0 && tag[126] != 0 {
+            (&tag[97..125], Some(tag[126]))
+        } else {
+            (&tag[97..127], None)
+        };
+        let mut result = Self {
+            title: non_empty(latin1(&tag[3..33])),
+            artist: non_empty(latin1(&tag[33..63])),
+            album: non_empty(latin1(&tag[63..93])),
+            year: non_empty(latin1(&tag[93..97])),
+            comment: non_empty(latin1(comment)),
+            track,
+            genre: (tag[127] != 255).then_some(tag[127]),
+            extended: false,
+            speed: None,
+            genre_text: None,
+            start_time: None,
+            end_time: None,
+        };
+
+        let Some(plus) = tag_plus.filter(|p| p.len() == TAG_PLUS_SIZE && p[..4] == *b"TAG+") else {
+            return Some(result);
+        };
+        // TAG+中的标题、艺术家和专辑接在ID3v1的30个字符之后
+        let extend = |field: &mut Option<String>, data: &[u8]| {
+            let rest = latin1(data);
+            if !rest.is_empty() {
+                *field = Some(field.take().unwrap_or_default() + &rest);
+            }
+        };
+        result.extended = true;
+        extend(&mut result.title, &plus[4..64]);
+        extend(&mut result.artist, &plus[64..124]);
+        extend(&mut result.album, &plus[124..184]);
+        result.speed = (plus[184] != 0).then_some(plus[184]);
+        result.genre_text = non_empty(latin1(&plus[185..215]));
+        result.start_time = non_empty(latin1(&plus[215..221]));
+        result.end_time = non_empty(latin1(&plus[221..227]));
+        Some(result)
+    }
+
+    // 从完整文件数据的末尾解析
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let tag = data.get(data.len().checked_sub(ID3V1_SIZE)?..)?;
+        let plus = data
+            .len()
+            .checked_sub(ID3V1_SIZE + TAG_PLUS_SIZE)
+            .map(|start| &data[start..start + TAG_PLUS_SIZE]);
+        Self::parse(tag, plus)
+    }
+
+    // 读取文件末尾的标签，之后恢复reader的位置
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Option<Self> {
+        let pos = reader.stream_position().ok()?;
+        let len = reader.seek(SeekFrom::End(0)).ok()? as usize;
+        let size = len.min(ID3V1_SIZE + TAG_PLUS_SIZE);
+        let mut data = vec![0; size];
+        let result = reader
+            .seek(SeekFrom::End(-(size as i64)))
+            .and_then(|_| reader.read_exact(&mut data))
+            .ok()
+            .and_then(|_| Self::from_bytes(&data));
+        reader.seek(SeekFrom::Start(pos)).ok()?;
+        result
+    }
+
+    // TAG+中的流派文本优先
+    pub fn genre_name(&self) -> Option<String> {
+        self.genre_text
+            .clone()
+            .or_else(|| self.genre.and_then(genre_name).map(str::to_string))
+    }
+
+    // 标签在文件末尾占用的字节数
+    pub fn size(&self) -> usize {
+        if self.extended {
+            ID3V1_SIZE + TAG_PLUS_SIZE
+        } else {
+            ID3V1_SIZE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v11_and_tag_plus() {
+        let mut tag = [0u8; 128];
+        tag[..3].copy_from_slice(b"TAG");
+        tag[3..33].copy_from_slice(&[b'T'; 30]);
+        tag[33..39].copy_from_slice(b"Artist");
+        tag[93..97].copy_from_slice(b"1999");
+        tag[97..104].copy_from_slice(b"Comment");
+        tag[126] = 7;
+        tag[127] = 17;
+        let v1 = Id3v1::parse(&tag, None).unwrap();
+        assert_eq!(v1.artist.as_deref(), Some("Artist"));
+        assert_eq!(v1.year.as_deref(), Some("1999"));
+        assert_eq!(v1.comment.as_deref(), Some("Comment"));
+        assert_eq!(v1.track, Some(7));
+        assert_eq!(v1.genre_name().as_deref(), Some("Rock"));
+        assert!(v1.album.is_none());
+
+        let mut plus = [0u8; 227];
+        plus[..4].copy_from_slice(b"TAG+");
+        plus[4..8].copy_from_slice(b"tail");
+        plus[185..194].copy_from_slice(b"Post-Rock");
+        let data = [&[0xff; 10][..], &plus, &tag].concat();
+        let v1 = Id3v1::from_bytes(&data).unwrap();
+        assert_eq!(v1.title.unwrap(), "T".repeat(30) + "tail");
+        assert_eq!(v1.genre_text.as_deref(), Some("Post-Rock"));
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+
+use super::{
+    frames::{ID3v2Frame, TimestampFormat},
+    syncsafe, Id3v2, TAG_FOOTER,
+};
+
+// 重写整个文件时预留的填充，之后修改标签时可以原地写入
+const DEFAULT_PADDING: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TextEncoding {
+    Latin1,
+    Utf16,
+    Utf16be,
+    Utf8,
+}
+
+impl TextEncoding {
+    // v2.3只支持ISO-8859-1和带BOM的UTF-16
+    fn for_version(self, version: u8) -> Self {
+        match self {
+            Self::Utf16be | Self::Utf8 if version < 4 => Self::Utf16,
+            _ => self,
+        }
+    }
+
+    // 无法用ISO-8859-1表示的文本改用UTF-16
+    fn for_text(self, text: &str) -> Self {
+        if self == Self::Latin1 && text.chars().any(|c| c as u32 > 0xff) {
+            Self::Utf16
+        } else {
+            self
+        }
+    }
+
+    fn byte(self) -> u8 {
+        self as u8
+    }
+
+    fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            Self::Latin1 => text.chars().map(|c| c as u8).collect(),
+            Self::Utf16 => [0xff, 0xfe]
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect(),
+            Self::Utf16be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            Self::Utf8 => text.as_bytes().to_vec(),
+        }
+    }
+
+    fn terminator(self) -> &'static [u8] {
+        match self {
+            Self::Utf16 | Self::Utf16be => &[0, 0],
+            _ => &[0],
+        }
+    }
+}
+
+// 写入帧内容时的状态
+struct FrameWriter {
+    version: u8,
+    encoding: TextEncoding,
+}
+
+impl FrameWriter {
+    // texts中的所有文本使用同一种编码
+    fn encoding(&self, texts: &[&str]) -> TextEncoding {
+        let encoding = self.encoding.for_version(self.version);
+        texts.iter().fold(encoding, |encoding, text| encoding.for_text(text))
+    }
+
+    // v2.4中多个值以空字符分隔，v2.3用"/"连接
+    fn join(&self, values: &[String], encoding: TextEncoding) -> Vec<u8> {
+        if self.version < 4 {
+            return encoding.encode(&values.join("/"));
+        }
+        values
+            .iter()
+            .map(|value| encoding.encode(value))
+            .collect::<Vec<_>>()
+            .join(encoding.terminator())
+    }
+
+    fn language(language: &str) -> Vec<u8> {
+        let mut bytes: Vec<u8> = language.bytes().take(3).collect();
+        bytes.resize(3, b'X');
+        bytes
+    }
+
+    // 序列化所有帧，包括帧头
+    fn frames(&self, frames: &BTreeMap<String, Vec<ID3v2Frame>>) -> Vec<u8> {
+        let mut body = Vec::new();
+        let mut written: Vec<&str> = Vec::new();
+        for (id, frames) in frames {
+            let id = frame_id(id, self.version);
+            // 只写入四字母ID，转换后重复的帧只保留第一个
+            if id.len() != 4 || written.contains(&id) {
+                continue;
+            }
+            written.push(id);
+            for frame in frames {
+                let data = match (id, frame) {
+                    // TDRC中可以带有月和日，TYER只有年份
+                    ("TYER", ID3v2Frame::Text(values)) => {
+                        self.data(&ID3v2Frame::Text(values.iter().map(|v| v.chars().take(4).collect()).collect()))
+                    }
+                    _ => self.data(frame),
+                };
+                body.extend_from_slice(id.as_bytes());
+                if self.version == 3 {
+                    body.extend_from_slice(&(data.len() as u32).to_be_bytes());
+                } else {
+                    body.extend_from_slice(&syncsafe_bytes(data.len()));
+                }
+                body.extend_from_slice(&[0, 0]);
+                body.extend(data);
+            }
+        }
+        body
+    }
+
+    fn data(&self, frame: &ID3v2Frame) -> Vec<u8> {
+        let mut data = Vec::new();
+        match frame {
+            ID3v2Frame::Text(values) => {
+                let texts: Vec<&str> = values.iter().map(String::as_str).collect();
+                let encoding = self.encoding(&texts);
+                data.push(encoding.byte());
+                data.extend(self.join(values, encoding));
+            }
+            ID3v2Frame::Comment { language, description, text } | ID3v2Frame::Lyrics { language, description, text } => {
+                let encoding = self.encoding(&[description, text]);
+                data.push(encoding.byte());
+                data.extend(Self::language(language));
+                data.extend(encoding.encode(description));
+                data.extend_from_slice(encoding.terminator());
+                data.extend(encoding.encode(text));
+            }
+            ID3v2Frame::Picture(picture) => {
+                let encoding = self.encoding(&[&picture.description]);
+                data.push(encoding.byte());
+                data.extend(TextEncoding::Latin1.encode(&picture.mime_type));
+                data.push(0);
+                data.push(picture.picture_type);
+                data.extend(encoding.encode(&picture.description));
+                data.extend_from_slice(encoding.terminator());
+                data.extend_from_slice(&picture.data);
+            }
+            ID3v2Frame::SyncedLyrics(lyrics) => {
+                let mut texts: Vec<&str> = lyrics.lines.iter().map(|(_, text)| text.as_str()).collect();
+                texts.push(&lyrics.description);
+                let encoding = self.encoding(&texts);
+                data.push(encoding.byte());
+                data.extend(Self::language(&lyrics.language));
+                data.push(match lyrics.timestamp_format {
+                    TimestampFormat::MpegFrames => 1,
+                    TimestampFormat::Milliseconds => 2,
+                });
+                data.push(lyrics.content_type);
+                data.extend(encoding.encode(&lyrics.description));
+                data.extend_from_slice(encoding.terminator());
+                for (timestamp, text) in &lyrics.lines {
+                    data.extend(encoding.encode(text));
+                    data.extend_from_slice(encoding.terminator());
+                    data.extend_from_slice(&timestamp.to_be_bytes());
+                }
+            }
+            ID3v2Frame::UserText { description, values } => {
+                let mut texts: Vec<&str> = values.iter().map(String::as_str).collect();
+                texts.push(description);
+                let encoding = self.encoding(&texts);
+                data.push(encoding.byte());
+                data.extend(encoding.encode(description));
+                data.extend_from_slice(encoding.terminator());
+                data.extend(self.join(values, encoding));
+            }
+            ID3v2Frame::Url(url) => data.extend(TextEncoding::Latin1.encode(url)),
+            ID3v2Frame::UserUrl { description, url } => {
+                let encoding = self.encoding(&[description]);
+                data.push(encoding.byte());
+                data.extend(encoding.encode(description));
+                data.extend_from_slice(encoding.terminator());
+                data.extend(TextEncoding::Latin1.encode(url));
+            }
+            ID3v2Frame::UniqueFileId { owner, identifier: bytes } | ID3v2Frame::Private { owner, data: bytes } => {
+                data.extend(TextEncoding::Latin1.encode(owner));
+                data.push(0);
+                data.extend_from_slice(bytes);
+            }
+            ID3v2Frame::Chapter(chapter) => {
+                data.extend(TextEncoding::Latin1.encode(&chapter.element_id));
+                data.push(0);
+                let offset = |value: Option<u32>| value.unwrap_or(u32::MAX);
+                for value in [chapter.start_time, chapter.end_time, offset(chapter.start_offset), offset(chapter.end_offset)] {
+                    data.extend_from_slice(&value.to_be_bytes());
+                }
+                data.extend(self.frames(&chapter.frames));
+            }
+            ID3v2Frame::TableOfContents(toc) => {
+                data.extend(TextEncoding::Latin1.encode(&toc.element_id));
+                data.push(0);
+                data.push(if toc.top_level { 0x02 } else { 0 } | if toc.ordered { 0x01 } else { 0 });
+                data.push(toc.children.len() as u8);
+                for child in &toc.children {
+                    data.extend(TextEncoding::Latin1.encode(child));
+                    data.push(0);
+                }
+                data.extend(self.frames(&toc.frames));
+            }
+            ID3v2Frame::Unknown(bytes) => data.extend_from_slice(bytes),
+        }
+        data
+    }
+}
+
+fn syncsafe_bytes(size: usize) -> [u8; 4] {
+    [(size >> 21) as u8 & 0x7f, (size >> 14) as u8 & 0x7f, (size >> 7) as u8 & 0x7f, size as u8 & 0x7f]
+}
+
+// v2.3和v2.4中录音时间帧不同，写入前转换
+fn frame_id(id: &str, version: u8) -> &str {
+    match (id, version) {
+        ("TYER", 4) => "TDRC",
+        ("TDRC", 3) => "TYER",
+        _ => id,
+    }
+}
+
+// 序列化为v2.3或v2.4标签，总长度不小于min_size，不足的部分用0填充
+pub fn to_bytes(tag: &Id3v2, version: u8, encoding: TextEncoding, min_size: usize) -> Vec<u8> {
+    let writer = FrameWriter { version, encoding };
+    let mut body = writer.frames(tag.frames());
+    body.resize(body.len().max(min_size.saturating_sub(10)), 0);
+
+    let mut data = vec![b'I', b'D', b'3', version, 0, 0];
+    data.extend_from_slice(&syncsafe_bytes(body.len()));
+    data.extend(body);
+    data
+}
+
+// 写入文件开头的标签，替换原有的ID3v2标签
+// 新标签不超过原标签（含填充）时原地写入，否则写到临时文件后替换原文件，返回是否原地写入
+pub fn write_file(path: &Path, tag: &Id3v2, version: u8, encoding: TextEncoding) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 10];
+    let old_size = match file.read_exact(&mut header) {
+        // 只有标签头时id3v2_tag_size会截断到10字节，这里直接按标签头计算
+        Ok(()) if header[..3] == *b"ID3" => 10 + syncsafe(&header[6..10]) + if header[5] & TAG_FOOTER != 0 { 10 } else { 0 },
+        Ok(()) => 0,
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => 0,
+        Err(e) => return Err(e),
+    };
+
+    let data = to_bytes(tag, version, encoding, 0);
+    if old_size > 0 && data.len() <= old_size {
+        drop(file);
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.write_all(&to_bytes(tag, version, encoding, old_size))?;
+        file.sync_all()?;
+        return Ok(true);
+    }
+
+    let data = to_bytes(tag, version, encoding, data.len() + DEFAULT_PADDING);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let temp = path.with_file_name(name);
+    let result = (|| {
+        let mut output = File::create(&temp)?;
+        output.write_all(&data)?;
+        file.seek(SeekFrom::Start(old_size as u64))?;
+        io::copy(&mut file, &mut output)?;
+        output.sync_all()?;
+        // 新文件保持原文件的权限
+        fs::set_permissions(&temp, fs::metadata(path)?.permissions())?;
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result.map(|_| false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id3::frames::Picture;
+
+    #[test]
+    fn round_trip_and_in_place() {
+        let mut tag = Id3v2::default();
+        tag.set_text("TIT2", "Café 東京");
+        tag.set_text("TPE1", "Artist");
+        tag.set_text("TYER", "1999");
+        tag.set_picture(Picture {
+            mime_type: "image/png".to_string(),
+            picture_type: 3,
+            description: String::new(),
+            data: vec![0x89, b'P', b'N', b'G'],
+        });
+
+        for (version, encoding) in [(3, TextEncoding::Utf8), (4, TextEncoding::Latin1), (4, TextEncoding::Utf16be)] {
+            let parsed = Id3v2::from_bytes(&to_bytes(&tag, version, encoding, 0)).unwrap();
+            assert_eq!(parsed.major_version, version);
+            assert_eq!(parsed.title.as_deref(), Some("Café 東京"));
+            assert_eq!(parsed.artist.as_deref(), Some("Artist"));
+            assert_eq!(parsed.year.as_deref(), Some("1999"));
+            assert_eq!(parsed.cover().unwrap().data, tag.cover().unwrap().data);
+        }
+
+        // 新设置的年份替换另一版本的录音时间帧
+        let mut dated = Id3v2::from_bytes(&to_bytes(&tag, 4, TextEncoding::Utf8, 0)).unwrap();
+        assert!(dated.text("TDRC").is_some());
+        dated.set_text("TYER", "2001");
+        for version in [3, 4] {
+            let parsed = Id3v2::from_bytes(&to_bytes(&dated, version, TextEncoding::Utf8, 0)).unwrap();
+            assert_eq!(parsed.recording_date().map(|d| d.year), Some(2001));
+        }
+        dated.set_text("TDRC", "2002-03-04");
+        assert!(dated.text("TYER").is_none());
+        let parsed = Id3v2::from_bytes(&to_bytes(&dated, 3, TextEncoding::Utf8, 0)).unwrap();
+        assert_eq!(parsed.year.as_deref(), Some("2002"));
+
+        let path = std::env::temp_dir().join(format!("id3_writer_{}.mp3", std::process::id()));
+        fs::write(&path, [&to_bytes(&tag, 4, TextEncoding::Utf8, 4096)[..], b"audio"].concat()).unwrap();
+        tag.remove("TPE1");
+        assert!(write_file(&path, &tag, 4, TextEncoding::Utf8).unwrap());
+        tag.set_text("TALB", &"x".repeat(5000));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+        assert!(!write_file(&path, &tag, 3, TextEncoding::Utf16).unwrap());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(data.ends_with(b"audio"));
+        let parsed = Id3v2::from_bytes(&data).unwrap();
+        assert!(parsed.artist.is_none());
+        assert_eq!(parsed.album.map(|a| a.len()), Some(5000));
+    }
+}
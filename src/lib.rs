@@ -15,12 +15,25 @@ use mpeg_frame::{
 };
 use thiserror;
 
+pub mod ape;
 pub mod bitstream;
+pub mod cue;
 pub mod debug;
 pub mod decode;
+pub mod edit;
+pub mod encode;
 pub mod huffman;
 pub mod id3;
+pub mod loudness;
+pub mod lyrics;
+pub mod metadata;
 pub mod mpeg_frame;
+pub mod output;
+pub mod player;
+pub mod playlist;
+pub mod queue;
+pub mod tags;
+pub mod validate;
 
 const SQRT2: f32 = 1.41421356;
 
@@ -34,6 +47,8 @@ pub enum DecodeError {
     UnsupportedMpegVersion(u8),
     #[error("读取文件失败: {0}")]
     ReadFileError(std::io::Error),
+    #[error("写入文件失败: {0}")]
+    WriteFileError(std::io::Error),
 }
 
 pub struct Decoder {
@@ -45,8 +60,13 @@ pub struct Decoder {
 
     pub channel_num: usize,
     pub sample_rate: usize,
+    // 最近解码的一帧的比特率（kbps）和声道模式，VBR文件中每帧可能不同
+    pub bit_rate: usize,
+    pub channel_mode: Option<MpegChannelMode>,
 
     pub data_end: u64,
+    // calculate_mp3_duration中记录的每一帧的起始位置，用于跳转
+    pub frame_positions: Vec<u64>,
 }
 
 impl Decoder {
@@ -59,7 +79,10 @@ impl Decoder {
             fifo: [[0f32; 1024]; 2],
             channel_num: 0,
             sample_rate: 0,
+            bit_rate: 0,
+            channel_mode: None,
             data_end: 0,
+            frame_positions: Vec::new(),
         }
     }
 
@@ -69,9 +92,14 @@ impl Decoder {
     ) -> Result<Duration, DecodeError> {
         let pos = reader.stream_position().unwrap();
         let mut duration: f32 = 0.0;
+        self.frame_positions.clear();
 
         let mut buf = [0u8; 4];
         loop {
+            // data_end已经设置时（如文件末尾有APEv2标签）只计算到该位置
+            if self.data_end != 0 && reader.stream_position().unwrap() >= self.data_end {
+                break;
+            }
             match reader.read_exact(&mut buf) {
                 Ok(_) => {}
                 Err(e) => {
@@ -82,10 +110,11 @@ impl Decoder {
                 }
             };
             if buf[0..3] == [b'T', b'A', b'G'] {
-                self.data_end = reader.stream_position().unwrap();
+                self.data_end = reader.stream_position().unwrap() - 4;
                 break;
             }
-            let mpeg_header = parse_header(&buf).unwrap();
+            let mpeg_header = parse_header(&buf)?;
+            self.frame_positions.push(reader.stream_position().unwrap() - 4);
             self.channel_num = mpeg_header.channel.to_channel_num();
             self.sample_rate = mpeg_header.sample_rate.get_rate();
             let bit_rate = mpeg_header.bit_rate.get_rate();
@@ -120,6 +149,8 @@ impl Decoder {
         let mpeg_header = parse_header(&buf)?;
         self.channel_num = mpeg_header.channel.to_channel_num();
         self.sample_rate = mpeg_header.sample_rate.get_rate();
+        self.bit_rate = mpeg_header.bit_rate.get_rate();
+        self.channel_mode = Some(mpeg_header.channel);
 
         let size =
             144 * mpeg_header.bit_rate.get_rate() * 1000 / mpeg_header.sample_rate.get_rate();
@@ -143,6 +174,13 @@ impl Decoder {
             + if mpeg_header.padding { 1 } else { 0 };
         dbg_println!(DebugType::Header, "nslots: {}", nslots);
 
+        // 比特池中的数据不足（如跳转之后的第一帧），本帧输出静音
+        if self.main_buf.len() < side_info.main_data_end {
+            let mut buf = vec![0u8; nslots];
+            reader.read_exact(&mut buf).map_err(DecodeError::ReadFileError)?;
+            self.main_buf.extend(buf);
+            return Ok(vec![0.0; 1152 * self.channel_num]);
+        }
         self.main_buf = self
             .main_buf
             .split_off(self.main_buf.len() - side_info.main_data_end);
@@ -215,4 +253,38 @@ impl Decoder {
 
         Ok(pcm[0..(1152 * self.channel_num)].to_vec())
     }
+
+    // 跳转到第frame帧，需要先调用calculate_mp3_duration
+    // 之前的若干帧会被解码后丢弃，用于填充比特池和IMDCT的重叠部分
+    pub fn seek_frame(&mut self, reader: &mut BufReader<File>, frame: usize) -> Result<(), DecodeError> {
+        let Some(&target) = self.frame_positions.get(frame) else {
+            return Err(DecodeError::EndOfFile);
+        };
+        // 比特池最多向前引用511字节
+        let mut start = frame.saturating_sub(1);
+        while start > 0 && target - self.frame_positions[start] < 1024 {
+            start -= 1;
+        }
+
+        self.main_buf.clear();
+        self.main_data_begin = 0;
+        self.prev_samples = [[[0f32; 18]; 32]; 2];
+        self.synthesis = Synthesis::new();
+        self.fifo = [[0f32; 1024]; 2];
+        reader
+            .seek(std::io::SeekFrom::Start(self.frame_positions[start]))
+            .map_err(DecodeError::ReadFileError)?;
+        for _ in start..frame {
+            self.decode_mp3(reader)?;
+        }
+        Ok(())
+    }
+
+    // 跳转到time所在的帧，返回该帧的开始时间
+    pub fn seek(&mut self, reader: &mut BufReader<File>, time: Duration) -> Result<Duration, DecodeError> {
+        let frame_duration = 1152.0 / self.sample_rate as f64;
+        let frame = ((time.as_secs_f64() / frame_duration) as usize).min(self.frame_positions.len().saturating_sub(1));
+        self.seek_frame(reader, frame)?;
+        Ok(Duration::from_secs_f64(frame as f64 * frame_duration))
+    }
 }
@@ -0,0 +1,276 @@
+use std::{f64::consts::PI, io};
+
+use crate::output::PcmSink;
+
+// ReplayGain 2.0的参考响度
+pub const REFERENCE_LOUDNESS: f64 = -18.0;
+const ABSOLUTE_GATE: f64 = -70.0;
+// 积分响度和响度范围的相对门限
+const RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+// 以100ms的片段为单位：400ms的门限块和3s的短期响度窗口
+const BLOCK_SEGMENTS: usize = 4;
+const SHORT_TERM_SEGMENTS: usize = 30;
+// 真峰值检测时4倍过采样，每个相位12个抽头
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+// 直接II型转置结构的二阶滤波器，系数已除以a0，每个声道单独保存状态
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Biquad {
+    pub(crate) b: [f64; 3],
+    pub(crate) a: [f64; 2],
+}
+
+impl Biquad {
+    pub(crate) fn process(&self, x: f64, state: &mut [f64; 2]) -> f64 {
+        let y = self.b[0] * x + state[0];
+        state[0] = self.b[1] * x - self.a[0] * y + state[1];
+        state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+// BS.1770的K计权：模拟头部影响的高架滤波器和RLB高通滤波器，按采样率重新计算系数
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+    [shelf, high_pass]
+}
+
+// 过采样插值滤波器：加汉宁窗的sinc，按相位排列
+fn interpolation_filter() -> [[f64; TAPS_PER_PHASE]; OVERSAMPLING] {
+    let taps = OVERSAMPLING * TAPS_PER_PHASE;
+    let center = (taps - 1) as f64 / 2.0;
+    let mut phases = [[0.0; TAPS_PER_PHASE]; OVERSAMPLING];
+    for n in 0..taps {
+        let x = (n as f64 - center) / OVERSAMPLING as f64;
+        let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+        let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / taps as f64).cos();
+        phases[n % OVERSAMPLING][n / OVERSAMPLING] = sinc * window;
+    }
+    phases
+}
+
+// 均方值对应的响度（LUFS）
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// 相邻片段组成的窗口的均方值，每次移动一个片段
+fn windows(segments: &[f64], size: usize) -> impl Iterator<Item = f64> + '_ {
+    segments.windows(size).map(mean)
+}
+
+// 经过绝对门限和相对门限之后剩下的块
+fn gate(blocks: &[f64], relative: f64) -> Vec<f64> {
+    let blocks: Vec<f64> = blocks.iter().copied().filter(|&z| loudness(z) > ABSOLUTE_GATE).collect();
+    if blocks.is_empty() {
+        return blocks;
+    }
+    let threshold = loudness(mean(&blocks)) + relative;
+    blocks.into_iter().filter(|&z| loudness(z) > threshold).collect()
+}
+
+// 一首或一张专辑的测量结果，峰值为线性值（满幅为1.0）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    // 积分响度（LUFS），太短或者全是静音时为None
+    pub integrated: Option<f64>,
+    // 响度范围（LU）
+    pub range: f64,
+    pub true_peak: f32,
+    pub sample_peak: f32,
+}
+
+impl Loudness {
+    fn measure<'a>(analyzers: impl Iterator<Item = &'a LoudnessAnalyzer> + Clone) -> Self {
+        let blocks: Vec<f64> = analyzers.clone().flat_map(|a| windows(&a.segments, BLOCK_SEGMENTS)).collect();
+        let short_terms: Vec<f64> = analyzers.clone().flat_map(|a| windows(&a.segments, SHORT_TERM_SEGMENTS)).collect();
+        let gated = gate(&blocks, RELATIVE_GATE);
+        let integrated = (!gated.is_empty()).then(|| loudness(mean(&gated)));
+
+        // 响度范围为门限之后短期响度分布的10%到95%
+        let mut levels: Vec<f64> = gate(&short_terms, RANGE_RELATIVE_GATE).into_iter().map(loudness).collect();
+        levels.sort_by(f64::total_cmp);
+        let percentile = |p: f64| levels[((levels.len() - 1) as f64 * p).round() as usize];
+        let range = if levels.is_empty() { 0.0 } else { percentile(0.95) - percentile(0.10) };
+
+        let peak = |f: fn(&LoudnessAnalyzer) -> f32| analyzers.clone().map(f).fold(0.0, f32::max);
+        Self {
+            integrated,
+            range,
+            true_peak: peak(|a| a.true_peak),
+            sample_peak: peak(|a| a.sample_peak),
+        }
+    }
+
+    // 专辑的结果：所有曲目的块合在一起计算
+    pub fn album(analyzers: &[LoudnessAnalyzer]) -> Self {
+        Self::measure(analyzers.iter())
+    }
+
+    // 达到ReplayGain 2.0参考响度需要的增益（dB）
+    pub fn replay_gain(&self) -> Option<f64> {
+        self.integrated.map(|integrated| REFERENCE_LOUDNESS - integrated)
+    }
+}
+
+// 按BS.1770测量响度的输出端，解码得到的PCM写入后取得结果
+pub struct LoudnessAnalyzer {
+    channels: usize,
+    filters: [Biquad; 2],
+    // 每个声道两级滤波器的状态
+    states: Vec<[[f64; 2]; 2]>,
+    // 每个100ms片段的各声道均方值之和，不满100ms的结尾被丢弃
+    segments: Vec<f64>,
+    segment_len: usize,
+    segment_frames: usize,
+    segment_energy: f64,
+    interpolation: [[f64; TAPS_PER_PHASE]; OVERSAMPLING],
+    // 每个声道最近的样本，用于过采样
+    history: Vec<[f64; TAPS_PER_PHASE]>,
+    true_peak: f32,
+    sample_peak: f32,
+}
+
+impl Default for LoudnessAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoudnessAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            channels: 0,
+            filters: k_weighting(48000.0),
+            states: Vec::new(),
+            segments: Vec::new(),
+            segment_len: 0,
+            segment_frames: 0,
+            segment_energy: 0.0,
+            interpolation: interpolation_filter(),
+            history: Vec::new(),
+            true_peak: 0.0,
+            sample_peak: 0.0,
+        }
+    }
+
+    pub fn result(&self) -> Loudness {
+        Loudness::measure(std::iter::once(self))
+    }
+
+    fn process_frame(&mut self, frame: &[f32]) {
+        for (channel, &sample) in frame.iter().enumerate() {
+            let x = sample as f64;
+            self.sample_peak = self.sample_peak.max(sample.abs());
+
+            let history = &mut self.history[channel];
+            history.copy_within(1.., 0);
+            history[TAPS_PER_PHASE - 1] = x;
+            for phase in &self.interpolation {
+                let y: f64 = phase.iter().rev().zip(history.iter()).map(|(h, x)| h * x).sum();
+                self.true_peak = self.true_peak.max(y.abs() as f32);
+            }
+
+            let state = &mut self.states[channel];
+            let y = self.filters[1].process(self.filters[0].process(x, &mut state[0]), &mut state[1]);
+            self.segment_energy += y * y;
+        }
+        self.segment_frames += 1;
+        if self.segment_frames == self.segment_len {
+            self.segments.push(self.segment_energy / self.segment_len as f64);
+            self.segment_frames = 0;
+            self.segment_energy = 0.0;
+        }
+    }
+}
+
+impl PcmSink for LoudnessAnalyzer {
+    fn begin(&mut self, sample_rate: u32, channels: u16) -> io::Result<()> {
+        self.channels = channels.max(1) as usize;
+        self.filters = k_weighting(sample_rate as f64);
+        self.states = vec![[[0.0; 2]; 2]; self.channels];
+        self.history = vec![[0.0; TAPS_PER_PHASE]; self.channels];
+        self.segment_len = (sample_rate / 10).max(1) as usize;
+        Ok(())
+    }
+
+    fn write_samples(&mut self, pcm: &[f32]) -> io::Result<()> {
+        for frame in pcm.chunks_exact(self.channels) {
+            self.process_frame(frame);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f64, amplitude: f64, seconds: f64, channels: usize) -> Vec<f32> {
+        (0..(48000.0 * seconds) as usize)
+            .flat_map(|i| {
+                let sample = (amplitude * (2.0 * PI * frequency * i as f64 / 48000.0).sin()) as f32;
+                std::iter::repeat_n(sample, channels)
+            })
+            .collect()
+    }
+
+    fn analyze(pcm: &[f32], channels: u16) -> LoudnessAnalyzer {
+        let mut analyzer = LoudnessAnalyzer::new();
+        analyzer.begin(48000, channels).unwrap();
+        analyzer.write_samples(pcm).unwrap();
+        analyzer.finish().unwrap();
+        analyzer
+    }
+
+    #[test]
+    fn measures_reference_tones() {
+        // EBU Tech 3341：双声道1kHz、-23dBFS的正弦波为-23LUFS
+        let amplitude = 10f64.powf(-23.0 / 20.0);
+        let tone = analyze(&sine(1000.0, amplitude, 20.0, 2), 2);
+        let result = tone.result();
+        assert!((result.integrated.unwrap() + 23.0).abs() < 0.1);
+        assert!(result.range < 0.1);
+        assert!((result.sample_peak as f64 - amplitude).abs() < 1e-3);
+        assert!((result.replay_gain().unwrap() - 5.0).abs() < 0.1);
+
+        // 采样点落在峰值两侧（相位45°）时真峰值高于采样峰值
+        let shifted: Vec<f32> = (0..48000).map(|i| (0.7 * (PI * i as f64 / 2.0 + PI / 4.0).sin()) as f32).collect();
+        let peaks = analyze(&shifted, 1).result();
+        assert!((peaks.sample_peak - 0.495).abs() < 1e-3);
+        assert!((peaks.true_peak - 0.7).abs() < 0.02);
+
+        // 两段相差10dB的音调：专辑的响度范围约为10LU，静音不影响积分响度
+        let quiet = analyze(&sine(1000.0, amplitude / 10f64.sqrt(), 20.0, 2), 2);
+        let silence = analyze(&vec![0.0; 48000 * 2 * 5], 2);
+        assert_eq!(silence.result().integrated, None);
+        let album = Loudness::album(&[tone, quiet, silence]);
+        assert!((album.range - 10.0).abs() < 0.5);
+        assert!(album.integrated.unwrap() > -26.0 && album.integrated.unwrap() < -23.0);
+    }
+}
@@ -0,0 +1,123 @@
+use std::{path::Path, time::Duration};
+
+use crate::id3::frames::{SyncedLyrics, TimestampFormat};
+
+pub struct LyricLine {
+    pub time: Duration,
+    pub text: String,
+}
+
+// 按时间排序的歌词，来自.lrc文件或SYLT帧
+pub struct Lyrics {
+    pub lines: Vec<LyricLine>,
+}
+
+// 解析[mm:ss]、[mm:ss.xx]或[mm:ss:xx]形式的时间标签
+fn parse_time(tag: &str) -> Option<Duration> {
+    let (minute, second) = tag.split_once(':')?;
+    let minute: u64 = minute.trim().parse().ok()?;
+    let second: f64 = second.trim().replacen(':', ".", 1).parse().ok()?;
+    if !(0.0..60.0).contains(&second) {
+        return None;
+    }
+    Duration::from_secs(minute.checked_mul(60)?).checked_add(Duration::from_secs_f64(second))
+}
+
+impl Lyrics {
+    // 一行可以有多个时间标签，[offset:ms]为正时歌词提前显示
+    pub fn parse_lrc(text: &str) -> Self {
+        let mut offset = 0i64;
+        let mut lines = Vec::new();
+        for line in text.trim_start_matches('\u{feff}').lines() {
+            let mut rest = line.trim();
+            let mut times = Vec::new();
+            while let Some(tag) = rest.strip_prefix('[') {
+                let Some((tag, after)) = tag.split_once(']') else {
+                    break;
+                };
+                if let Some(value) = tag.strip_prefix("offset:") {
+                    offset = value.trim().parse().unwrap_or(0);
+                } else if let Some(time) = parse_time(tag) {
+                    times.push(time);
+                }
+                rest = after;
+            }
+            for time in times {
+                lines.push(LyricLine {
+                    time,
+                    text: rest.trim().to_string(),
+                });
+            }
+        }
+
+        for line in &mut lines {
+            let ms = i64::try_from(line.time.as_millis()).unwrap_or(i64::MAX).saturating_sub(offset);
+            line.time = Duration::from_millis(ms.max(0) as u64);
+        }
+        lines.sort_by_key(|line| line.time);
+        Self { lines }
+    }
+
+    // 读取文件，非UTF-8的内容按ISO-8859-1处理
+    pub fn load_lrc(path: &Path) -> Option<Self> {
+        let data = std::fs::read(path).ok()?;
+        let text = match String::from_utf8(data) {
+            Ok(text) => text,
+            Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+        };
+        let lyrics = Self::parse_lrc(&text);
+        (!lyrics.lines.is_empty()).then_some(lyrics)
+    }
+
+    // 与MP3文件同名的.lrc文件
+    pub fn load_sidecar(mp3_path: &Path) -> Option<Self> {
+        Self::load_lrc(&mp3_path.with_extension("lrc"))
+    }
+
+    // frame_duration为一帧的时长，用于换算以MPEG帧为单位的时间戳
+    pub fn from_sylt(sylt: &SyncedLyrics, frame_duration: Duration) -> Self {
+        let mut lines: Vec<LyricLine> = sylt
+            .lines
+            .iter()
+            .map(|(timestamp, text)| LyricLine {
+                time: match sylt.timestamp_format {
+                    TimestampFormat::MpegFrames => frame_duration * *timestamp,
+                    TimestampFormat::Milliseconds => Duration::from_millis(*timestamp as u64),
+                },
+                // SYLT中的换行写在文本开头
+                text: text.trim_start_matches(['\n', '\r']).to_string(),
+            })
+            .collect();
+        lines.sort_by_key(|line| line.time);
+        Self { lines }
+    }
+
+    // pos时应显示的行的序号
+    pub fn index_at(&self, pos: Duration) -> Option<usize> {
+        self.lines.partition_point(|line| line.time <= pos).checked_sub(1)
+    }
+
+    pub fn line_at(&self, pos: Duration) -> Option<&LyricLine> {
+        self.index_at(pos).map(|i| &self.lines[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lrc_lines_in_order() {
+        let lyrics = Lyrics::parse_lrc("[ar:Someone]\n[offset:500]\n[00:12.50][01:02]Chorus\n[00:05.00]First\nnot a lyric\n");
+        let times: Vec<u128> = lyrics.lines.iter().map(|l| l.time.as_millis()).collect();
+        assert_eq!(times, [4500, 12000, 61500]);
+        assert!(lyrics.line_at(Duration::from_secs(1)).is_none());
+        assert_eq!(lyrics.line_at(Duration::from_secs(10)).unwrap().text, "First");
+        assert_eq!(lyrics.line_at(Duration::from_secs(70)).unwrap().text, "Chorus");
+
+        // 超出范围的时间标签被忽略
+        let lyrics = Lyrics::parse_lrc("[offset:-9223372036854775808]\n[99999999999999999999:00]a\n[18446744073709551615:00]b\n[307445734561825860:59.5]c\n[00:01]d\n");
+        assert_eq!(lyrics.lines.len(), 1);
+        assert_eq!(lyrics.lines[0].text, "d");
+    }
+}
@@ -1,78 +1,247 @@
-use std::{fs::File, io::{self, BufReader, Seek, Write}};
-use rodio::{OutputStream, Sink};
-use tiny_mp3_player::{id3::Id3v2, DecodeError, Decoder};
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, IsTerminal},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use rodio::{source::Amplify, OutputStream, Sink, Source};
+use tiny_mp3_player::{
+    edit::Mp3Stream,
+    id3::chapters::Chapter,
+    loudness::{Loudness, LoudnessAnalyzer},
+    lyrics::Lyrics,
+    metadata::Metadata,
+    player::{
+        equalizer::{EqHandle, Equalizer, Preset, GRAPHIC_FREQUENCIES},
+        fade::{FadeCurve, FadeHandle, Fader},
+        replay_gain::{GainSettings, ReplayGainMode},
+        FrameInfo, Mp3Source,
+    },
+    playlist::{Playlist, PlaylistEntry, PlaylistFormat},
+    queue::{collect_files, expand_cue_sheets, is_cue, Entry, Queue, Repeat},
+    output::{decode_to_sink, flac::vorbis_comments_from_metadata, new_sink, FlacWriter, OutputFormat},
+    id3::{frames::Picture, writer::{write_file, TextEncoding}, Id3v2},
+    tags::FileTags,
+    validate::{repair, validate},
+    DecodeError, Decoder,
+};
 use debug::{DebugType, DebugConfig};
-use clap::{Parser, ArgAction};
+use terminal::{format_time, poll_action, status_text, Action, RawMode, StatusLine};
+use clap::{Parser, Subcommand, ArgAction};
 mod debug;
+mod terminal;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(long = "debug", value_name = "TYPE", action = ArgAction::Append)]
+    #[arg(long = "debug", value_name = "TYPE", action = ArgAction::Append, global = true)]
     debug: Vec<DebugType>,
 
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// MP3文件或目录，目录中的.mp3文件按路径顺序加入播放队列
     #[arg(required = true)]
-    input_file: String,
-}
-
-fn pcm_f32_to_int16(sample: f32) -> i16
-{
-   if sample < -0.999999f32
-   {
-      return i16::MIN;
-   }
-   else if sample > 0.999999f32
-   {
-     return i16::MAX;
-   }
-   else
-   {
-     return (sample * 32767.0f32) as i16;
-   }
-}
-
-fn write_wav_header<W: Write>(
-    writer: &mut W,
-    num_samples: u32,
-    sample_rate: u32,
-    num_channels: u16,
-    bits_per_sample: u16,
-) -> io::Result<()> {
-    let byte_rate = sample_rate * num_channels as u32 * (bits_per_sample / 8) as u32;
-    let block_align = num_channels * (bits_per_sample / 8);
-
-    // 写入 RIFF 头
-    writer.write_all(b"RIFF")?;
-    writer.write_all(&(36 + num_samples * (bits_per_sample / 8) as u32).to_le_bytes())?; // 文件大小
-    writer.write_all(b"WAVE")?;
-
-    // 写入 fmt 头
-    writer.write_all(b"fmt ")?;
-    writer.write_all(&16u32.to_le_bytes())?; // Subchunk1Size (PCM = 16)
-    writer.write_all(&1u16.to_le_bytes())?;  // AudioFormat (PCM = 1)
-    writer.write_all(&num_channels.to_le_bytes())?; // NumChannels
-    writer.write_all(&sample_rate.to_le_bytes())?;  // SampleRate
-    writer.write_all(&byte_rate.to_le_bytes())?;    // ByteRate
-    writer.write_all(&block_align.to_le_bytes())?;  // BlockAlign
-    writer.write_all(&bits_per_sample.to_le_bytes())?; // BitsPerSample
-
-    // 写入 data 头
-    writer.write_all(b"data")?;
-    writer.write_all(&(num_samples * (bits_per_sample / 8) as u32).to_le_bytes())?; // Subchunk2Size
-
-    Ok(())
+    input_files: Vec<String>,
+
+    /// 从第N个章节开始播放（从1开始，按章节列表中的编号），只对第一首有效
+    #[arg(long, value_name = "N")]
+    chapter: Option<usize>,
+
+    /// 随机播放
+    #[arg(long)]
+    shuffle: bool,
+
+    #[arg(long, value_enum, default_value_t = Repeat::Off)]
+    repeat: Repeat,
+
+    /// 把播放队列按播放顺序保存为播放列表（.m3u、.m3u8、.pls或.xspf）
+    #[arg(long, value_name = "FILE")]
+    save_playlist: Option<String>,
+
+    /// 曲目之间交叉淡化的秒数，0表示不淡化，此时有LAME标签的曲目无缝衔接
+    #[arg(long, value_name = "SECONDS", default_value = "0", value_parser = parse_seconds)]
+    crossfade: Duration,
+
+    /// 停止播放时淡出的秒数
+    #[arg(long, value_name = "SECONDS", default_value = "0.5", value_parser = parse_seconds)]
+    fade_out: Duration,
+
+    #[arg(long, value_enum, default_value_t = FadeCurve::EqualPower)]
+    fade_curve: FadeCurve,
+
+    /// 按ReplayGain调整音量，增益依次取自ID3v2的TXXX、APEv2和LAME标签
+    #[arg(long, value_enum, default_value_t = ReplayGainMode::Off)]
+    replay_gain: ReplayGainMode,
+
+    /// 加在ReplayGain增益上的前级增益（dB）
+    #[arg(long, value_name = "DB", default_value_t = 0.0, allow_negative_numbers = true)]
+    preamp: f32,
+
+    /// 均衡器预设，内置flat、bass、treble、rock和vocal
+    #[arg(long, value_name = "PRESET", default_value = "flat")]
+    eq: String,
+
+    /// 均衡器预设文件，其中的预设与内置预设同名时替换内置预设
+    #[arg(long, value_name = "FILE")]
+    eq_presets: Option<String>,
+}
+
+// 以秒为单位的时长参数，负数、inf和超出范围的值报错
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let seconds = s.parse::<f64>().map_err(|e| e.to_string())?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 将MP3解码为PCM文件
+    Decode {
+        input_file: String,
+
+        /// 输出文件，默认为输入文件名加上对应格式的扩展名
+        #[arg(short, long)]
+        output: Option<String>,
+
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Wav)]
+        format: OutputFormat,
+    },
+    /// 按帧无损截取MP3片段，不重新编码
+    Cut {
+        input_file: String,
+
+        #[arg(short, long)]
+        output: String,
+
+        /// 开始时间（秒）
+        #[arg(short, long, default_value_t = 0.0)]
+        start: f64,
+
+        /// 结束时间（秒），默认到文件结尾
+        #[arg(short, long)]
+        end: Option<f64>,
+    },
+    /// 检查MP3文件的帧同步、CRC、比特池和标签等问题
+    Validate {
+        input_file: String,
+
+        /// 写出修复后的文件
+        #[arg(long)]
+        repair: bool,
+
+        /// 修复后的输出文件，默认为输入文件名加上.repaired.mp3
+        #[arg(short, long, requires = "repair")]
+        output: Option<String>,
+    },
+    /// 按帧无损拼接多个采样率和声道数相同的MP3文件
+    Join {
+        #[arg(required = true, num_args = 2..)]
+        input_files: Vec<String>,
+
+        #[arg(short, long)]
+        output: String,
+    },
+    /// 导出ID3v2标签中的封面图片
+    Cover {
+        input_file: String,
+
+        /// 输出文件，默认为输入文件名加上.cover和图片格式的扩展名
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 导出所有图片，文件名依次加上序号
+        #[arg(long)]
+        all: bool,
+    },
+    /// 修改ID3v2标签
+    Tag {
+        input_file: String,
+
+        #[arg(long)]
+        title: Option<String>,
+
+        #[arg(long)]
+        artist: Option<String>,
+
+        #[arg(long)]
+        album: Option<String>,
+
+        /// 音轨号，可以写成"3/12"
+        #[arg(long)]
+        track: Option<String>,
+
+        #[arg(long)]
+        genre: Option<String>,
+
+        /// 封面图片文件
+        #[arg(long)]
+        cover: Option<String>,
+
+        /// 清除字段，可以指定多次
+        #[arg(long, value_enum)]
+        clear: Vec<TagField>,
+
+        /// 写入的ID3v2版本，默认保持原版本，没有标签时为4
+        #[arg(long = "id3-version", value_parser = clap::value_parser!(u8).range(3..=4))]
+        version: Option<u8>,
+
+        /// 文本编码，v2.3不支持的编码改用UTF-16
+        #[arg(long, value_enum, default_value_t = TextEncoding::Utf8)]
+        encoding: TextEncoding,
+    },
+    /// 按ITU-R BS.1770测量积分响度、响度范围和真峰值，并计算ReplayGain 2.0的增益
+    Loudness {
+        /// MP3文件或目录，有多个文件时同时给出专辑的结果
+        #[arg(required = true)]
+        input_files: Vec<String>,
+
+        /// 把增益和峰值写入ID3v2标签的TXXX帧（REPLAYGAIN_*）
+        #[arg(long)]
+        write: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum TagField {
+    Title,
+    Artist,
+    Album,
+    Track,
+    Genre,
+    Cover,
+}
+
+impl TagField {
+    fn frame_id(self) -> &'static str {
+        match self {
+            Self::Title => "TIT2",
+            Self::Artist => "TPE1",
+            Self::Album => "TALB",
+            Self::Track => "TRCK",
+            Self::Genre => "TCON",
+            Self::Cover => "APIC",
+        }
+    }
+}
+
+// 打开MP3文件并读取开头和结尾的所有标签，返回的reader指向音频数据开头
+fn open_mp3(filename: &str) -> (BufReader<File>, FileTags) {
+    let file = File::open(filename).expect("打开文件失败！");
+    let mut reader = BufReader::new(file);
+    let tags = FileTags::read(&mut reader).expect("读取文件失败！");
+    (reader, tags)
 }
 
 #[test]
 fn test() {
+    use std::io::Write;
+    use tiny_mp3_player::output::{pcm_f32_to_int16, write_wav_header};
+
     let filename = "test.mp3";
-    let file = File::open(filename).expect("打开文件失败！");
-    let mut reader = BufReader::new(file);
-    
-    let id3 = Id3v2::new(&mut reader);
-    if let None = id3 {
-        reader.seek_relative(-10).unwrap();
-    }
+    let (mut reader, _) = open_mp3(filename);
 
     let mut decoder = Decoder::new();
 
@@ -108,97 +277,876 @@ fn test() {
     }
 }
 
-fn main() {
-    let args = Args::parse();
-    
-    // 初始化 DEBUG_CONFIG
-    DebugConfig::init(&args.debug);
+// 输出错误并以非零状态退出
+fn fail(error: impl fmt::Display) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
+}
 
-    let file = File::open(&args.input_file).expect("打开文件失败！");
-    let mut reader = BufReader::new(file);
+fn decode(input_file: &str, output: Option<String>, format: OutputFormat) {
+    let (mut reader, tags) = open_mp3(input_file);
+    let output = output.unwrap_or_else(|| {
+        Path::new(input_file)
+            .with_extension(format.extension())
+            .to_string_lossy()
+            .to_string()
+    });
 
-    // 读取描述信息
-    let id3 = Id3v2::new(&mut reader);
-    if let Some(id3) = id3 {
-        let title = match id3.title {
-            Some(title) => {title},
-            None => {args.input_file.clone()},
+    let mut decoder = Decoder::new();
+    decoder.data_end = tags.audio_end;
+    if let Err(e) = decoder.calculate_mp3_duration(&mut reader) {
+        fail(format_args!("{}: {}", input_file, e));
+    }
+
+    let file = File::create(&output).expect("创建输出文件失败！");
+    let mut sink = match format {
+        OutputFormat::Flac => Box::new(FlacWriter::new(file).with_comments(vorbis_comments_from_metadata(&tags.metadata()))),
+        _ => new_sink(format, file),
+    };
+    if let Err(e) = decode_to_sink(&mut decoder, &mut reader, sink.as_mut()) {
+        fail(format_args!("{}: {}", input_file, e));
+    }
+    println!("Output: {}", output);
+}
+
+fn read_stream(filename: &str) -> Mp3Stream {
+    let data = std::fs::read(filename).unwrap_or_else(|e| fail(format_args!("{}: {}", filename, e)));
+    Mp3Stream::parse(&data).unwrap_or_else(|e| fail(format_args!("{}: {}", filename, e)))
+}
+
+fn cut(input_file: &str, output: &str, start: f64, end: Option<f64>) {
+    let stream = read_stream(input_file);
+    let rate = stream.sample_rate as f64;
+    let end = end.map_or(stream.samples(), |end| (end * rate) as u64);
+    let result = stream.cut((start * rate) as u64, end);
+    let data = result.to_bytes().unwrap_or_else(|e| fail(e));
+    std::fs::write(output, data).expect("写入文件失败！");
+    println!("Output: {} ({} frames, {} samples)", output, result.frames.len(), result.samples());
+}
+
+fn join(input_files: &[String], output: &str) {
+    let streams: Vec<Mp3Stream> = input_files.iter().map(|f| read_stream(f)).collect();
+    let result = Mp3Stream::join(&streams).unwrap_or_else(|e| fail(e));
+    let data = result.to_bytes().unwrap_or_else(|e| fail(e));
+    std::fs::write(output, data).expect("写入文件失败！");
+    println!("Output: {} ({} frames, {} samples)", output, result.frames.len(), result.samples());
+    // 只能在帧边界拼接，接缝处的编码延迟和结尾补充无法去掉
+    let gap = Mp3Stream::join_gap(&streams);
+    if gap > 0 {
+        let seconds = gap as f64 / result.sample_rate as f64;
+        println!("Gap: {} samples ({:.3}s) of encoder delay/padding left at the joins", gap, seconds);
+    }
+}
+
+fn check(input_file: &str, fix: bool, output: Option<String>) {
+    let data = std::fs::read(input_file).expect("打开文件失败！");
+    let report = validate(&data);
+    for issue in &report.issues {
+        println!("{}", issue);
+    }
+    println!("Frames: {}, Issues: {}", report.frames, report.issues.len());
+
+    if fix {
+        let output = output.unwrap_or_else(|| {
+            Path::new(input_file)
+                .with_extension("repaired.mp3")
+                .to_string_lossy()
+                .to_string()
+        });
+        // 修复需要重新排列帧，只支持MPEG-1的文件
+        let data = repair(&data).unwrap_or_else(|e| fail(format_args!("{}: {}", input_file, e)));
+        std::fs::write(&output, data).expect("写入文件失败！");
+        println!("Output: {}", output);
+    }
+}
+
+fn cover(input_file: &str, output: Option<String>, all: bool) {
+    let (_, tags) = open_mp3(input_file);
+    let metadata = tags.metadata();
+    let pictures: Vec<_> = if all {
+        metadata.pictures.iter().collect()
+    } else {
+        metadata.cover().into_iter().collect()
+    };
+    if pictures.is_empty() {
+        println!("No picture found");
+        return;
+    }
+
+    for (i, picture) in pictures.iter().enumerate() {
+        let base = output.clone().unwrap_or_else(|| {
+            Path::new(input_file)
+                .with_extension(format!("cover.{}", picture.extension()))
+                .to_string_lossy()
+                .to_string()
+        });
+        let path = if all {
+            let path = Path::new(&base);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let name = match path.extension() {
+                Some(ext) => format!("{}-{}.{}", stem, i + 1, ext.to_string_lossy()),
+                None => format!("{}-{}", stem, i + 1),
+            };
+            path.with_file_name(name).to_string_lossy().to_string()
+        } else {
+            base
+        };
+        std::fs::write(&path, &picture.data).expect("写入文件失败！");
+        println!(
+            "Output: {} ({}, {}, {} bytes)",
+            path,
+            picture.type_name(),
+            picture.mime_type,
+            picture.data.len()
+        );
+    }
+}
+
+struct TagEdit {
+    values: Vec<(TagField, String)>,
+    cover: Option<String>,
+    clear: Vec<TagField>,
+    version: Option<u8>,
+    encoding: TextEncoding,
+}
+
+fn edit_tag(input_file: &str, edit: TagEdit) {
+    let (_, tags) = open_mp3(input_file);
+    let mut tag = tags.id3v2.unwrap_or_default();
+    for field in &edit.clear {
+        tag.remove(field.frame_id());
+    }
+    for (field, value) in &edit.values {
+        tag.set_text(field.frame_id(), value);
+    }
+    if let Some(cover) = &edit.cover {
+        let data = std::fs::read(cover).expect("读取封面图片失败！");
+        let mime_type = match data.as_slice() {
+            [0x89, b'P', b'N', b'G', ..] => "image/png",
+            [0xff, 0xd8, ..] => "image/jpeg",
+            [b'G', b'I', b'F', ..] => "image/gif",
+            _ => "application/octet-stream",
         };
-        println!("Title: {}", title);
-        if let Some(artist) = id3.artist {
-            println!("Artist: {}", artist);
+        tag.set_picture(Picture {
+            mime_type: mime_type.to_string(),
+            picture_type: 3,
+            description: String::new(),
+            data,
+        });
+    }
+
+    let version = edit.version.unwrap_or(match tag.major_version {
+        3 => 3,
+        _ => 4,
+    });
+    let in_place = write_file(Path::new(input_file), &tag, version, edit.encoding).expect("写入文件失败！");
+    println!(
+        "Output: {} (ID3v2.{}, {})",
+        input_file,
+        version,
+        if in_place { "in place" } else { "rewritten" }
+    );
+}
+
+fn format_loudness(loudness: &Loudness) -> String {
+    let decibel = |peak: f32| 20.0 * peak.max(f32::MIN_POSITIVE).log10();
+    let integrated = match (loudness.integrated, loudness.replay_gain()) {
+        (Some(integrated), Some(gain)) => format!("{:.2} LUFS, gain {:+.2} dB", integrated, gain),
+        _ => "silent".to_string(),
+    };
+    format!(
+        "{}, LRA {:.2} LU, true peak {:.2} dBTP, sample peak {:.2} dBFS",
+        integrated,
+        loudness.range,
+        decibel(loudness.true_peak),
+        decibel(loudness.sample_peak)
+    )
+}
+
+fn loudness(input_files: &[String], write: bool) {
+    let paths: Vec<PathBuf> = input_files.iter().map(PathBuf::from).collect();
+    let files: Vec<PathBuf> = collect_files(&paths)
+        .expect("读取目录失败！")
+        .into_iter()
+        .filter(|path| !is_cue(path))
+        .collect();
+    // 无法解码的文件输出错误后跳过，专辑值只由测量成功的文件计算
+    let mut measured = Vec::new();
+    let mut analyzers = Vec::new();
+    let mut failed = false;
+    for path in &files {
+        match measure_loudness(path) {
+            Ok(analyzer) => {
+                println!("{}: {}", path.display(), format_loudness(&analyzer.result()));
+                measured.push(path);
+                analyzers.push(analyzer);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                failed = true;
+            }
         }
-        if let Some(album) = id3.album {
-            println!("Album: {}", album);
+    }
+    let album = (analyzers.len() > 1).then(|| Loudness::album(&analyzers));
+    if let Some(album) = &album {
+        println!("Album: {}", format_loudness(album));
+    }
+    if write {
+        write_loudness(&measured, &analyzers, album.as_ref());
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+// 把测量结果写入各文件的ID3v2标签
+fn write_loudness(paths: &[&PathBuf], analyzers: &[LoudnessAnalyzer], album: Option<&Loudness>) {
+    // 峰值写入真峰值，静音的曲目不写入增益
+    let fields = |tag: &mut Id3v2, loudness: &Loudness, prefix: &str| {
+        if let Some(gain) = loudness.replay_gain() {
+            tag.set_user_text(&format!("{}_GAIN", prefix), &format!("{:.2} dB", gain));
+            tag.set_user_text(&format!("{}_PEAK", prefix), &format!("{:.6}", loudness.true_peak));
         }
-        if let Some(year) = id3.year {
-            println!("Year: {}", year);
+    };
+    for (path, analyzer) in paths.iter().zip(analyzers) {
+        let (_, tags) = open_mp3(&path.to_string_lossy());
+        let mut tag = tags.id3v2.unwrap_or_default();
+        fields(&mut tag, &analyzer.result(), "REPLAYGAIN_TRACK");
+        if let Some(album) = album {
+            fields(&mut tag, album, "REPLAYGAIN_ALBUM");
         }
-        if let Some(comment) = id3.comment {
-            println!("Comment: {}", comment);
+        let version = match tag.major_version {
+            3 => 3,
+            _ => 4,
+        };
+        write_file(path, &tag, version, TextEncoding::Utf8).expect("写入文件失败！");
+        println!("Output: {}", path.display());
+    }
+}
+
+// 解码整个文件并测量响度
+fn measure_loudness(path: &Path) -> Result<LoudnessAnalyzer, DecodeError> {
+    let file = File::open(path).map_err(DecodeError::ReadFileError)?;
+    let mut reader = BufReader::new(file);
+    let tags = FileTags::read(&mut reader).map_err(DecodeError::ReadFileError)?;
+    let mut decoder = Decoder::new();
+    decoder.data_end = tags.audio_end;
+    decoder.calculate_mp3_duration(&mut reader)?;
+    let mut analyzer = LoudnessAnalyzer::new();
+    decode_to_sink(&mut decoder, &mut reader, &mut analyzer)?;
+    Ok(analyzer)
+}
+
+// 播放开始前输出的描述信息
+fn describe(path: &Path, metadata: &Metadata, duration: Duration, synced_lyrics: bool) -> Vec<String> {
+    let mut lines = Vec::new();
+    lines.push(format!("Title: {}", metadata.title.clone().unwrap_or_else(|| path.display().to_string())));
+    let number = |n: Option<u32>, total: Option<u32>| match (n, total) {
+        (Some(n), Some(total)) => Some(format!("{}/{}", n, total)),
+        (n, _) => n.map(|n| n.to_string()),
+    };
+    let fields = [
+        ("Artist", metadata.artist()),
+        ("Album Artist", metadata.album_artist.clone()),
+        ("Album", metadata.album.clone()),
+        ("Track Number", number(metadata.track, metadata.track_total)),
+        ("Disc Number", number(metadata.disc, metadata.disc_total)),
+        ("Date", metadata.date.map(|date| date.to_string())),
+        ("Original Date", metadata.original_date.map(|date| date.to_string())),
+        ("Genre", metadata.genre()),
+    ];
+    for (label, value) in fields {
+        if let Some(value) = value {
+            lines.push(format!("{}: {}", label, value));
         }
-        if let Some(genre) = id3.genre {
-            println!("Genre: {}", genre);
+    }
+    for comment in &metadata.comments {
+        lines.push(format!("Comment: {}", comment));
+    }
+    for picture in &metadata.pictures {
+        lines.push(format!("Picture: {}, {}, {} bytes", picture.type_name(), picture.mime_type, picture.data.len()));
+    }
+    let gain = metadata.replay_gain;
+    if let Some(track_gain) = gain.track_gain {
+        lines.push(format!("ReplayGain: track {:+.2} dB", track_gain));
+    }
+    if let Some(album_gain) = gain.album_gain {
+        lines.push(format!("ReplayGain: album {:+.2} dB", album_gain));
+    }
+    for (key, value) in &metadata.custom {
+        lines.push(format!("{}: {}", key, value));
+    }
+    lines.push(format!("Duration: {}", format_time(duration)));
+
+    // 章节列表，只有叶子节点有编号，可以用--chapter跳转
+    fn list_chapters(chapters: &[Chapter], leaves: &[&Chapter], depth: usize, lines: &mut Vec<String>) {
+        for chapter in chapters {
+            let number = leaves
+                .iter()
+                .position(|leaf| std::ptr::eq(*leaf, chapter))
+                .map_or(String::new(), |i| format!("{}. ", i + 1));
+            lines.push(format!(
+                "{}{}[{}] {}",
+                "  ".repeat(depth),
+                number,
+                format_time(chapter.start),
+                chapter.title.as_deref().unwrap_or(&chapter.id)
+            ));
+            list_chapters(&chapter.children, leaves, depth + 1, lines);
         }
-        if let Some(track_number) = id3.track_number {
-            println!("Track Number: {}", track_number);
+    }
+    if !metadata.chapters.is_empty() {
+        lines.push("Chapters:".to_string());
+        list_chapters(&metadata.chapters, &Chapter::leaves(&metadata.chapters), 1, &mut lines);
+    }
+    // 只有不同步歌词时在开始前全部输出
+    if let (false, Some(text)) = (synced_lyrics, &metadata.lyrics) {
+        lines.push("Lyrics:".to_string());
+        lines.extend(text.lines().map(str::to_string));
+    }
+    lines
+}
+
+// 加入Sink的音源：解码、ReplayGain增益、均衡器和淡入淡出
+type PlaybackSource = Fader<Equalizer<Amplify<Mp3Source>>>;
+
+// 已经加入Sink的一首曲目
+struct Track {
+    // 在播放顺序中的位置
+    position: usize,
+    duration: Duration,
+    frame_info: Arc<Mutex<FrameInfo>>,
+    chapters: Vec<Chapter>,
+    lyrics: Option<Lyrics>,
+    // CUE中的一首在文件中的开始位置，歌词的时间相对于文件开头
+    lyrics_offset: Duration,
+    current_line: Option<usize>,
+    description: Vec<String>,
+    fade: FadeHandle,
+}
+
+impl Track {
+    fn load(
+        entry: &Entry,
+        position: usize,
+        gain: GainSettings,
+        eq: &EqHandle,
+    ) -> Result<(Self, PlaybackSource), DecodeError> {
+        let path = entry.path.as_path();
+        let (mut source, tags) = Mp3Source::open(path)?;
+        let mut metadata = tags.metadata();
+        let mut lyrics_offset = Duration::ZERO;
+        if let Some(cue) = &entry.cue {
+            // 在CUE的INDEX之间播放，CUE中的字段优先于文件的标签，整个文件的章节不再适用
+            source.trim(cue.start, cue.end)?;
+            metadata = cue.metadata.clone().or(metadata);
+            metadata.chapters.clear();
+            lyrics_offset = cue.start;
         }
-    } else {
-        println!("Title: {}", args.input_file);
-        reader.seek_relative(-10).unwrap();
+        metadata.replay_gain = metadata.replay_gain.or(source.lame_gain());
+        // 同名.lrc文件优先于标签中的同步歌词
+        let lyrics = Lyrics::load_sidecar(path).or_else(|| {
+            let sylt = tags.id3v2.as_ref()?.synced_lyrics()?;
+            Some(Lyrics::from_sylt(sylt, source.frame_duration()))
+        });
+        let duration = source.duration();
+        let frame_info = source.frame_info();
+        let mut description = describe(path, &metadata, duration, lyrics.is_some());
+        if let Some(gain) = gain.gain(&metadata.replay_gain) {
+            description.push(format!("Playback Gain: {:+.2} dB", gain));
+        }
+        let source = Fader::new(Equalizer::new(source.amplify(gain.factor(&metadata.replay_gain)), eq.clone()));
+        let track = Self {
+            position,
+            duration,
+            frame_info,
+            description,
+            chapters: metadata.chapters,
+            lyrics,
+            lyrics_offset,
+            current_line: None,
+            fade: source.handle(),
+        };
+        Ok((track, source))
     }
 
-    let mut decoder = Decoder::new();
+    // 播放到新的一行歌词时返回该行
+    fn lyric_line(&mut self, position: Duration) -> Option<String> {
+        let lyrics = self.lyrics.as_ref()?;
+        let index = lyrics.index_at(position + self.lyrics_offset);
+        if index == self.current_line {
+            return None;
+        }
+        self.current_line = index;
+        let line = &lyrics.lines[index?];
+        Some(format!("[{}] {}", format_time(line.time), line.text))
+    }
+
+    // 按键跳转的目标章节：下一章节，或者当前章节的开头，刚开始播放当前章节时为上一章节
+    fn chapter_target(&self, position: Duration, forward: bool) -> Option<&Chapter> {
+        let leaves = Chapter::leaves(&self.chapters);
+        let current = leaves.iter().rposition(|leaf| leaf.start <= position);
+        let index = match (forward, current) {
+            (true, current) => current.map_or(0, |i| i + 1),
+            (false, Some(i)) if i > 0 && position - leaves[i].start < RESTART_THRESHOLD => i - 1,
+            (false, current) => current?,
+        };
+        leaves.get(index).copied()
+    }
+}
+
+// 当前曲目剩余时间少于该值时把下一首加入Sink，使曲目之间没有间隙
+const PRELOAD: Duration = Duration::from_secs(2);
+// 播放超过该时间后“上一首”回到当前曲目开头
+const RESTART_THRESHOLD: Duration = Duration::from_secs(3);
+
+// 把队列按当前顺序保存为播放列表，同时写入标题和时长
+// 列表格式无法表示CUE中的一段，CUE中的曲目在其中第一首的位置保存为CUE文件本身
+fn save_playlist(queue: &Queue, path: &Path) {
+    let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut sheets: Vec<&Path> = Vec::new();
+    let mut entries = Vec::new();
+    for track in queue.tracks() {
+        if let (Some(sheet), Some(cue)) = (&track.sheet, &track.cue) {
+            if !sheets.contains(&sheet.as_path()) {
+                sheets.push(sheet);
+                let mut entry = PlaylistEntry::new(canonical(sheet));
+                let metadata = &cue.metadata;
+                entry.title = match (&metadata.album_artist, &metadata.album) {
+                    (Some(artist), Some(album)) => Some(format!("{} - {}", artist, album)),
+                    (_, album) => album.clone(),
+                };
+                entries.push(entry);
+            }
+            continue;
+        }
+        let mut entry = PlaylistEntry::new(canonical(&track.path));
+        if let Ok((source, tags)) = Mp3Source::open(&track.path) {
+            let metadata = tags.metadata();
+            entry.title = match (metadata.artist(), metadata.title) {
+                (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+                (_, title) => title,
+            };
+            entry.duration = Some(source.duration());
+        }
+        entries.push(entry);
+    }
+    match (Playlist { entries }).save(path) {
+        Ok(()) => println!("Output: {}", path.display()),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+// 曲目之间的交叉淡化和停止时的淡出，crossfade为None时曲目之间无缝衔接
+#[derive(Clone, Copy)]
+struct Fades {
+    crossfade: Option<Duration>,
+    stop: Duration,
+    curve: FadeCurve,
+}
+
+// 均衡器预设和交互模式下正在调整的图示均衡频段，修改通过handle作用于正在播放的所有音源
+struct EqControl {
+    presets: Vec<Preset>,
+    preset: usize,
+    band: usize,
+    handle: EqHandle,
+}
 
-    // 计算时长
-    let duration = decoder.calculate_mp3_duration(&mut reader).unwrap();
-    let second = duration.as_secs();
-    let minute = second / 60;
-    let hour = minute / 60;
-    let minute = minute % 60;
-    let second = second % 60;
-    print!("Duration: ");
-    for i in [hour, minute] {
-        if i != 0 {
-            print!("{:02}:", i)
+impl EqControl {
+    fn next_preset(&mut self) -> String {
+        self.preset = (self.preset + 1) % self.presets.len();
+        let preset = &self.presets[self.preset];
+        self.handle.update(|settings| *settings = preset.settings.clone());
+        format!("EQ: {}", preset.name)
+    }
+
+    fn select_band(&mut self, band: usize) -> String {
+        self.band = band;
+        self.band_text()
+    }
+
+    // 图示均衡每段的增益限制在±12dB
+    fn band_gain(&mut self, delta: f64) -> String {
+        let band = self.band;
+        self.handle.update(|settings| settings.graphic[band] = (settings.graphic[band] + delta).clamp(-12.0, 12.0));
+        self.band_text()
+    }
+
+    fn band_text(&self) -> String {
+        let gain = self.handle.settings().graphic[self.band];
+        format!("EQ band: {} Hz {:+.1} dB", GRAPHIC_FREQUENCIES[self.band], gain)
+    }
+}
+
+struct PlayOptions {
+    chapter: Option<usize>,
+    shuffle: bool,
+    repeat: Repeat,
+    save: Option<String>,
+    fades: Fades,
+    gain: GainSettings,
+    eq: EqControl,
+}
+
+// 交叉淡化时两个Sink同时播放，暂停和音量对两者同时生效
+fn sinks<'a>(sink: &'a Sink, next_sink: &'a Option<Sink>) -> impl Iterator<Item = &'a Sink> {
+    std::iter::once(sink).chain(next_sink)
+}
+
+fn play(input_files: &[String], options: PlayOptions) {
+    let PlayOptions { mut chapter, shuffle, repeat, save, fades, gain, mut eq } = options;
+    // 播放列表展开为其中的条目
+    let mut paths = Vec::new();
+    for input in input_files {
+        let path = PathBuf::from(input);
+        if PlaylistFormat::from_path(&path).is_none() {
+            paths.push(path);
+            continue;
+        }
+        match Playlist::load(&path) {
+            Ok(playlist) => paths.extend(playlist.entries.into_iter().map(|entry| entry.path)),
+            Err(e) => eprintln!("{}: {}", input, e),
         }
     }
-    println!("{:02}", second);
+    let files = collect_files(&paths).expect("读取目录失败！");
+    let entries = expand_cue_sheets(files, |path, e| eprintln!("{}: {}", path.display(), e));
+    let mut queue = Queue::new(entries);
+    if queue.is_empty() {
+        eprintln!("没有找到MP3文件");
+        return;
+    }
+    queue.repeat = repeat;
+    queue.set_shuffle(shuffle);
+    if let Some(save) = save {
+        save_playlist(&queue, Path::new(&save));
+    }
 
     let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-    let sink = Sink::try_new(&stream_handle).unwrap();
+    let mut sink = Sink::try_new(&stream_handle).unwrap();
+    // 交叉淡化时下一首在另一个Sink中与当前曲目重叠播放
+    let mut next_sink: Option<Sink> = None;
 
-    // 解码
-    'outer: loop {
-        if reader.stream_position().unwrap() >= decoder.data_end && decoder.data_end != 0 {
-            break;
+    // 标准输入是终端时进入交互模式，raw模式下换行需要同时输出\r
+    let raw_mode = if std::io::stdin().is_terminal() { RawMode::enable().ok() } else { None };
+    if raw_mode.is_some() {
+        print!("{}\r\n", terminal::HELP);
+    }
+    let mut status = StatusLine::new();
+    // 静音前的音量
+    let mut muted: Option<f32> = None;
+    // 正在播放的曲目，以及已经排在它后面的下一首
+    let mut current: Option<Track> = None;
+    let mut preloaded: Option<Track> = None;
+    // 连续无法打开的曲目数，全部失败时停止
+    let mut failures = 0;
+    // 交叉淡化中按下跳转类按键时立即切换到下一首，按键在切换之后处理
+    let mut end_crossfade = false;
+    let mut deferred: Option<Action> = None;
+    // 重新载入当前曲目时恢复的播放位置和暂停状态
+    let mut reload: Option<(Duration, bool)> = None;
+
+    loop {
+        if current.is_none() {
+            sink.clear();
+            next_sink = None;
+            preloaded = None;
+            let resume = reload.take();
+            let position = queue.position();
+            match Track::load(queue.track(position), position, gain, &eq.handle) {
+                Ok((track, source)) => {
+                    failures = 0;
+                    if resume.is_none() {
+                        if queue.len() > 1 {
+                            status.print_line(&format!("Track {}/{}: {}", position + 1, queue.len(), queue.track(position)));
+                        }
+                        for line in &track.description {
+                            status.print_line(line);
+                        }
+                    }
+                    // 暂停状态下跳转，之后sink.get_pos()即为在文件中的位置
+                    sink.append(source);
+                    if let Some((position, _)) = resume {
+                        if let Err(e) = sink.try_seek(position) {
+                            dbg_println!(DebugType::Decoder, "跳转失败: {:?}", e);
+                        }
+                    }
+                    if let Some(n) = chapter.take() {
+                        let leaves = Chapter::leaves(&track.chapters);
+                        match n.checked_sub(1).and_then(|i| leaves.get(i)) {
+                            Some(leaf) => {
+                                status.print_line(&format!("Chapter: {}", leaf.title.as_deref().unwrap_or(&leaf.id)));
+                                if let Err(e) = sink.try_seek(leaf.start) {
+                                    dbg_println!(DebugType::Decoder, "跳转失败: {:?}", e);
+                                }
+                            }
+                            None => status.print_line(&format!("没有第{}个章节，从头开始播放", n)),
+                        }
+                    }
+                    if !resume.is_some_and(|(_, paused)| paused) {
+                        sink.play();
+                    }
+                    current = Some(track);
+                }
+                Err(e) => {
+                    status.print_line(&format!("无法播放{}: {}", queue.track(position), e));
+                    failures += 1;
+                    match queue.next_position(true) {
+                        Some(next) if failures < queue.len() => queue.set_position(next),
+                        _ => break,
+                    }
+                    continue;
+                }
+            }
         }
-        let pcm_data = match decoder.decode_mp3(&mut reader) {
-            Ok(pcm_data) => pcm_data,
-            Err(e) => {
-                match e {
-                    DecodeError::EndOfFile => {
-                        dbg_println!(DebugType::Decoder, "到达文件末尾，解码完成");
-                        break 'outer;
-                    },
-                    _ => {
-                        dbg_println!(DebugType::Decoder, "解码错误: {:?}", e);
-                        break 'outer;
+        let track = current.as_mut().unwrap();
+        let position = sink.get_pos();
+
+        // 剩余时间不多时准备下一首：交叉淡化时在另一个Sink中淡入，否则排在当前曲目之后无缝衔接
+        let remaining = track.duration.saturating_sub(position);
+        if preloaded.is_none() && sink.len() == 1 && remaining < fades.crossfade.unwrap_or(PRELOAD) {
+            if let Some(next) = queue.next_position(false) {
+                if let Ok((next_track, source)) = Track::load(queue.track(next), next, gain, &eq.handle) {
+                    if fades.crossfade.is_some() {
+                        track.fade.fade_out(remaining, fades.curve);
+                        next_track.fade.fade_in(remaining, fades.curve);
+                        let fading_in = Sink::try_new(&stream_handle).unwrap();
+                        fading_in.set_volume(sink.volume());
+                        if sink.is_paused() {
+                            fading_in.pause();
+                        }
+                        fading_in.append(source);
+                        next_sink = Some(fading_in);
+                    } else {
+                        sink.append(source);
                     }
+                    preloaded = Some(next_track);
                 }
-            },
+            }
+        }
+        // 当前曲目已经播放完
+        if sink.empty() || end_crossfade || (preloaded.is_some() && next_sink.is_none() && sink.len() == 1) {
+            end_crossfade = false;
+            if let Some(fading_in) = next_sink.take() {
+                sink = fading_in;
+            }
+            match preloaded.take() {
+                Some(next_track) => {
+                    queue.set_position(next_track.position);
+                    if queue.len() > 1 {
+                        status.print_line(&format!("Track {}/{}: {}", next_track.position + 1, queue.len(), queue.track(next_track.position)));
+                    }
+                    for line in &next_track.description {
+                        status.print_line(line);
+                    }
+                    current = Some(next_track);
+                }
+                None => match queue.next_position(false) {
+                    Some(next) => {
+                        queue.set_position(next);
+                        current = None;
+                    }
+                    None => break,
+                },
+            }
+            continue;
+        }
+
+        if let Some(line) = track.lyric_line(position) {
+            status.print_line(&line);
+        }
+        let volume = if muted.is_some() { None } else { Some(sink.volume()) };
+        let info = *track.frame_info.lock().unwrap();
+        let mut text = status_text(position, track.duration, &info, sink.is_paused(), volume);
+        if queue.len() > 1 {
+            text = format!("[{}/{}] {}", queue.position() + 1, queue.len(), text);
+        }
+        status.update(text);
+
+        let action = match (deferred.take(), &raw_mode) {
+            (Some(action), _) => Some(action),
+            (None, Some(_)) => poll_action(Duration::from_millis(50)).unwrap_or(None),
+            (None, None) => {
+                std::thread::sleep(Duration::from_millis(50));
+                None
+            }
         };
+        let seeking = matches!(
+            action,
+            Some(Action::Seek(_) | Action::Next | Action::Previous | Action::NextChapter | Action::PreviousChapter)
+        );
+        if next_sink.is_some() && seeking {
+            end_crossfade = true;
+            if action != Some(Action::Next) {
+                deferred = action;
+            }
+            continue;
+        }
+        match action {
+            Some(Action::TogglePause) => {
+                let paused = sink.is_paused();
+                for sink in sinks(&sink, &next_sink) {
+                    if paused {
+                        sink.play();
+                    } else {
+                        sink.pause();
+                    }
+                }
+            }
+            Some(Action::Seek(seconds)) => {
+                let target = (position.as_secs_f64() + seconds as f64).max(0.0);
+                if let Err(e) = sink.try_seek(Duration::from_secs_f64(target)) {
+                    dbg_println!(DebugType::Decoder, "跳转失败: {:?}", e);
+                }
+            }
+            Some(Action::Volume(delta)) => {
+                let volume = (muted.take().unwrap_or(sink.volume()) + delta).clamp(0.0, 2.0);
+                sinks(&sink, &next_sink).for_each(|sink| sink.set_volume(volume));
+            }
+            Some(Action::ToggleMute) => match muted.take() {
+                Some(volume) => sinks(&sink, &next_sink).for_each(|sink| sink.set_volume(volume)),
+                None => {
+                    muted = Some(sink.volume());
+                    sinks(&sink, &next_sink).for_each(|sink| sink.set_volume(0.0));
+                }
+            },
+            Some(Action::Next) => match queue.next_position(true) {
+                Some(next) => {
+                    queue.set_position(next);
+                    current = None;
+                }
+                None => break,
+            },
+            Some(Action::Previous) => match queue.previous_position() {
+                Some(previous) if position < RESTART_THRESHOLD => {
+                    queue.set_position(previous);
+                    current = None;
+                }
+                _ => {
+                    let _ = sink.try_seek(Duration::ZERO);
+                }
+            },
+            Some(action @ (Action::NextChapter | Action::PreviousChapter)) => {
+                if let Some(chapter) = track.chapter_target(position, action == Action::NextChapter) {
+                    status.print_line(&format!("Chapter: {}", chapter.title.as_deref().unwrap_or(&chapter.id)));
+                    if let Err(e) = sink.try_seek(chapter.start) {
+                        dbg_println!(DebugType::Decoder, "跳转失败: {:?}", e);
+                    }
+                }
+            }
+            Some(action @ (Action::ToggleShuffle | Action::CycleRepeat)) => {
+                if action == Action::ToggleShuffle {
+                    queue.set_shuffle(!queue.shuffle());
+                    status.print_line(&format!("Shuffle: {}", if queue.shuffle() { "on" } else { "off" }));
+                } else {
+                    queue.repeat = queue.repeat.cycle();
+                    status.print_line(&format!("Repeat: {}", queue.repeat.name()));
+                }
+                // 已经排在后面的下一首按旧的顺序选出，无法从Sink中移除，在原位置重新载入当前曲目
+                if preloaded.is_some() {
+                    reload = Some((position, sink.is_paused()));
+                    current = None;
+                }
+            }
+            Some(Action::NextPreset) => status.print_line(&eq.next_preset()),
+            Some(Action::SelectBand(band)) => status.print_line(&eq.select_band(band)),
+            Some(Action::BandGain(delta)) => status.print_line(&eq.band_gain(delta)),
+            Some(Action::Quit) => {
+                // 停止前淡出，排在后面无缝衔接的下一首也要保持静音
+                if !sink.is_paused() && !fades.stop.is_zero() {
+                    track.fade.fade_out(fades.stop, fades.curve);
+                    if let Some(next_track) = &preloaded {
+                        let duration = if next_sink.is_some() { fades.stop } else { Duration::ZERO };
+                        next_track.fade.fade_out(duration, fades.curve);
+                    }
+                    std::thread::sleep(fades.stop);
+                }
+                break;
+            }
+            None => {}
+        }
+    }
+    status.finish();
+}
+
+fn main() {
+    let args = Args::parse();
+    
+    // 初始化 DEBUG_CONFIG
+    DebugConfig::init(&args.debug);
 
-        let samples: Vec<i16> = pcm_data.iter()
-            .map(|&sample| pcm_f32_to_int16(sample))
+    match args.command {
+        Some(Command::Decode { input_file, output, format }) => {
+            decode(&input_file, output, format);
+        }
+        Some(Command::Cut { input_file, output, start, end }) => {
+            cut(&input_file, &output, start, end);
+        }
+        Some(Command::Validate { input_file, repair, output }) => {
+            check(&input_file, repair, output);
+        }
+        Some(Command::Join { input_files, output }) => {
+            join(&input_files, &output);
+        }
+        Some(Command::Cover { input_file, output, all }) => {
+            cover(&input_file, output, all);
+        }
+        Some(Command::Tag { input_file, title, artist, album, track, genre, cover, clear, version, encoding }) => {
+            let values = [
+                (TagField::Title, title),
+                (TagField::Artist, artist),
+                (TagField::Album, album),
+                (TagField::Track, track),
+                (TagField::Genre, genre),
+            ]
+            .into_iter()
+            .filter_map(|(field, value)| Some((field, value?)))
             .collect();
-        let source = rodio::buffer::SamplesBuffer::new(
-            decoder.channel_num as u16, 
-            decoder.sample_rate as u32, 
-            samples
-        );
-        sink.append(source);
+            edit_tag(&input_file, TagEdit { values, cover, clear, version, encoding });
+        }
+        Some(Command::Loudness { input_files, write }) => {
+            loudness(&input_files, write);
+        }
+        None => {
+            let fades = Fades {
+                crossfade: Some(args.crossfade).filter(|d| !d.is_zero()),
+                stop: args.fade_out,
+                curve: args.fade_curve,
+            };
+            let gain = GainSettings {
+                mode: args.replay_gain,
+                preamp: args.preamp,
+            };
+            let presets = match &args.eq_presets {
+                Some(path) => match Preset::load(Path::new(path)) {
+                    Ok(presets) => presets,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                },
+                None => Preset::builtin(),
+            };
+            let Some(preset) = presets.iter().position(|preset| preset.name == args.eq) else {
+                eprintln!("没有名为{}的均衡器预设", args.eq);
+                return;
+            };
+            let eq = EqControl {
+                handle: EqHandle::new(presets[preset].settings.clone()),
+                presets,
+                preset,
+                band: 0,
+            };
+            let options = PlayOptions {
+                chapter: args.chapter,
+                shuffle: args.shuffle,
+                repeat: args.repeat,
+                save: args.save_playlist,
+                fades,
+                gain,
+                eq,
+            };
+            play(&args.input_files, options);
+        }
     }
-    dbg_println!(DebugType::Decoder, "音频解码完成");
-    sink.sleep_until_end();
 }
@@ -0,0 +1,352 @@
+use crate::{
+    ape::ApeTag,
+    id3::{chapters::Chapter, date::Date, frames::{ID3v2Frame, Picture}, lyrics3::Lyrics3, v1::Id3v1, Id3v2},
+    mpeg_frame::xing::LameTag,
+    tags::FileTags,
+};
+
+// ReplayGain增益（dB）和峰值（满幅为1.0）
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            track_gain: self.track_gain.or(other.track_gain),
+            track_peak: self.track_peak.or(other.track_peak),
+            album_gain: self.album_gain.or(other.album_gain),
+            album_peak: self.album_peak.or(other.album_peak),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // LAME标签中的RG字段：3位名称（1为单曲，2为专辑，0为未设置）、3位来源、1位符号和9位以0.1dB为单位的增益
+    pub fn from_lame(tag: &LameTag) -> Self {
+        let gain = |field: u16, name: u16| {
+            if field >> 13 != name {
+                return None;
+            }
+            let value = (field & 0x1ff) as f32 / 10.0;
+            Some(if field & 0x200 != 0 { -value } else { value })
+        };
+        Self {
+            track_gain: gain(tag.radio_gain, 1),
+            track_peak: Some(tag.peak).filter(|peak| *peak > 0.0),
+            album_gain: gain(tag.audiophile_gain, 2),
+            album_peak: None,
+        }
+    }
+
+    // 按"REPLAYGAIN_TRACK_GAIN"等键取值，gain形如"-6.50 dB"
+    pub(crate) fn from_fields(get: impl Fn(&str) -> Option<String>) -> Self {
+        let number = |key: &str| {
+            let value = get(key)?;
+            value.trim().trim_end_matches("dB").trim_end_matches("db").trim().parse().ok()
+        };
+        Self {
+            track_gain: number("REPLAYGAIN_TRACK_GAIN"),
+            track_peak: number("REPLAYGAIN_TRACK_PEAK"),
+            album_gain: number("REPLAYGAIN_ALBUM_GAIN"),
+            album_peak: number("REPLAYGAIN_ALBUM_PEAK"),
+        }
+    }
+}
+
+// 与标签格式无关的元数据
+// 由FileTags合并时每个字段按 ID3v2 > APEv2 > Lyrics3 > ID3v1 的顺序取第一个有值的来源，
+// 列表字段（艺术家、流派等）整体取自同一个来源，自定义字段则合并所有来源中不重复的键
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc: Option<u32>,
+    pub disc_total: Option<u32>,
+    // 录音时间和原始发行时间
+    pub date: Option<Date>,
+    pub original_date: Option<Date>,
+    pub genres: Vec<String>,
+    pub comments: Vec<String>,
+    pub lyrics: Option<String>,
+    pub pictures: Vec<Picture>,
+    pub replay_gain: ReplayGain,
+    // CHAP/CTOC组成的章节树，只有ID3v2中有
+    pub chapters: Vec<Chapter>,
+    // 没有对应字段的键值，如TXXX和APEv2中的其他项目
+    pub custom: Vec<(String, String)>,
+}
+
+// 解析"3"或"3/12"形式的编号和总数
+fn number_and_total(value: &str) -> (Option<u32>, Option<u32>) {
+    let (number, total) = value.split_once('/').unwrap_or((value, ""));
+    (number.trim().parse().ok(), total.trim().parse().ok())
+}
+
+fn is_replay_gain(key: &str) -> bool {
+    key.get(..11).is_some_and(|p| p.eq_ignore_ascii_case("REPLAYGAIN_"))
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|v| !v.trim().is_empty())
+}
+
+impl Metadata {
+    pub fn from_id3v2(tag: &Id3v2) -> Self {
+        let text = |id: &str| non_empty(tag.text(id).map(|values| values.join("/")));
+        let list = |id: &str| -> Vec<String> {
+            tag.text(id)
+                .unwrap_or_default()
+                .iter()
+                .filter(|v| !v.is_empty())
+                .cloned()
+                .collect()
+        };
+        let (track, track_total) = text("TRCK").map_or((None, None), |v| number_and_total(&v));
+        let (disc, disc_total) = text("TPOS").map_or((None, None), |v| number_and_total(&v));
+
+        let frames = tag.frames();
+        // 带描述的注释通常是iTunNORM等程序数据
+        let comments = frames
+            .get("COMM")
+            .into_iter()
+            .flatten()
+            .filter_map(|frame| match frame {
+                ID3v2Frame::Comment { description, text, .. } if !description.starts_with("iTun") && !text.is_empty() => {
+                    Some(text.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        let custom: Vec<(String, String)> = frames
+            .get("TXXX")
+            .into_iter()
+            .flatten()
+            .filter_map(|frame| match frame {
+                ID3v2Frame::UserText { description, values } if !is_replay_gain(description) => {
+                    Some((description.clone(), values.join("/")))
+                }
+                _ => None,
+            })
+            .collect();
+        let replay_gain = ReplayGain::from_fields(|key| tag.user_text(key).map(|v| v.join("/")));
+
+        Self {
+            title: text("TIT2"),
+            artists: list("TPE1"),
+            album_artist: text("TPE2"),
+            album: text("TALB"),
+            track,
+            track_total,
+            disc,
+            disc_total,
+            date: tag.recording_date(),
+            original_date: tag.original_date(),
+            genres: tag.genres(),
+            comments,
+            lyrics: non_empty(tag.lyrics().map(str::to_string)),
+            pictures: tag.pictures().into_iter().cloned().collect(),
+            replay_gain,
+            chapters: tag.chapters(),
+            custom,
+        }
+    }
+
+    pub fn from_ape(tag: &ApeTag) -> Self {
+        let text = |key: &str| non_empty(tag.text(key).map(|values| values.join("/")));
+        let list = |key: &str| tag.text(key).map(<[String]>::to_vec).unwrap_or_default();
+        let (track, track_total) = text("Track").map_or((None, None), |v| number_and_total(&v));
+        let (disc, disc_total) = text("Disc").map_or((None, None), |v| number_and_total(&v));
+
+        // APEv2中的封面为"Cover Art (Front)"等二进制项目
+        let pictures = [("Cover Art (Front)", 3), ("Cover Art (Back)", 4)]
+            .into_iter()
+            .filter_map(|(key, picture_type)| {
+                let (name, data) = tag.binary(key)?;
+                let extension = name.rsplit('.').next().unwrap_or_default().to_ascii_lowercase();
+                Some(Picture {
+                    mime_type: match extension.as_str() {
+                        "jpg" | "jpeg" => "image/jpeg".to_string(),
+                        "png" => "image/png".to_string(),
+                        _ => format!("image/{}", extension),
+                    },
+                    picture_type,
+                    description: String::new(),
+                    data: data.to_vec(),
+                })
+            })
+            .collect();
+
+        const KNOWN: [&str; 12] = [
+            "Title", "Artist", "Album Artist", "Album", "Track", "Disc", "Year", "Genre", "Comment", "Lyrics", "Cover Art",
+            "REPLAYGAIN_",
+        ];
+        let custom = tag
+            .items
+            .iter()
+            .filter(|item| {
+                !KNOWN.iter().any(|k| item.key.get(..k.len()).is_some_and(|p| p.eq_ignore_ascii_case(k)))
+            })
+            .filter_map(|item| Some((item.key.clone(), tag.text(&item.key)?.join("/"))))
+            .collect();
+
+        Self {
+            title: text("Title"),
+            artists: list("Artist"),
+            album_artist: text("Album Artist"),
+            album: text("Album"),
+            track,
+            track_total,
+            disc,
+            disc_total,
+            date: text("Year").as_deref().and_then(Date::parse),
+            original_date: None,
+            genres: list("Genre"),
+            comments: list("Comment"),
+            lyrics: text("Lyrics"),
+            pictures,
+            replay_gain: ReplayGain::from_fields(text),
+            chapters: Vec::new(),
+            custom,
+        }
+    }
+
+    pub fn from_lyrics3(tag: &Lyrics3) -> Self {
+        let text = |value: Option<&str>| non_empty(value.map(str::to_string));
+        Self {
+            title: text(tag.title()),
+            artists: text(tag.artist()).into_iter().collect(),
+            album: text(tag.album()),
+            lyrics: text(tag.lyrics()),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_id3v1(tag: &Id3v1) -> Self {
+        Self {
+            title: tag.title.clone(),
+            artists: tag.artist.clone().into_iter().collect(),
+            album: tag.album.clone(),
+            track: tag.track.map(u32::from),
+            date: tag.year.as_deref().and_then(Date::parse),
+            genres: tag.genre_name().into_iter().collect(),
+            comments: tag.comment.clone().into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    // 用other补充self中没有的字段
+    pub fn or(mut self, other: Self) -> Self {
+        fn fill<T>(field: &mut Vec<T>, other: Vec<T>) {
+            if field.is_empty() {
+                *field = other;
+            }
+        }
+        self.title = self.title.or(other.title);
+        fill(&mut self.artists, other.artists);
+        self.album_artist = self.album_artist.or(other.album_artist);
+        self.album = self.album.or(other.album);
+        if self.track.is_none() {
+            (self.track, self.track_total) = (other.track, other.track_total);
+        }
+        if self.disc.is_none() {
+            (self.disc, self.disc_total) = (other.disc, other.disc_total);
+        }
+        self.date = self.date.or(other.date);
+        self.original_date = self.original_date.or(other.original_date);
+        fill(&mut self.genres, other.genres);
+        fill(&mut self.comments, other.comments);
+        self.lyrics = self.lyrics.or(other.lyrics);
+        fill(&mut self.pictures, other.pictures);
+        self.replay_gain = self.replay_gain.or(other.replay_gain);
+        fill(&mut self.chapters, other.chapters);
+        for (key, value) in other.custom {
+            if !self.custom.iter().any(|(k, _)| k.eq_ignore_ascii_case(&key)) {
+                self.custom.push((key, value));
+            }
+        }
+        self
+    }
+
+    pub fn from_tags(tags: &FileTags) -> Self {
+        let sources = [
+            tags.id3v2.as_ref().map(Self::from_id3v2),
+            tags.ape.as_ref().map(Self::from_ape),
+            tags.lyrics3.as_ref().map(Self::from_lyrics3),
+            tags.id3v1.as_ref().map(Self::from_id3v1),
+        ];
+        sources.into_iter().flatten().fold(Self::default(), Self::or)
+    }
+
+    // 多个艺术家以"; "连接
+    pub fn artist(&self) -> Option<String> {
+        (!self.artists.is_empty()).then(|| self.artists.join("; "))
+    }
+
+    pub fn genre(&self) -> Option<String> {
+        (!self.genres.is_empty()).then(|| self.genres.join("; "))
+    }
+
+    // 封面图片，没有封面类型时取第一张
+    pub fn cover(&self) -> Option<&Picture> {
+        self.pictures.iter().find(|p| p.picture_type == 3).or(self.pictures.first())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ape::{ApeItem, ApeValue};
+
+    #[test]
+    fn precedence() {
+        let mut id3v2 = Id3v2::default();
+        id3v2.set_text("TIT2", "From ID3v2");
+        id3v2.set_text("TRCK", "3/12");
+        let text = |key: &str, value: &str| ApeItem {
+            key: key.to_string(),
+            value: ApeValue::Text(vec![value.to_string()]),
+        };
+        let ape = ApeTag {
+            version: 2000,
+            items: vec![
+                text("Title", "From APE"),
+                text("Artist", "Someone"),
+                text("REPLAYGAIN_TRACK_GAIN", "-7.25 dB"),
+                text("Catalog", "ABC-1"),
+            ],
+        };
+        let mut v1 = [0u8; 128];
+        v1[..3].copy_from_slice(b"TAG");
+        v1[3..5].copy_from_slice(b"v1");
+        v1[63..68].copy_from_slice(b"Album");
+        v1[127] = 8;
+
+        let tags = FileTags {
+            id3v2: Some(id3v2),
+            id3v1: Id3v1::parse(&v1, None),
+            ape: Some(ape),
+            lyrics3: None,
+            audio_start: 0,
+            audio_end: 0,
+        };
+        let metadata = Metadata::from_tags(&tags);
+        assert_eq!(metadata.title.as_deref(), Some("From ID3v2"));
+        assert_eq!((metadata.track, metadata.track_total), (Some(3), Some(12)));
+        assert_eq!(metadata.artists, ["Someone"]);
+        assert_eq!(metadata.album.as_deref(), Some("Album"));
+        assert_eq!(metadata.genres, ["Jazz"]);
+        assert_eq!(metadata.replay_gain.track_gain, Some(-7.25));
+        assert_eq!(metadata.custom, [("Catalog".to_string(), "ABC-1".to_string())]);
+    }
+}
@@ -1,5 +1,3 @@
-use std::{fs::File, io::BufReader};
-
 use types::{
     Channel, MpegBitRate, MpegChannelMode, MpegCopyright, MpegLayer, MpegModeExtension, MpegOringinal, MpegProtection, MpegSampleRate, MpegSideInfo, MpegVersion, ScaleFactor
 };
@@ -9,6 +7,7 @@ use crate::dbg_println;
 use crate::debug::DebugType;
 
 pub mod types;
+pub mod xing;
 
 const SLEN: [[usize; 2]; 16] = [
     [0, 0],
@@ -42,6 +41,76 @@ pub struct MpegHeader {
     pub original: MpegOringinal,
 }
 
+impl MpegHeader {
+    // 每帧的样本数，MPEG-2/2.5的Layer III每帧只有一个granule
+    pub fn samples_per_frame(&self) -> usize {
+        match (self.layer, self.version) {
+            (MpegLayer::Layer1, _) => 384,
+            (MpegLayer::Layer3, MpegVersion::V2 | MpegVersion::V2_5) => 576,
+            _ => 1152,
+        }
+    }
+
+    // 整帧的字节数，包括帧头，比特率或采样率无效时返回0
+    pub fn frame_size(&self) -> usize {
+        let bit_rate = self.bit_rate.get_rate();
+        let sample_rate = self.sample_rate.get_rate();
+        if bit_rate == 0 || sample_rate == 0 {
+            return 0;
+        }
+        let padding = if self.padding { 1 } else { 0 };
+        if self.layer == MpegLayer::Layer1 {
+            (12 * bit_rate * 1000 / sample_rate + padding) * 4
+        } else {
+            self.samples_per_frame() / 8 * bit_rate * 1000 / sample_rate + padding
+        }
+    }
+
+    pub fn side_info_size(&self) -> usize {
+        match (self.version, self.channel) {
+            (MpegVersion::V1, MpegChannelMode::SingleChannel) => 17,
+            (MpegVersion::V1, _) => 32,
+            (_, MpegChannelMode::SingleChannel) => 9,
+            _ => 17,
+        }
+    }
+
+    // 帧头（含CRC）之后的side info起始位置
+    pub fn side_info_offset(&self) -> usize {
+        if self.protection == MpegProtection::Protected {
+            6
+        } else {
+            4
+        }
+    }
+}
+
+// 尝试把data[pos..]解析为完整的一个Layer III帧，返回帧头和帧长
+pub fn frame_at(data: &[u8], pos: usize) -> Option<(MpegHeader, usize)> {
+    let bytes: [u8; 4] = data.get(pos..pos + 4)?.try_into().unwrap();
+    if bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 || (bytes[1] >> 3) & 0x03 == 0b01 {
+        return None;
+    }
+    let header = parse_header(&bytes).ok()?;
+    let size = header.frame_size();
+    if header.layer != MpegLayer::Layer3 || size == 0 || pos + size > data.len() {
+        return None;
+    }
+    Some((header, size))
+}
+
+// 帧的CRC校验值，覆盖帧头后两个字节和side info，多项式为0x8005
+pub fn frame_crc(frame: &[u8], side_info_size: usize) -> u16 {
+    let mut crc = 0xffffu16;
+    for &byte in frame[2..4].iter().chain(frame[6..6 + side_info_size].iter()) {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
 pub fn parse_header(header: &[u8; 4]) -> Result<MpegHeader, DecodeError> {
     if header[0] != 0xff || (header[1] & 0xe0) != 0xe0 {
         return Err(DecodeError::CanNotFindFrameSync);
@@ -119,7 +188,7 @@ pub fn parse_header(header: &[u8; 4]) -> Result<MpegHeader, DecodeError> {
     })
 }
 
-pub fn parse_side_info(header: &MpegHeader, bs: &mut BitStream<BufReader<File>>) -> MpegSideInfo {
+pub fn parse_side_info<R: BitReader>(header: &MpegHeader, bs: &mut BitStream<R>) -> MpegSideInfo {
     let mut side_info: MpegSideInfo = MpegSideInfo::new();
 
     side_info.main_data_end = bs.read(9).unwrap();
@@ -0,0 +1,226 @@
+use super::MpegHeader;
+
+const FLAG_FRAMES: u32 = 0x01;
+const FLAG_BYTES: u32 = 0x02;
+const FLAG_TOC: u32 = 0x04;
+const FLAG_QUALITY: u32 = 0x08;
+const LAME_TAG_SIZE: usize = 36;
+
+// LAME在Xing头之后追加的扩展信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct LameTag {
+    pub encoder: [u8; 9],
+    pub revision: u8,
+    pub vbr_method: u8,
+    pub lowpass: u8,
+    pub peak: f32,
+    pub radio_gain: u16,
+    pub audiophile_gain: u16,
+    pub encoding_flags: u8,
+    pub bit_rate: u8,
+    // 编码器在开头加入的延迟和结尾补充的样本数，不含解码端的529个样本
+    pub delay: u16,
+    pub padding: u16,
+    pub misc: u8,
+    pub mp3_gain: u8,
+    pub preset: u16,
+    // 从Xing帧开头到最后一帧结尾的字节数
+    pub music_length: u32,
+    pub music_crc: u16,
+    // 读取到的标签CRC，写入时重新计算
+    pub tag_crc: u16,
+}
+
+impl LameTag {
+    pub fn new(delay: u16, padding: u16) -> Self {
+        let mut encoder = [b' '; 9];
+        encoder[..4].copy_from_slice(b"tmp3");
+        Self {
+            encoder,
+            revision: 0,
+            vbr_method: 0,
+            lowpass: 0,
+            peak: 0.0,
+            radio_gain: 0,
+            audiophile_gain: 0,
+            encoding_flags: 0,
+            bit_rate: 0,
+            delay,
+            padding,
+            misc: 0,
+            mp3_gain: 0,
+            preset: 0,
+            music_length: 0,
+            music_crc: 0,
+            tag_crc: 0,
+        }
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < LAME_TAG_SIZE || !data[..4].iter().all(|b| b.is_ascii_alphanumeric()) {
+            return None;
+        }
+        let mut encoder = [0u8; 9];
+        encoder.copy_from_slice(&data[..9]);
+        let peak = u32::from_be_bytes(data[11..15].try_into().unwrap());
+        Some(Self {
+            encoder,
+            revision: data[9] >> 4,
+            vbr_method: data[9] & 0x0f,
+            lowpass: data[10],
+            peak: peak as f32 / (1 << 23) as f32,
+            radio_gain: u16::from_be_bytes([data[15], data[16]]),
+            audiophile_gain: u16::from_be_bytes([data[17], data[18]]),
+            encoding_flags: data[19],
+            bit_rate: data[20],
+            delay: (data[21] as u16) << 4 | (data[22] as u16) >> 4,
+            padding: (data[22] as u16 & 0x0f) << 8 | data[23] as u16,
+            misc: data[24],
+            mp3_gain: data[25],
+            preset: u16::from_be_bytes([data[26], data[27]]),
+            music_length: u32::from_be_bytes(data[28..32].try_into().unwrap()),
+            music_crc: u16::from_be_bytes([data[32], data[33]]),
+            tag_crc: u16::from_be_bytes([data[34], data[35]]),
+        })
+    }
+
+    // 写入除最后的标签CRC之外的34个字节
+    fn write(&self, data: &mut [u8]) {
+        let delay = self.delay.min(0xfff);
+        let padding = self.padding.min(0xfff);
+        data[..9].copy_from_slice(&self.encoder);
+        data[9] = self.revision << 4 | self.vbr_method & 0x0f;
+        data[10] = self.lowpass;
+        data[11..15].copy_from_slice(&((self.peak * (1 << 23) as f32) as u32).to_be_bytes());
+        data[15..17].copy_from_slice(&self.radio_gain.to_be_bytes());
+        data[17..19].copy_from_slice(&self.audiophile_gain.to_be_bytes());
+        data[19] = self.encoding_flags;
+        data[20] = self.bit_rate;
+        data[21] = (delay >> 4) as u8;
+        data[22] = ((delay & 0x0f) << 4 | padding >> 8) as u8;
+        data[23] = padding as u8;
+        data[24] = self.misc;
+        data[25] = self.mp3_gain;
+        data[26..28].copy_from_slice(&self.preset.to_be_bytes());
+        data[28..32].copy_from_slice(&self.music_length.to_be_bytes());
+        data[32..34].copy_from_slice(&self.music_crc.to_be_bytes());
+    }
+}
+
+// VBR文件第一帧中的Xing头，CBR文件使用相同格式的Info头
+#[derive(Debug, Clone, PartialEq)]
+pub struct XingHeader {
+    pub vbr: bool,
+    pub frames: Option<u32>,
+    pub bytes: Option<u32>,
+    pub toc: Option<[u8; 100]>,
+    pub quality: Option<u32>,
+    pub lame: Option<LameTag>,
+}
+
+impl XingHeader {
+    // frame为包含帧头的完整一帧
+    pub fn parse(frame: &[u8], header: &MpegHeader) -> Option<Self> {
+        let mut pos = header.side_info_offset() + header.side_info_size();
+        let vbr = match frame.get(pos..pos + 4)? {
+            b"Xing" => true,
+            b"Info" => false,
+            _ => return None,
+        };
+        let flags = u32::from_be_bytes(frame.get(pos + 4..pos + 8)?.try_into().unwrap());
+        pos += 8;
+
+        let mut read_u32 = |flag: u32| -> Option<Option<u32>> {
+            if flags & flag == 0 {
+                return Some(None);
+            }
+            let value = u32::from_be_bytes(frame.get(pos..pos + 4)?.try_into().unwrap());
+            pos += 4;
+            Some(Some(value))
+        };
+        let frames = read_u32(FLAG_FRAMES)?;
+        let bytes = read_u32(FLAG_BYTES)?;
+        let toc = if flags & FLAG_TOC != 0 {
+            let toc: [u8; 100] = frame.get(pos..pos + 100)?.try_into().unwrap();
+            pos += 100;
+            Some(toc)
+        } else {
+            None
+        };
+        let quality = if flags & FLAG_QUALITY != 0 {
+            let value = u32::from_be_bytes(frame.get(pos..pos + 4)?.try_into().unwrap());
+            pos += 4;
+            Some(value)
+        } else {
+            None
+        };
+        let lame = frame.get(pos..).and_then(LameTag::parse);
+
+        Some(Self {
+            vbr,
+            frames,
+            bytes,
+            toc,
+            quality,
+            lame,
+        })
+    }
+
+    // 写入后所需的帧长度
+    pub fn size(&self, header: &MpegHeader) -> usize {
+        header.side_info_offset()
+            + header.side_info_size()
+            + 8
+            + if self.frames.is_some() { 4 } else { 0 }
+            + if self.bytes.is_some() { 4 } else { 0 }
+            + if self.toc.is_some() { 100 } else { 0 }
+            + if self.quality.is_some() { 4 } else { 0 }
+            + if self.lame.is_some() { LAME_TAG_SIZE } else { 0 }
+    }
+
+    // 写入frame中帧头和side info之后的位置，frame长度需不小于size()
+    pub fn write(&self, frame: &mut [u8], header: &MpegHeader) {
+        let mut pos = header.side_info_offset() + header.side_info_size();
+        frame[pos..pos + 4].copy_from_slice(if self.vbr { b"Xing" } else { b"Info" });
+        let flags = if self.frames.is_some() { FLAG_FRAMES } else { 0 }
+            | if self.bytes.is_some() { FLAG_BYTES } else { 0 }
+            | if self.toc.is_some() { FLAG_TOC } else { 0 }
+            | if self.quality.is_some() { FLAG_QUALITY } else { 0 };
+        frame[pos + 4..pos + 8].copy_from_slice(&flags.to_be_bytes());
+        pos += 8;
+
+        for value in [self.frames, self.bytes].into_iter().flatten() {
+            frame[pos..pos + 4].copy_from_slice(&value.to_be_bytes());
+            pos += 4;
+        }
+        if let Some(toc) = &self.toc {
+            frame[pos..pos + 100].copy_from_slice(toc);
+            pos += 100;
+        }
+        if let Some(quality) = self.quality {
+            frame[pos..pos + 4].copy_from_slice(&quality.to_be_bytes());
+            pos += 4;
+        }
+        if let Some(lame) = &self.lame {
+            lame.write(&mut frame[pos..pos + LAME_TAG_SIZE]);
+            // 标签CRC覆盖Xing帧开头到LAME标签CRC之前的190个字节
+            let crc = lame_crc16(&frame[..pos + LAME_TAG_SIZE - 2]);
+            frame[pos + LAME_TAG_SIZE - 2..pos + LAME_TAG_SIZE].copy_from_slice(&crc.to_be_bytes());
+        }
+    }
+}
+
+// LAME标签中使用的CRC-16，多项式0x8005按位反转
+pub fn lame_crc16(data: &[u8]) -> u16 {
+    lame_crc16_update(0, data)
+}
+
+pub fn lame_crc16_update(mut crc: u16, data: &[u8]) -> u16 {
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xa001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
@@ -0,0 +1,127 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use super::{pcm_f32_to_int16, PcmSink};
+
+// AIFF的采样率使用80位扩展精度浮点数存储
+fn to_extended(value: f64) -> [u8; 10] {
+    let mut result = [0u8; 10];
+    if value <= 0.0 {
+        return result;
+    }
+    let mut exponent = 16383 + 63;
+    let mut mantissa = value;
+    while mantissa < (1u64 << 63) as f64 {
+        mantissa *= 2.0;
+        exponent -= 1;
+    }
+    while mantissa >= 2.0 * (1u64 << 63) as f64 {
+        mantissa /= 2.0;
+        exponent += 1;
+    }
+    result[0..2].copy_from_slice(&(exponent as u16).to_be_bytes());
+    result[2..10].copy_from_slice(&(mantissa as u64).to_be_bytes());
+    result
+}
+
+// AIFF文件，数据为大端序16位PCM
+pub struct AiffWriter<W: Write + Seek> {
+    writer: W,
+    channels: u16,
+    num_samples: u32,
+}
+
+impl<W: Write + Seek> AiffWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            channels: 0,
+            num_samples: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek> PcmSink for AiffWriter<W> {
+    fn begin(&mut self, sample_rate: u32, channels: u16) -> io::Result<()> {
+        self.channels = channels;
+        self.num_samples = 0;
+
+        self.writer.write_all(b"FORM")?;
+        self.writer.write_all(&0u32.to_be_bytes())?; // 文件大小，结束时回填
+        self.writer.write_all(b"AIFF")?;
+
+        self.writer.write_all(b"COMM")?;
+        self.writer.write_all(&18u32.to_be_bytes())?;
+        self.writer.write_all(&channels.to_be_bytes())?;
+        self.writer.write_all(&0u32.to_be_bytes())?; // numSampleFrames，结束时回填
+        self.writer.write_all(&16u16.to_be_bytes())?; // sampleSize
+        self.writer.write_all(&to_extended(sample_rate as f64))?;
+
+        self.writer.write_all(b"SSND")?;
+        self.writer.write_all(&0u32.to_be_bytes())?; // 块大小，结束时回填
+        self.writer.write_all(&0u32.to_be_bytes())?; // offset
+        self.writer.write_all(&0u32.to_be_bytes()) // blockSize
+    }
+
+    fn write_samples(&mut self, pcm: &[f32]) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(pcm.len() * 2);
+        for &sample in pcm {
+            buf.extend_from_slice(&pcm_f32_to_int16(sample).to_be_bytes());
+        }
+        self.num_samples += pcm.len() as u32;
+        self.writer.write_all(&buf)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let data_size = self.num_samples * 2;
+        let frames = self.num_samples / self.channels.max(1) as u32;
+        let end = self.writer.stream_position()?;
+
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(4 + 26 + 16 + data_size).to_be_bytes())?;
+        self.writer.seek(SeekFrom::Start(22))?;
+        self.writer.write_all(&frames.to_be_bytes())?;
+        self.writer.seek(SeekFrom::Start(42))?;
+        self.writer.write_all(&(8 + data_size).to_be_bytes())?;
+
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_sizes_after_finish() {
+        let mut writer = AiffWriter::new(Cursor::new(Vec::new()));
+        writer.begin(44100, 2).unwrap();
+        writer.write_samples(&[0.5, -0.5, 1.0, -1.0]).unwrap();
+        writer.finish().unwrap();
+        let data = writer.into_inner().into_inner();
+
+        assert_eq!(data.len(), 54 + 8);
+        assert_eq!(&data[0..4], b"FORM");
+        assert_eq!(data[4..8], 54u32.to_be_bytes());
+        assert_eq!(&data[8..16], b"AIFFCOMM");
+        assert_eq!(data[16..20], 18u32.to_be_bytes());
+        assert_eq!(data[20..22], 2u16.to_be_bytes());
+        assert_eq!(data[22..26], 2u32.to_be_bytes());
+        assert_eq!(data[26..28], 16u16.to_be_bytes());
+        // 44100 = 0xac44 * 2^0，指数16383+15
+        assert_eq!(data[28..38], [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&data[38..42], b"SSND");
+        assert_eq!(data[42..46], 16u32.to_be_bytes());
+        assert_eq!(data[46..54], [0; 8]);
+        // 大端序
+        assert_eq!(data[54..], [0x3f, 0xff, 0xc0, 0x01, 0x7f, 0xff, 0x80, 0x00]);
+
+        assert_eq!(to_extended(48000.0)[..4], [0x40, 0x0e, 0xbb, 0x80]);
+        assert_eq!(to_extended(0.0), [0; 10]);
+    }
+}
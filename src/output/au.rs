@@ -0,0 +1,81 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use super::{pcm_f32_to_int16, PcmSink};
+
+const AU_HEADER_SIZE: u32 = 24;
+// 16位线性PCM
+const AU_ENCODING_LINEAR_16: u32 = 3;
+
+// Sun AU文件，数据为大端序16位PCM
+pub struct AuWriter<W: Write + Seek> {
+    writer: W,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> AuWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            data_size: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek> PcmSink for AuWriter<W> {
+    fn begin(&mut self, sample_rate: u32, channels: u16) -> io::Result<()> {
+        self.data_size = 0;
+        self.writer.write_all(b".snd")?;
+        self.writer.write_all(&AU_HEADER_SIZE.to_be_bytes())?;
+        // 长度未知时规范允许填0xffffffff，结束时再回填
+        self.writer.write_all(&u32::MAX.to_be_bytes())?;
+        self.writer.write_all(&AU_ENCODING_LINEAR_16.to_be_bytes())?;
+        self.writer.write_all(&sample_rate.to_be_bytes())?;
+        self.writer.write_all(&(channels as u32).to_be_bytes())
+    }
+
+    fn write_samples(&mut self, pcm: &[f32]) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(pcm.len() * 2);
+        for &sample in pcm {
+            buf.extend_from_slice(&pcm_f32_to_int16(sample).to_be_bytes());
+        }
+        self.data_size += buf.len() as u32;
+        self.writer.write_all(&buf)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(8))?;
+        self.writer.write_all(&self.data_size.to_be_bytes())?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_sizes_after_finish() {
+        let mut writer = AuWriter::new(Cursor::new(Vec::new()));
+        writer.begin(8000, 1).unwrap();
+        writer.write_samples(&[0.5, -0.5, 1.0, -1.0]).unwrap();
+        writer.finish().unwrap();
+        let data = writer.into_inner().into_inner();
+
+        assert_eq!(data.len(), 24 + 8);
+        assert_eq!(&data[0..4], b".snd");
+        assert_eq!(data[4..8], [0, 0, 0, 24]);
+        assert_eq!(data[8..12], [0, 0, 0, 8]);
+        assert_eq!(data[12..16], [0, 0, 0, 3]);
+        assert_eq!(data[16..20], [0, 0, 0x1f, 0x40]);
+        assert_eq!(data[20..24], [0, 0, 0, 1]);
+        // 大端序
+        assert_eq!(data[24..], [0x3f, 0xff, 0xc0, 0x01, 0x7f, 0xff, 0x80, 0x00]);
+    }
+}
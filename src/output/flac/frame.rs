@@ -0,0 +1,764 @@
+use crate::bitstream::BitWriter;
+
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_LPC_ORDER: usize = 8;
+const MAX_PARTITION_ORDER: usize = 6;
+const MAX_RICE_PARAM: usize = 14;
+const QLP_PRECISION: usize = 12;
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn write_signed(bw: &mut BitWriter, value: i64, n: usize) {
+    bw.write((value as u64 & ((1u64 << n) - 1)) as usize, n);
+}
+
+// 帧号使用类似UTF-8的变长编码
+fn write_utf8(bw: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        bw.write(value as usize, 8);
+        return;
+    }
+    let mut len = 2;
+    while value >= 1u64 << (5 * len + 1) {
+        len += 1;
+    }
+    let lead = (0xffu64 << (8 - len)) & 0xff;
+    bw.write((lead | (value >> (6 * (len - 1)))) as usize & 0xff, 8);
+    for i in (0..len - 1).rev() {
+        bw.write(0x80 | ((value >> (6 * i)) & 0x3f) as usize, 8);
+    }
+}
+
+fn zigzag(residual: i64) -> u64 {
+    ((residual << 1) ^ (residual >> 63)) as u64
+}
+
+// 残差的Rice编码方案
+struct RiceCoding {
+    partition_order: usize,
+    params: Vec<usize>,
+    bits: usize,
+}
+
+fn choose_rice_coding(residual: &[i64], block_size: usize, order: usize) -> RiceCoding {
+    // 先按最细的分区统计每个参数下的商之和，粗分区的值由相邻的细分区合并得到
+    let mut max_order = 0;
+    while max_order < MAX_PARTITION_ORDER
+        && block_size.trailing_zeros() as usize > max_order
+        && (block_size >> (max_order + 1)) > order
+    {
+        max_order += 1;
+    }
+
+    let partitions = 1 << max_order;
+    let partition_size = block_size >> max_order;
+    let mut sums = vec![[0u64; MAX_RICE_PARAM + 1]; partitions];
+    let mut counts = vec![0usize; partitions];
+    let mut start = 0;
+    for p in 0..partitions {
+        let end = (p + 1) * partition_size - order;
+        for &r in &residual[start..end] {
+            let u = zigzag(r);
+            for (k, sum) in sums[p].iter_mut().enumerate() {
+                *sum += u >> k;
+            }
+        }
+        counts[p] = end - start;
+        start = end;
+    }
+
+    let mut best: Option<RiceCoding> = None;
+    let mut partition_order = max_order;
+    loop {
+        let mut bits = 2 + 4;
+        let mut params = Vec::with_capacity(sums.len());
+        for (sum, &count) in sums.iter().zip(counts.iter()) {
+            let (k, cost) = (0..=MAX_RICE_PARAM)
+                .map(|k| (k, sum[k] as usize + count * (k + 1)))
+                .min_by_key(|&(_, cost)| cost)
+                .unwrap();
+            params.push(k);
+            bits += 4 + cost;
+        }
+        if best.as_ref().is_none_or(|b| bits < b.bits) {
+            best = Some(RiceCoding {
+                partition_order,
+                params,
+                bits,
+            });
+        }
+
+        if partition_order == 0 {
+            break;
+        }
+        partition_order -= 1;
+        sums = sums
+            .chunks(2)
+            .map(|pair| {
+                let mut merged = pair[0];
+                for (m, s) in merged.iter_mut().zip(pair[1].iter()) {
+                    *m += s;
+                }
+                merged
+            })
+            .collect();
+        counts = counts.chunks(2).map(|pair| pair[0] + pair[1]).collect();
+    }
+    best.unwrap()
+}
+
+fn write_residual(bw: &mut BitWriter, residual: &[i64], coding: &RiceCoding, block_size: usize, order: usize) {
+    bw.write(0b00, 2);
+    bw.write(coding.partition_order, 4);
+    let partition_size = block_size >> coding.partition_order;
+    let mut start = 0;
+    for (p, &k) in coding.params.iter().enumerate() {
+        let end = (p + 1) * partition_size - order;
+        bw.write(k, 4);
+        for &r in &residual[start..end] {
+            let u = zigzag(r);
+            let q = (u >> k) as usize;
+            for _ in 0..q / 32 {
+                bw.write(0, 32);
+            }
+            bw.write(1, q % 32 + 1);
+            bw.write((u & ((1 << k) - 1)) as usize, k);
+        }
+        start = end;
+    }
+}
+
+enum SubframeType {
+    Constant,
+    Verbatim,
+    Fixed(usize),
+    Lpc {
+        coefs: Vec<i64>,
+        shift: usize,
+    },
+}
+
+struct Subframe {
+    kind: SubframeType,
+    residual: Vec<i64>,
+    coding: Option<RiceCoding>,
+    bits: usize,
+}
+
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    samples[order..]
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| {
+            let n = i + order;
+            match order {
+                0 => x,
+                1 => x - samples[n - 1],
+                2 => x - 2 * samples[n - 1] + samples[n - 2],
+                3 => x - 3 * samples[n - 1] + 3 * samples[n - 2] - samples[n - 3],
+                _ => {
+                    x - 4 * samples[n - 1] + 6 * samples[n - 2] - 4 * samples[n - 3]
+                        + samples[n - 4]
+                }
+            }
+        })
+        .collect()
+}
+
+fn lpc_residual(samples: &[i64], coefs: &[i64], shift: usize) -> Vec<i64> {
+    let order = coefs.len();
+    (order..samples.len())
+        .map(|n| {
+            let prediction: i64 = coefs
+                .iter()
+                .enumerate()
+                .map(|(j, &c)| c * samples[n - j - 1])
+                .sum();
+            samples[n] - (prediction >> shift)
+        })
+        .collect()
+}
+
+// 加窗后求自相关，再用Levinson-Durbin递推求出各阶的预测系数
+fn lpc_coefficients(samples: &[i64], max_order: usize) -> Vec<Vec<f64>> {
+    let n = samples.len();
+    let windowed: Vec<f64> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| {
+            let w = 0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / (n - 1) as f64).cos();
+            x as f64 * w
+        })
+        .collect();
+
+    let mut autoc = vec![0f64; max_order + 1];
+    for (lag, value) in autoc.iter_mut().enumerate() {
+        *value = windowed[lag..]
+            .iter()
+            .zip(windowed.iter())
+            .map(|(a, b)| a * b)
+            .sum();
+    }
+
+    let mut result = Vec::new();
+    if autoc[0] == 0.0 {
+        return result;
+    }
+    let mut lpc = vec![0f64; max_order];
+    let mut err = autoc[0];
+    for i in 0..max_order {
+        let mut r = -autoc[i + 1];
+        for j in 0..i {
+            r -= lpc[j] * autoc[i - j];
+        }
+        r /= err;
+
+        lpc[i] = r;
+        for j in 0..i / 2 {
+            let tmp = lpc[j];
+            lpc[j] += r * lpc[i - 1 - j];
+            lpc[i - 1 - j] += r * tmp;
+        }
+        if i % 2 == 1 {
+            lpc[i / 2] += lpc[i / 2] * r;
+        }
+        err *= 1.0 - r * r;
+
+        result.push(lpc[..=i].iter().map(|c| -c).collect());
+        if err <= 0.0 {
+            break;
+        }
+    }
+    result
+}
+
+fn quantize_coefficients(coefs: &[f64]) -> Option<(Vec<i64>, usize)> {
+    let cmax = coefs.iter().fold(0f64, |m, c| m.max(c.abs()));
+    if cmax <= 0.0 || !cmax.is_finite() {
+        return None;
+    }
+    let max_coef = (1i64 << (QLP_PRECISION - 1)) - 1;
+    let log2cmax = cmax.log2().floor() as i32 + 1;
+    let shift = (QLP_PRECISION as i32 - 1 - log2cmax).clamp(0, 15) as usize;
+
+    // 误差反馈量化，减少系数取整带来的累积偏差
+    let mut error = 0.0;
+    let mut result = Vec::with_capacity(coefs.len());
+    for &c in coefs {
+        error += c * (1 << shift) as f64;
+        let q = (error.round() as i64).clamp(-max_coef - 1, max_coef);
+        error -= q as f64;
+        result.push(q);
+    }
+    Some((result, shift))
+}
+
+fn encode_subframe(samples: &[i64], bps: usize) -> Subframe {
+    let block_size = samples.len();
+
+    if samples.iter().all(|&x| x == samples[0]) {
+        return Subframe {
+            kind: SubframeType::Constant,
+            residual: Vec::new(),
+            coding: None,
+            bits: 8 + bps,
+        };
+    }
+
+    let mut best = Subframe {
+        kind: SubframeType::Verbatim,
+        residual: Vec::new(),
+        coding: None,
+        bits: 8 + bps * block_size,
+    };
+
+    for order in 0..=MAX_FIXED_ORDER.min(block_size - 1) {
+        let residual = fixed_residual(samples, order);
+        let coding = choose_rice_coding(&residual, block_size, order);
+        let bits = 8 + bps * order + coding.bits;
+        if bits < best.bits {
+            best = Subframe {
+                kind: SubframeType::Fixed(order),
+                residual,
+                coding: Some(coding),
+                bits,
+            };
+        }
+    }
+
+    if block_size > MAX_LPC_ORDER * 2 {
+        for coefs in lpc_coefficients(samples, MAX_LPC_ORDER) {
+            let order = coefs.len();
+            let Some((qcoefs, shift)) = quantize_coefficients(&coefs) else {
+                continue;
+            };
+            let residual = lpc_residual(samples, &qcoefs, shift);
+            let coding = choose_rice_coding(&residual, block_size, order);
+            let bits = 8 + bps * order + 4 + 5 + QLP_PRECISION * order + coding.bits;
+            if bits < best.bits {
+                best = Subframe {
+                    kind: SubframeType::Lpc {
+                        coefs: qcoefs,
+                        shift,
+                    },
+                    residual,
+                    coding: Some(coding),
+                    bits,
+                };
+            }
+        }
+    }
+    best
+}
+
+fn write_subframe(bw: &mut BitWriter, subframe: &Subframe, samples: &[i64], bps: usize) {
+    let block_size = samples.len();
+    match &subframe.kind {
+        SubframeType::Constant => {
+            bw.write(0b0000_0000, 8);
+            write_signed(bw, samples[0], bps);
+        }
+        SubframeType::Verbatim => {
+            bw.write(0b0000_0010, 8);
+            for &x in samples {
+                write_signed(bw, x, bps);
+            }
+        }
+        SubframeType::Fixed(order) => {
+            bw.write((0b001000 | order) << 1, 8);
+            for &x in &samples[..*order] {
+                write_signed(bw, x, bps);
+            }
+            let coding = subframe.coding.as_ref().unwrap();
+            write_residual(bw, &subframe.residual, coding, block_size, *order);
+        }
+        SubframeType::Lpc { coefs, shift } => {
+            let order = coefs.len();
+            bw.write((0b100000 | (order - 1)) << 1, 8);
+            for &x in &samples[..order] {
+                write_signed(bw, x, bps);
+            }
+            bw.write(QLP_PRECISION - 1, 4);
+            bw.write(*shift, 5);
+            for &c in coefs {
+                write_signed(bw, c, QLP_PRECISION);
+            }
+            let coding = subframe.coding.as_ref().unwrap();
+            write_residual(bw, &subframe.residual, coding, block_size, order);
+        }
+    }
+}
+
+fn block_size_code(block_size: usize) -> usize {
+    match block_size {
+        192 => 0b0001,
+        576 => 0b0010,
+        1152 => 0b0011,
+        2304 => 0b0100,
+        4608 => 0b0101,
+        256 => 0b1000,
+        512 => 0b1001,
+        1024 => 0b1010,
+        2048 => 0b1011,
+        4096 => 0b1100,
+        8192 => 0b1101,
+        16384 => 0b1110,
+        32768 => 0b1111,
+        _ => 0b0111,
+    }
+}
+
+pub fn sample_rate_code(sample_rate: u32) -> usize {
+    match sample_rate {
+        88200 => 0b0001,
+        176400 => 0b0010,
+        192000 => 0b0011,
+        8000 => 0b0100,
+        16000 => 0b0101,
+        22050 => 0b0110,
+        24000 => 0b0111,
+        32000 => 0b1000,
+        44100 => 0b1001,
+        48000 => 0b1010,
+        96000 => 0b1011,
+        // 其余采样率从STREAMINFO中获取
+        _ => 0b0000,
+    }
+}
+
+// 将一个块编码为FLAC帧，channels中为每个声道的样本
+pub fn encode_frame(
+    channels: &[Vec<i64>],
+    frame_number: u64,
+    sample_rate: u32,
+    bps: usize,
+) -> Vec<u8> {
+    let block_size = channels[0].len();
+
+    // 立体声时尝试左右、左-差、右-差、中-差四种声道组合，取编码后最短的一种
+    let (assignment, subframes) = if channels.len() == 2 {
+        let left = &channels[0];
+        let right = &channels[1];
+        let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+        let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+
+        let sub_left = encode_subframe(left, bps);
+        let sub_right = encode_subframe(right, bps);
+        let sub_side = encode_subframe(&side, bps + 1);
+        let sub_mid = encode_subframe(&mid, bps);
+
+        let candidates = [
+            (0b0001, sub_left.bits + sub_right.bits),
+            (0b1000, sub_left.bits + sub_side.bits),
+            (0b1001, sub_side.bits + sub_right.bits),
+            (0b1010, sub_mid.bits + sub_side.bits),
+        ];
+        let assignment = candidates.iter().min_by_key(|c| c.1).unwrap().0;
+        let subframes = match assignment {
+            0b0001 => vec![(sub_left, left.clone(), bps), (sub_right, right.clone(), bps)],
+            0b1000 => vec![(sub_left, left.clone(), bps), (sub_side, side, bps + 1)],
+            0b1001 => vec![(sub_side, side, bps + 1), (sub_right, right.clone(), bps)],
+            _ => vec![(sub_mid, mid, bps), (sub_side, side, bps + 1)],
+        };
+        (assignment, subframes)
+    } else {
+        let subframes = channels
+            .iter()
+            .map(|samples| (encode_subframe(samples, bps), samples.clone(), bps))
+            .collect();
+        (channels.len() - 1, subframes)
+    };
+
+    let mut bw = BitWriter::new();
+    bw.write(0b11111111111110, 14);
+    bw.write(0, 1);
+    bw.write(0, 1); // 固定块大小
+    let bs_code = block_size_code(block_size);
+    bw.write(bs_code, 4);
+    bw.write(sample_rate_code(sample_rate), 4);
+    bw.write(assignment, 4);
+    bw.write(
+        match bps {
+            8 => 0b001,
+            12 => 0b010,
+            16 => 0b100,
+            20 => 0b101,
+            24 => 0b110,
+            _ => 0b000,
+        },
+        3,
+    );
+    bw.write(0, 1);
+    write_utf8(&mut bw, frame_number);
+    if bs_code == 0b0111 {
+        bw.write(block_size - 1, 16);
+    }
+    let crc = crc8(bw.bytes());
+    bw.write(crc as usize, 8);
+
+    for (subframe, samples, bps) in &subframes {
+        write_subframe(&mut bw, subframe, samples, *bps);
+    }
+    bw.align_byte();
+    let crc = crc16(bw.bytes());
+    bw.write(crc as usize, 16);
+    bw.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        bitstream::BitStream,
+        output::{flac::md5::Md5, pcm_f32_to_int16, FlacWriter, PcmSink},
+    };
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Kind {
+        Constant,
+        Verbatim,
+        Fixed,
+        Lpc,
+    }
+
+    struct Decoded {
+        frame_number: u64,
+        sample_rate_code: usize,
+        channels: Vec<Vec<i64>>,
+        kinds: Vec<Kind>,
+        // 帧的字节数
+        len: usize,
+    }
+
+    fn read(bs: &mut BitStream<Vec<u8>>, n: usize) -> usize {
+        bs.read(n).unwrap()
+    }
+
+    fn read_signed(bs: &mut BitStream<Vec<u8>>, n: usize) -> i64 {
+        (read(bs, n) as i64) << (64 - n) >> (64 - n)
+    }
+
+    fn read_utf8(bs: &mut BitStream<Vec<u8>>) -> u64 {
+        let first = read(bs, 8) as u8;
+        let len = first.leading_ones();
+        if len == 0 {
+            return first as u64;
+        }
+        let mut value = (first & (0x7f >> len)) as u64;
+        for _ in 1..len {
+            value = value << 6 | (read(bs, 8) & 0x3f) as u64;
+        }
+        value
+    }
+
+    fn read_residual(bs: &mut BitStream<Vec<u8>>, block_size: usize, order: usize) -> Vec<i64> {
+        assert_eq!(read(bs, 2), 0b00);
+        let partition_order = read(bs, 4);
+        let partition_size = block_size >> partition_order;
+        let mut residual = Vec::with_capacity(block_size - order);
+        for p in 0..1 << partition_order {
+            let k = read(bs, 4);
+            let count = if p == 0 { partition_size - order } else { partition_size };
+            for _ in 0..count {
+                let mut q = 0;
+                while read(bs, 1) == 0 {
+                    q += 1;
+                }
+                let u = (q << k | read(bs, k)) as i64;
+                residual.push((u >> 1) ^ -(u & 1));
+            }
+        }
+        residual
+    }
+
+    fn read_subframe(bs: &mut BitStream<Vec<u8>>, block_size: usize, bps: usize) -> (Kind, Vec<i64>) {
+        let header = read(bs, 8);
+        // 填充位和wasted bits标志都为0
+        assert_eq!(header & 0x81, 0);
+        let kind = header >> 1;
+        match kind {
+            0b000000 => (Kind::Constant, vec![read_signed(bs, bps); block_size]),
+            0b000001 => (Kind::Verbatim, (0..block_size).map(|_| read_signed(bs, bps)).collect()),
+            0b001000..=0b001100 => {
+                let order = kind & 0b111;
+                let mut s: Vec<i64> = (0..order).map(|_| read_signed(bs, bps)).collect();
+                for r in read_residual(bs, block_size, order) {
+                    let n = s.len();
+                    let prediction = match order {
+                        0 => 0,
+                        1 => s[n - 1],
+                        2 => 2 * s[n - 1] - s[n - 2],
+                        3 => 3 * s[n - 1] - 3 * s[n - 2] + s[n - 3],
+                        _ => 4 * s[n - 1] - 6 * s[n - 2] + 4 * s[n - 3] - s[n - 4],
+                    };
+                    s.push(prediction + r);
+                }
+                (Kind::Fixed, s)
+            }
+            0b100000.. => {
+                let order = (kind & 0b11111) + 1;
+                let mut s: Vec<i64> = (0..order).map(|_| read_signed(bs, bps)).collect();
+                let precision = read(bs, 4) + 1;
+                let shift = read(bs, 5);
+                let coefs: Vec<i64> = (0..order).map(|_| read_signed(bs, precision)).collect();
+                for r in read_residual(bs, block_size, order) {
+                    let n = s.len();
+                    let prediction: i64 = coefs.iter().enumerate().map(|(j, &c)| c * s[n - j - 1]).sum();
+                    s.push((prediction >> shift) + r);
+                }
+                (Kind::Lpc, s)
+            }
+            _ => panic!("保留的子帧类型: {:#08b}", kind),
+        }
+    }
+
+    // 按FLAC格式独立解析一帧，校验两个CRC
+    fn decode_frame(data: &[u8]) -> Decoded {
+        let mut bytes = data.to_vec();
+        let mut bs = BitStream::new(&mut bytes);
+        assert_eq!(read(&mut bs, 14), 0b11111111111110);
+        assert_eq!(read(&mut bs, 2), 0);
+        let bs_code = read(&mut bs, 4);
+        let sample_rate_code = read(&mut bs, 4);
+        let assignment = read(&mut bs, 4);
+        let bps = match read(&mut bs, 3) {
+            0b001 => 8,
+            0b010 => 12,
+            0b100 => 16,
+            0b101 => 20,
+            0b110 => 24,
+            code => panic!("未知的位深: {:#05b}", code),
+        };
+        assert_eq!(read(&mut bs, 1), 0);
+        let frame_number = read_utf8(&mut bs);
+        let block_size = match bs_code {
+            0b0001 => 192,
+            0b0010..=0b0101 => 576 << (bs_code - 0b0010),
+            0b0111 => read(&mut bs, 16) + 1,
+            0b1000..=0b1111 => 256 << (bs_code - 0b1000),
+            _ => panic!("未知的块大小: {:#06b}", bs_code),
+        };
+        let header_len = bs.get_bit_offset() / 8;
+        assert_eq!(read(&mut bs, 8) as u8, crc8(&data[..header_len]));
+
+        // 差信号多一位
+        let (count, side) = match assignment {
+            0b0000..=0b0111 => (assignment + 1, None),
+            0b1000 => (2, Some(1)),
+            0b1001 => (2, Some(0)),
+            0b1010 => (2, Some(1)),
+            _ => panic!("保留的声道组合: {:#06b}", assignment),
+        };
+        let mut kinds = Vec::new();
+        let mut channels = Vec::new();
+        for channel in 0..count {
+            let bps = if side == Some(channel) { bps + 1 } else { bps };
+            let (kind, samples) = read_subframe(&mut bs, block_size, bps);
+            kinds.push(kind);
+            channels.push(samples);
+        }
+        if assignment >= 0b1000 {
+            let (a, b) = (channels[0].clone(), channels[1].clone());
+            for i in 0..block_size {
+                let (left, right) = match assignment {
+                    0b1000 => (a[i], a[i] - b[i]),
+                    0b1001 => (a[i] + b[i], b[i]),
+                    _ => {
+                        let sum = a[i] * 2 + (b[i] & 1);
+                        ((sum + b[i]) >> 1, (sum - b[i]) >> 1)
+                    }
+                };
+                channels[0][i] = left;
+                channels[1][i] = right;
+            }
+        }
+
+        bs.align_byte();
+        let body_len = bs.get_bit_offset() / 8;
+        assert_eq!(read(&mut bs, 16) as u16, crc16(&data[..body_len]));
+        Decoded {
+            frame_number,
+            sample_rate_code,
+            channels,
+            kinds,
+            len: body_len + 2,
+        }
+    }
+
+    fn sine(len: usize, amplitude: f64, frequency: f64) -> Vec<i64> {
+        (0..len)
+            .map(|i| (amplitude * (2.0 * std::f64::consts::PI * frequency * i as f64 / 44100.0).sin()).round() as i64)
+            .collect()
+    }
+
+    // 满幅的16位白噪声，xorshift生成
+    fn noise(len: usize, mut seed: u32) -> Vec<i64> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                (seed >> 16) as i16 as i64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let cases: Vec<(u64, Vec<Vec<i64>>)> = vec![
+            (0, vec![vec![0; 4096]; 2]),
+            (1, vec![sine(4096, 12000.0, 440.0), sine(4096, 9000.0, 660.0)]),
+            (2, vec![noise(4096, 1), noise(4096, 2)]),
+            // 非标准块大小和多字节帧号
+            (300, vec![sine(1000, 30000.0, 1000.0)]),
+            // 太短时不使用LPC
+            (100000, vec![sine(16, 20000.0, 440.0)]),
+            (5, vec![sine(4096, 8000.0, 440.0), noise(4096, 3).iter().map(|x| x >> 8).collect()]),
+        ];
+        let mut kinds = Vec::new();
+        for (frame_number, channels) in &cases {
+            let data = encode_frame(channels, *frame_number, 44100, 16);
+            let decoded = decode_frame(&data);
+            assert_eq!(decoded.len, data.len());
+            assert_eq!(decoded.frame_number, *frame_number);
+            assert_eq!(decoded.sample_rate_code, 0b1001);
+            assert_eq!(&decoded.channels, channels);
+            kinds.extend(decoded.kinds);
+        }
+        for kind in [Kind::Constant, Kind::Verbatim, Kind::Fixed, Kind::Lpc] {
+            assert!(kinds.contains(&kind), "没有用到{:?}子帧", kind);
+        }
+    }
+
+    #[test]
+    fn streaminfo_and_frames() {
+        let pcm: Vec<f32> = (0..10000 * 2)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * (i / 2) as f32 / 12000.0).sin())
+            .collect();
+        let mut writer = FlacWriter::new(Cursor::new(Vec::new()));
+        writer.begin(12000, 2).unwrap();
+        writer.write_samples(&pcm).unwrap();
+        writer.finish().unwrap();
+        let data = writer.into_inner().into_inner();
+
+        assert_eq!(&data[..4], b"fLaC");
+        assert_eq!(data[4..8], [0x00, 0x00, 0x00, 34]);
+        let mut streaminfo = data[8..42].to_vec();
+        let mut bs = BitStream::new(&mut streaminfo);
+        let fields: Vec<usize> = [16, 16, 24, 24, 20, 3, 5, 36].iter().map(|&n| read(&mut bs, n)).collect();
+        let (min_frame, max_frame) = (fields[2], fields[3]);
+        assert_eq!(fields[..2], [4096, 4096]);
+        assert_eq!(fields[4..], [12000, 1, 15, 10000]);
+
+        let samples: Vec<i64> = pcm.iter().map(|&s| pcm_f32_to_int16(s) as i64).collect();
+        let mut md5 = Md5::new();
+        for &s in &samples {
+            md5.update(&(s as i16).to_le_bytes());
+        }
+        assert_eq!(data[26..42], md5.finalize());
+
+        // VORBIS_COMMENT是最后一个元数据块
+        assert_eq!(data[42], 0x84);
+        let comment_len = u32::from_be_bytes([0, data[43], data[44], data[45]]) as usize;
+        let mut pos = 46 + comment_len;
+        let mut decoded = Vec::new();
+        let mut frame_lens = Vec::new();
+        while pos < data.len() {
+            let frame = decode_frame(&data[pos..]);
+            assert_eq!(frame.frame_number, frame_lens.len() as u64);
+            // 12000Hz没有对应的编码，从STREAMINFO中获取
+            assert_eq!(frame.sample_rate_code, 0b0000);
+            for i in 0..frame.channels[0].len() {
+                decoded.extend(frame.channels.iter().map(|c| c[i]));
+            }
+            frame_lens.push(frame.len);
+            pos += frame.len;
+        }
+        assert_eq!(frame_lens.len(), 3);
+        assert_eq!(*frame_lens.iter().min().unwrap(), min_frame);
+        assert_eq!(*frame_lens.iter().max().unwrap(), max_frame);
+        assert_eq!(decoded, samples);
+    }
+}
@@ -0,0 +1,180 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use md5::Md5;
+
+use super::{pcm_f32_to_int16, PcmSink};
+use crate::metadata::Metadata;
+
+pub mod frame;
+pub mod md5;
+
+const BLOCK_SIZE: usize = 4096;
+const BITS_PER_SAMPLE: usize = 16;
+const VENDOR: &str = "tiny_mp3_player";
+
+// 由合并后的元数据生成Vorbis comment，多个值的字段写成多条
+pub fn vorbis_comments_from_metadata(metadata: &Metadata) -> Vec<(String, String)> {
+    let mut comments = Vec::new();
+    let mut push = |key: &str, value: Option<String>| {
+        if let Some(value) = value {
+            comments.push((key.to_string(), value));
+        }
+    };
+    push("TITLE", metadata.title.clone());
+    for artist in &metadata.artists {
+        push("ARTIST", Some(artist.clone()));
+    }
+    push("ALBUMARTIST", metadata.album_artist.clone());
+    push("ALBUM", metadata.album.clone());
+    push("TRACKNUMBER", metadata.track.map(|n| n.to_string()));
+    push("TRACKTOTAL", metadata.track_total.map(|n| n.to_string()));
+    push("DISCNUMBER", metadata.disc.map(|n| n.to_string()));
+    push("DISCTOTAL", metadata.disc_total.map(|n| n.to_string()));
+    push("DATE", metadata.date.map(|date| date.to_string()));
+    push("ORIGINALDATE", metadata.original_date.map(|date| date.to_string()));
+    for genre in &metadata.genres {
+        push("GENRE", Some(genre.clone()));
+    }
+    for comment in &metadata.comments {
+        push("COMMENT", Some(comment.clone()));
+    }
+    let gain = metadata.replay_gain;
+    push("REPLAYGAIN_TRACK_GAIN", gain.track_gain.map(|g| format!("{:.2} dB", g)));
+    push("REPLAYGAIN_TRACK_PEAK", gain.track_peak.map(|p| format!("{:.6}", p)));
+    push("REPLAYGAIN_ALBUM_GAIN", gain.album_gain.map(|g| format!("{:.2} dB", g)));
+    push("REPLAYGAIN_ALBUM_PEAK", gain.album_peak.map(|p| format!("{:.6}", p)));
+    comments
+}
+
+// FLAC编码输出，使用16位整数PCM，STREAMINFO在finish时回填
+pub struct FlacWriter<W: Write + Seek> {
+    writer: W,
+    comments: Vec<(String, String)>,
+    sample_rate: u32,
+    channels: usize,
+    buffer: Vec<Vec<i64>>,
+    frame_number: u64,
+    total_samples: u64,
+    min_frame_size: usize,
+    max_frame_size: usize,
+    md5: Md5,
+    streaminfo_pos: u64,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            comments: Vec::new(),
+            sample_rate: 0,
+            channels: 0,
+            buffer: Vec::new(),
+            frame_number: 0,
+            total_samples: 0,
+            min_frame_size: 0,
+            max_frame_size: 0,
+            md5: Md5::new(),
+            streaminfo_pos: 0,
+        }
+    }
+
+    pub fn with_comments(mut self, comments: Vec<(String, String)>) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn streaminfo(&self, md5: [u8; 16]) -> Vec<u8> {
+        let mut bw = crate::bitstream::BitWriter::new();
+        bw.write(BLOCK_SIZE, 16); // 最小块大小
+        bw.write(BLOCK_SIZE, 16); // 最大块大小
+        bw.write(self.min_frame_size, 24);
+        bw.write(self.max_frame_size, 24);
+        bw.write(self.sample_rate as usize, 20);
+        bw.write(self.channels - 1, 3);
+        bw.write(BITS_PER_SAMPLE - 1, 5);
+        bw.write(self.total_samples as usize, 36);
+        bw.write_bytes(&md5);
+        bw.into_bytes()
+    }
+
+    fn write_block(&mut self, len: usize) -> io::Result<()> {
+        let channels: Vec<Vec<i64>> = self
+            .buffer
+            .iter_mut()
+            .map(|samples| samples.drain(..len).collect())
+            .collect();
+        let frame = frame::encode_frame(&channels, self.frame_number, self.sample_rate, BITS_PER_SAMPLE);
+
+        if self.frame_number == 0 || frame.len() < self.min_frame_size {
+            self.min_frame_size = frame.len();
+        }
+        self.max_frame_size = self.max_frame_size.max(frame.len());
+        self.frame_number += 1;
+        self.writer.write_all(&frame)
+    }
+}
+
+impl<W: Write + Seek> PcmSink for FlacWriter<W> {
+    fn begin(&mut self, sample_rate: u32, channels: u16) -> io::Result<()> {
+        self.sample_rate = sample_rate;
+        self.channels = channels as usize;
+        self.buffer = vec![Vec::with_capacity(BLOCK_SIZE * 2); self.channels];
+
+        self.writer.write_all(b"fLaC")?;
+
+        // STREAMINFO
+        self.writer.write_all(&[0x00, 0x00, 0x00, 34])?;
+        self.streaminfo_pos = self.writer.stream_position()?;
+        let streaminfo = self.streaminfo([0u8; 16]);
+        self.writer.write_all(&streaminfo)?;
+
+        // VORBIS_COMMENT，作为最后一个元数据块
+        let mut block = Vec::new();
+        block.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+        block.extend_from_slice(VENDOR.as_bytes());
+        block.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+        for (key, value) in &self.comments {
+            let comment = format!("{}={}", key, value);
+            block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            block.extend_from_slice(comment.as_bytes());
+        }
+        self.writer.write_all(&[0x84])?;
+        self.writer.write_all(&(block.len() as u32).to_be_bytes()[1..])?;
+        self.writer.write_all(&block)
+    }
+
+    fn write_samples(&mut self, pcm: &[f32]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(pcm.len() * 2);
+        for (i, &sample) in pcm.iter().enumerate() {
+            let sample = pcm_f32_to_int16(sample);
+            bytes.extend_from_slice(&sample.to_le_bytes());
+            self.buffer[i % self.channels].push(sample as i64);
+        }
+        self.md5.update(&bytes);
+        self.total_samples += (pcm.len() / self.channels) as u64;
+
+        while self.buffer[0].len() >= BLOCK_SIZE {
+            self.write_block(BLOCK_SIZE)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let remain = self.buffer[0].len();
+        if remain > 0 {
+            self.write_block(remain)?;
+        }
+
+        let md5 = std::mem::take(&mut self.md5).finalize();
+        let streaminfo = self.streaminfo(md5);
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.streaminfo_pos))?;
+        self.writer.write_all(&streaminfo)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()
+    }
+}
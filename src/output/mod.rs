@@ -0,0 +1,143 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Seek, Write},
+};
+
+use clap::ValueEnum;
+
+use crate::{dbg_println, debug::DebugType, DecodeError, Decoder};
+
+pub mod aiff;
+pub mod au;
+pub mod flac;
+pub mod raw;
+pub mod wav;
+
+pub use aiff::AiffWriter;
+pub use au::AuWriter;
+pub use flac::FlacWriter;
+pub use raw::{RawF32Writer, RawS16Writer};
+pub use wav::{write_wav_header, WavWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Wav,
+    S16le,
+    F32le,
+    Aiff,
+    Au,
+    Flac,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            Self::Wav => "wav",
+            Self::S16le | Self::F32le => "pcm",
+            Self::Aiff => "aiff",
+            Self::Au => "au",
+            Self::Flac => "flac",
+        }
+    }
+}
+
+// 解码得到的PCM数据的输出端，样本为交替存放的f32
+pub trait PcmSink {
+    // 在写入第一帧数据前调用，写入文件头
+    fn begin(&mut self, sample_rate: u32, channels: u16) -> io::Result<()>;
+    fn write_samples(&mut self, pcm: &[f32]) -> io::Result<()>;
+    // 写入完成后回填文件头中的长度等信息
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+pub fn pcm_f32_to_int16(sample: f32) -> i16 {
+    if sample < -0.999999f32 {
+        i16::MIN
+    } else if sample > 0.999999f32 {
+        i16::MAX
+    } else {
+        (sample * 32767.0f32) as i16
+    }
+}
+
+pub fn new_sink<W: Write + Seek + 'static>(format: OutputFormat, writer: W) -> Box<dyn PcmSink> {
+    match format {
+        OutputFormat::Wav => Box::new(WavWriter::new(writer)),
+        OutputFormat::S16le => Box::new(RawS16Writer::new(writer)),
+        OutputFormat::F32le => Box::new(RawF32Writer::new(writer)),
+        OutputFormat::Aiff => Box::new(AiffWriter::new(writer)),
+        OutputFormat::Au => Box::new(AuWriter::new(writer)),
+        OutputFormat::Flac => Box::new(FlacWriter::new(writer)),
+    }
+}
+
+// 将reader中剩余的所有帧解码并写入sink，中途解码失败时先回填已写入部分的文件头再返回错误
+pub fn decode_to_sink<S: PcmSink + ?Sized>(
+    decoder: &mut Decoder,
+    reader: &mut BufReader<File>,
+    sink: &mut S,
+) -> Result<(), DecodeError> {
+    let mut started = false;
+    loop {
+        let pos = reader.stream_position().map_err(DecodeError::ReadFileError)?;
+        if pos >= decoder.data_end && decoder.data_end != 0 {
+            break;
+        }
+        let pcm_data = match decoder.decode_mp3(reader) {
+            Ok(pcm_data) => pcm_data,
+            Err(DecodeError::EndOfFile) => {
+                dbg_println!(DebugType::Decoder, "到达文件末尾，解码完成");
+                break;
+            }
+            Err(e) => {
+                dbg_println!(DebugType::Decoder, "解码错误: {:?}", e);
+                if started {
+                    sink.finish().map_err(DecodeError::WriteFileError)?;
+                }
+                return Err(e);
+            }
+        };
+        if !started {
+            sink.begin(decoder.sample_rate as u32, decoder.channel_num as u16)
+                .map_err(DecodeError::WriteFileError)?;
+            started = true;
+        }
+        sink.write_samples(&pcm_data)
+            .map_err(DecodeError::WriteFileError)?;
+    }
+    if started {
+        sink.finish().map_err(DecodeError::WriteFileError)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::encode::Encoder;
+
+    #[test]
+    fn decode_error_after_partial_output() {
+        let pcm = vec![0.25f32; 1152 * 10];
+        let mut encoder = Encoder::new(44100, 1, 128).unwrap();
+        let mut data = encoder.encode(&pcm);
+        data.extend(encoder.flush());
+        let frames = encoder.frames() as usize;
+        data.extend_from_slice(&[0x55; 600]);
+
+        let path = std::env::temp_dir().join(format!("tiny_mp3_player_corrupt_{}.mp3", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let mut sink = WavWriter::new(Cursor::new(Vec::new()));
+        let result = decode_to_sink(&mut Decoder::new(), &mut reader, &mut sink);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(DecodeError::CanNotFindFrameSync)));
+        // 出错前写入的部分已经回填了长度
+        let wav = sink.into_inner().into_inner();
+        assert_eq!(wav.len(), 44 + frames * 1152 * 2);
+        assert_eq!(wav[40..44], ((frames * 1152 * 2) as u32).to_le_bytes());
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::{self, Write};
+
+use super::{pcm_f32_to_int16, PcmSink};
+
+// 无文件头的16位小端PCM
+pub struct RawS16Writer<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> RawS16Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> PcmSink for RawS16Writer<W> {
+    fn begin(&mut self, _sample_rate: u32, _channels: u16) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_samples(&mut self, pcm: &[f32]) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(pcm.len() * 2);
+        for &sample in pcm {
+            buf.extend_from_slice(&pcm_f32_to_int16(sample).to_le_bytes());
+        }
+        self.writer.write_all(&buf)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// 无文件头的32位小端浮点PCM
+pub struct RawF32Writer<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> RawF32Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> PcmSink for RawF32Writer<W> {
+    fn begin(&mut self, _sample_rate: u32, _channels: u16) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_samples(&mut self, pcm: &[f32]) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(pcm.len() * 4);
+        for &sample in pcm {
+            buf.extend_from_slice(&sample.to_le_bytes());
+        }
+        self.writer.write_all(&buf)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn little_endian_samples() {
+        let pcm = [0.5, -0.5, 1.0, -1.0];
+        let mut s16 = RawS16Writer::new(Vec::new());
+        s16.begin(44100, 2).unwrap();
+        s16.write_samples(&pcm).unwrap();
+        s16.finish().unwrap();
+        assert_eq!(s16.into_inner(), [0xff, 0x3f, 0x01, 0xc0, 0xff, 0x7f, 0x00, 0x80]);
+
+        let mut f32le = RawF32Writer::new(Vec::new());
+        f32le.begin(44100, 2).unwrap();
+        f32le.write_samples(&pcm).unwrap();
+        f32le.finish().unwrap();
+        let data = f32le.into_inner();
+        assert_eq!(data.len(), 16);
+        assert_eq!(data[0..4], [0x00, 0x00, 0x00, 0x3f]);
+        assert_eq!(data[12..16], [0x00, 0x00, 0x80, 0xbf]);
+    }
+}
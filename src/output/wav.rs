@@ -0,0 +1,111 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use super::{pcm_f32_to_int16, PcmSink};
+
+pub fn write_wav_header<W: Write>(
+    writer: &mut W,
+    num_samples: u32,
+    sample_rate: u32,
+    num_channels: u16,
+    bits_per_sample: u16,
+) -> io::Result<()> {
+    let byte_rate = sample_rate * num_channels as u32 * (bits_per_sample / 8) as u32;
+    let block_align = num_channels * (bits_per_sample / 8);
+    let data_size = num_samples * (bits_per_sample / 8) as u32;
+
+    // 写入 RIFF 头
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?; // 文件大小
+    writer.write_all(b"WAVE")?;
+
+    // 写入 fmt 头
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?; // Subchunk1Size (PCM = 16)
+    writer.write_all(&1u16.to_le_bytes())?; // AudioFormat (PCM = 1)
+    writer.write_all(&num_channels.to_le_bytes())?; // NumChannels
+    writer.write_all(&sample_rate.to_le_bytes())?; // SampleRate
+    writer.write_all(&byte_rate.to_le_bytes())?; // ByteRate
+    writer.write_all(&block_align.to_le_bytes())?; // BlockAlign
+    writer.write_all(&bits_per_sample.to_le_bytes())?; // BitsPerSample
+
+    // 写入 data 头
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?; // Subchunk2Size
+
+    Ok(())
+}
+
+// 16位PCM的WAV文件，长度在finish时回填
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    num_samples: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            num_samples: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek> PcmSink for WavWriter<W> {
+    fn begin(&mut self, sample_rate: u32, channels: u16) -> io::Result<()> {
+        self.num_samples = 0;
+        write_wav_header(&mut self.writer, 0, sample_rate, channels, 16)
+    }
+
+    fn write_samples(&mut self, pcm: &[f32]) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(pcm.len() * 2);
+        for &sample in pcm {
+            buf.extend_from_slice(&pcm_f32_to_int16(sample).to_le_bytes());
+        }
+        self.num_samples += pcm.len() as u32;
+        self.writer.write_all(&buf)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let data_size = self.num_samples * 2;
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_sizes_after_finish() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()));
+        writer.begin(44100, 2).unwrap();
+        writer.write_samples(&[0.5, -0.5, 1.0, -1.0]).unwrap();
+        writer.finish().unwrap();
+        let data = writer.into_inner().into_inner();
+
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(data[4..8], 44u32.to_le_bytes());
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(data[22..24], 2u16.to_le_bytes());
+        assert_eq!(data[24..28], 44100u32.to_le_bytes());
+        assert_eq!(data[28..32], (44100u32 * 4).to_le_bytes());
+        assert_eq!(data[32..34], 4u16.to_le_bytes());
+        assert_eq!(data[34..36], 16u16.to_le_bytes());
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(data[40..44], 8u32.to_le_bytes());
+        // 小端序
+        assert_eq!(data[44..], [0xff, 0x3f, 0x01, 0xc0, 0xff, 0x7f, 0x00, 0x80]);
+    }
+}
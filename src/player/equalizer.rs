@@ -0,0 +1,404 @@
+use std::{
+    f64::consts::{FRAC_1_SQRT_2, PI, SQRT_2},
+    fs, io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::{source::SeekError, Source};
+
+use crate::loudness::Biquad;
+
+// 每隔多少帧检查一次设置是否被修改
+const POLL_INTERVAL: u64 = 512;
+// 图示均衡器各频段的中心频率（Hz），相邻频段相差一个倍频程
+pub const GRAPHIC_FREQUENCIES: [f64; 10] = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+// 一个倍频程带宽对应的Q值
+const GRAPHIC_Q: f64 = SQRT_2;
+// 搁架和高低通滤波器默认的Q值（巴特沃斯）
+const DEFAULT_Q: f64 = FRAC_1_SQRT_2;
+
+// 内置预设，格式与预设文件相同
+const BUILTIN_PRESETS: &str = "\
+[flat]
+
+[bass]
+low-shelf = 120 6
+
+[treble]
+high-shelf = 6000 6
+
+[rock]
+preamp = -5
+graphic = 5 4 3 -1 -2 -1 2 4 5 5
+
+[vocal]
+preamp = -4
+graphic = -2 -2 -1 1 3 4 3 1 0 -1
+";
+
+#[derive(thiserror::Error, Debug)]
+pub enum EqError {
+    #[error("读取均衡器预设失败: {0}")]
+    Io(#[from] io::Error),
+    #[error("均衡器预设第{0}行: {1}")]
+    Syntax(usize, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    Peaking,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+}
+
+impl FilterKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "peaking" => Some(Self::Peaking),
+            "low-shelf" => Some(Self::LowShelf),
+            "high-shelf" => Some(Self::HighShelf),
+            "low-pass" => Some(Self::LowPass),
+            "high-pass" => Some(Self::HighPass),
+            _ => None,
+        }
+    }
+
+    // 高低通滤波器没有增益参数
+    fn has_gain(self) -> bool {
+        !matches!(self, Self::LowPass | Self::HighPass)
+    }
+}
+
+// 参数均衡的一个频段，gain为dB
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub kind: FilterKind,
+    pub frequency: f64,
+    pub gain: f64,
+    pub q: f64,
+}
+
+impl Band {
+    // RBJ Audio EQ Cookbook中的公式，频率限制在奈奎斯特频率以下
+    fn biquad(&self, sample_rate: f64) -> Biquad {
+        let w0 = 2.0 * PI * self.frequency.min(sample_rate * 0.45) / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * self.q);
+        let a = 10f64.powf(self.gain / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
+        let (b, a) = match self.kind {
+            FilterKind::Peaking => (
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            ),
+            FilterKind::LowShelf => (
+                [
+                    a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                ],
+                [
+                    (a + 1.0) + (a - 1.0) * cos + shelf,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - shelf,
+                ],
+            ),
+            FilterKind::HighShelf => (
+                [
+                    a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                ],
+                [
+                    (a + 1.0) - (a - 1.0) * cos + shelf,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - shelf,
+                ],
+            ),
+            FilterKind::LowPass => (
+                [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+                [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            ),
+            FilterKind::HighPass => (
+                [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+                [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            ),
+        };
+        Biquad {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+        }
+    }
+}
+
+// 均衡器设置：前级增益、10段图示均衡和任意个参数均衡频段
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EqSettings {
+    pub preamp: f64,
+    pub graphic: [f64; 10],
+    pub bands: Vec<Band>,
+}
+
+impl EqSettings {
+    // 实际生效的频段，图示均衡中增益为0的频段不产生滤波器
+    fn active_bands(&self) -> Vec<Band> {
+        let graphic = GRAPHIC_FREQUENCIES.iter().zip(self.graphic).filter(|(_, gain)| *gain != 0.0).map(|(&frequency, gain)| Band {
+            kind: FilterKind::Peaking,
+            frequency,
+            gain,
+            q: GRAPHIC_Q,
+        });
+        graphic.chain(self.bands.iter().copied()).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    pub settings: EqSettings,
+}
+
+impl Preset {
+    // [名称]开始一个预设，之后为"preamp = dB"、"graphic = 10个dB值"
+    // 以及"peaking|low-shelf|high-shelf = 频率 dB [Q]"、"low-pass|high-pass = 频率 [Q]"，#开始注释
+    pub fn parse_all(text: &str) -> Result<Vec<Self>, EqError> {
+        let mut presets: Vec<Self> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: &str| EqError::Syntax(number + 1, message.to_string());
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                presets.push(Self {
+                    name: name.trim().to_string(),
+                    settings: EqSettings::default(),
+                });
+                continue;
+            }
+            let settings = &mut presets.last_mut().ok_or_else(|| error("设置之前缺少[预设名称]"))?.settings;
+            let (key, value) = line.split_once('=').ok_or_else(|| error("应为\"键 = 值\""))?;
+            let key = key.trim().to_ascii_lowercase();
+            let values: Vec<f64> = value
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| error("数值格式错误"))?;
+            match key.as_str() {
+                "preamp" => match values[..] {
+                    [gain] => settings.preamp = gain,
+                    _ => return Err(error("preamp需要一个增益值")),
+                },
+                "graphic" => {
+                    settings.graphic = values.try_into().map_err(|_| error("graphic需要10个增益值"))?;
+                }
+                _ => {
+                    let kind = FilterKind::from_name(&key).ok_or_else(|| error(&format!("未知的滤波器类型: {}", key)))?;
+                    let (frequency, gain, q) = match (kind.has_gain(), values.as_slice()) {
+                        (true, &[frequency, gain]) => (frequency, gain, None),
+                        (true, &[frequency, gain, q]) => (frequency, gain, Some(q)),
+                        (false, &[frequency]) => (frequency, 0.0, None),
+                        (false, &[frequency, q]) => (frequency, 0.0, Some(q)),
+                        _ => return Err(error(&format!("{}的参数个数错误", key))),
+                    };
+                    let q = q.unwrap_or(if kind == FilterKind::Peaking { GRAPHIC_Q } else { DEFAULT_Q });
+                    if frequency <= 0.0 || q <= 0.0 {
+                        return Err(error("频率和Q值必须大于0"));
+                    }
+                    settings.bands.push(Band { kind, frequency, gain, q });
+                }
+            }
+        }
+        Ok(presets)
+    }
+
+    pub fn builtin() -> Vec<Self> {
+        Self::parse_all(BUILTIN_PRESETS).unwrap()
+    }
+
+    // 内置预设加上文件中的预设，同名时文件中的优先
+    pub fn load(path: &Path) -> Result<Vec<Self>, EqError> {
+        let mut presets = Self::builtin();
+        for preset in Self::parse_all(&fs::read_to_string(path)?)? {
+            presets.retain(|p| p.name != preset.name);
+            presets.push(preset);
+        }
+        Ok(presets)
+    }
+}
+
+// 主线程通过它修改正在播放的所有音源的均衡器设置，版本号用于音频线程发现修改
+#[derive(Clone, Default)]
+pub struct EqHandle(Arc<Mutex<(u64, EqSettings)>>);
+
+impl EqHandle {
+    pub fn new(settings: EqSettings) -> Self {
+        Self(Arc::new(Mutex::new((0, settings))))
+    }
+
+    pub fn settings(&self) -> EqSettings {
+        self.0.lock().unwrap().1.clone()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut EqSettings)) {
+        let mut guard = self.0.lock().unwrap();
+        guard.0 += 1;
+        f(&mut guard.1);
+    }
+}
+
+// 对解码输出施加均衡的音源，设置修改后重新计算系数，只改增益时滤波器状态保持不变
+pub struct Equalizer<S> {
+    inner: S,
+    handle: EqHandle,
+    version: Option<u64>,
+    bands: Vec<Band>,
+    biquads: Vec<Biquad>,
+    preamp: f32,
+    // 每个声道每个滤波器的状态
+    states: Vec<[f64; 2]>,
+    frames: u64,
+    channel: u16,
+}
+
+impl<S: Source<Item = f32>> Equalizer<S> {
+    pub fn new(inner: S, handle: EqHandle) -> Self {
+        Self {
+            inner,
+            handle,
+            version: None,
+            bands: Vec::new(),
+            biquads: Vec::new(),
+            preamp: 1.0,
+            states: Vec::new(),
+            frames: 0,
+            channel: 0,
+        }
+    }
+
+    fn poll_settings(&mut self) {
+        let guard = self.handle.0.lock().unwrap();
+        if self.version == Some(guard.0) {
+            return;
+        }
+        let (version, settings) = &*guard;
+        self.version = Some(*version);
+        let bands = settings.active_bands();
+        // 只修改增益时保留状态，避免出现爆音；频段的类型、频率或Q改变时状态不再对应，需要清零
+        let same_layout = bands.len() == self.bands.len()
+            && bands.iter().zip(&self.bands).all(|(a, b)| a.kind == b.kind && a.frequency == b.frequency && a.q == b.q);
+        if !same_layout {
+            self.states = vec![[0.0; 2]; bands.len() * self.inner.channels() as usize];
+        }
+        let sample_rate = self.inner.sample_rate() as f64;
+        self.biquads = bands.iter().map(|band| band.biquad(sample_rate)).collect();
+        self.bands = bands;
+        self.preamp = 10f64.powf(settings.preamp / 20.0) as f32;
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Equalizer<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            if self.frames.is_multiple_of(POLL_INTERVAL) {
+                self.poll_settings();
+            }
+            self.frames += 1;
+        }
+        let sample = self.inner.next()?;
+        let channel = self.channel as usize;
+        self.channel = (self.channel + 1) % self.inner.channels().max(1);
+        if self.biquads.is_empty() {
+            return Some(sample * self.preamp);
+        }
+        let states = &mut self.states[channel * self.biquads.len()..];
+        let y = self.biquads.iter().zip(states).fold(sample as f64, |x, (biquad, state)| biquad.process(x, state));
+        Some(y as f32 * self.preamp)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Equalizer<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.channel = 0;
+        self.states.iter_mut().for_each(|state| *state = [0.0; 2]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    // 稳定之后的峰值
+    fn peak_after(settings: EqSettings, frequency: f64) -> f32 {
+        let pcm: Vec<f32> = (0..48000).map(|i| (0.25 * (2.0 * PI * frequency * i as f64 / 48000.0).sin()) as f32).collect();
+        let equalizer = Equalizer::new(SamplesBuffer::new(1, 48000, pcm), EqHandle::new(settings));
+        equalizer.skip(24000).fold(0f32, |peak, s| peak.max(s.abs())) / 0.25
+    }
+
+    #[test]
+    fn presets_and_filter_response() {
+        let text = "# 自定义\n[custom]\npreamp = -6\ngraphic = 0 0 0 0 0 6 0 0 0 0\nhigh-pass = 200\npeaking = 4000 -12 2\n";
+        let presets = Preset::parse_all(text).unwrap();
+        let settings = presets[0].settings.clone();
+        assert_eq!(presets[0].name, "custom");
+        assert_eq!(settings.bands.len(), 2);
+        assert_eq!(settings.bands[0].q, DEFAULT_Q);
+        assert!(matches!(Preset::parse_all("[x]\nlow-pass = 1000 3 1 1"), Err(EqError::Syntax(2, _))));
+        assert!(Preset::builtin().iter().any(|p| p.name == "flat"));
+
+        // 1kHz提升6dB再由前级衰减6dB（其他频段有少量影响），4kHz衰减12dB，50Hz被高通滤掉
+        assert!((peak_after(settings.clone(), 1000.0) - 1.0).abs() < 0.05);
+        assert!((peak_after(settings.clone(), 4000.0) - 0.126).abs() < 0.02);
+        assert!(peak_after(settings, 50.0) < 0.05);
+        assert!((peak_after(EqSettings::default(), 440.0) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn filter_state_on_settings_change() {
+        let pcm: Vec<f32> = (0..4800).map(|i| (0.25 * (2.0 * PI * 1000.0 * i as f64 / 48000.0).sin()) as f32).collect();
+        let mut graphic = [0.0; 10];
+        graphic[5] = 6.0;
+        let handle = EqHandle::new(EqSettings { graphic, ..Default::default() });
+        let mut equalizer = Equalizer::new(SamplesBuffer::new(1, 48000, pcm), handle.clone());
+        equalizer.by_ref().take(1000).for_each(drop);
+        assert!(equalizer.states.iter().any(|state| *state != [0.0; 2]));
+
+        // 只改增益保留状态
+        handle.update(|settings| settings.graphic[5] = 3.0);
+        equalizer.poll_settings();
+        assert!(equalizer.states.iter().any(|state| *state != [0.0; 2]));
+
+        // 频段数量相同但换成了另一个频段，状态清零
+        handle.update(|settings| {
+            settings.graphic[5] = 0.0;
+            settings.graphic[7] = 6.0;
+        });
+        equalizer.poll_settings();
+        assert!(equalizer.states.iter().all(|state| *state == [0.0; 2]));
+    }
+}
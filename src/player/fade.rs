@@ -0,0 +1,203 @@
+use std::{
+    f32::consts::{FRAC_PI_2, PI},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use rodio::{source::SeekError, Source};
+
+// 每隔多少帧检查一次控制端的请求，44.1kHz时约12ms
+const POLL_INTERVAL: u64 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FadeCurve {
+    Linear,
+    // 等功率，交叉淡化时两首的总功率不变
+    #[default]
+    EqualPower,
+    // 两端平缓的S形
+    SCurve,
+    // 按分贝均匀变化（-60dB到0dB），听感上的音量线性变化
+    Exponential,
+}
+
+impl FadeCurve {
+    // 淡入到t（0到1）时的增益，淡出时使用1-t
+    pub fn gain(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::EqualPower => (t * FRAC_PI_2).sin(),
+            Self::SCurve => (1.0 - (t * PI).cos()) / 2.0,
+            Self::Exponential => (1000f32.powf(t) - 1.0) / 999.0,
+        }
+    }
+}
+
+// 从from到to的增益变化，以帧为单位计时
+#[derive(Clone, Copy)]
+struct Envelope {
+    from: f32,
+    to: f32,
+    curve: FadeCurve,
+    length: u64,
+    elapsed: u64,
+}
+
+impl Envelope {
+    fn gain(&self) -> f32 {
+        let t = self.elapsed as f32 / self.length.max(1) as f32;
+        if self.to >= self.from {
+            self.from + (self.to - self.from) * self.curve.gain(t)
+        } else {
+            self.to + (self.from - self.to) * self.curve.gain(1.0 - t)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct FadeRequest {
+    // None表示从当前增益开始
+    from: Option<f32>,
+    to: f32,
+    duration: Duration,
+    curve: FadeCurve,
+}
+
+// 源被移入Sink之后由主线程通过它请求淡入淡出
+#[derive(Clone, Default)]
+pub struct FadeHandle(Arc<Mutex<Option<FadeRequest>>>);
+
+impl FadeHandle {
+    // 从静音开始淡入
+    pub fn fade_in(&self, duration: Duration, curve: FadeCurve) {
+        self.request(Some(0.0), 1.0, duration, curve);
+    }
+
+    // 从当前音量淡出，结束后保持静音
+    pub fn fade_out(&self, duration: Duration, curve: FadeCurve) {
+        self.request(None, 0.0, duration, curve);
+    }
+
+    fn request(&self, from: Option<f32>, to: f32, duration: Duration, curve: FadeCurve) {
+        *self.0.lock().unwrap() = Some(FadeRequest { from, to, duration, curve });
+    }
+}
+
+// 对解码输出施加淡入淡出增益的音源
+pub struct Fader<S> {
+    inner: S,
+    gain: f32,
+    envelope: Option<Envelope>,
+    handle: FadeHandle,
+    // 已输出的帧数和当前帧中的声道
+    frames: u64,
+    channel: u16,
+}
+
+impl<S: Source<Item = f32>> Fader<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            gain: 1.0,
+            envelope: None,
+            handle: FadeHandle::default(),
+            frames: 0,
+            channel: 0,
+        }
+    }
+
+    pub fn handle(&self) -> FadeHandle {
+        self.handle.clone()
+    }
+
+    // 每帧开始时更新增益
+    fn start_frame(&mut self) {
+        if self.frames.is_multiple_of(POLL_INTERVAL) {
+            if let Some(request) = self.handle.0.lock().unwrap().take() {
+                let rate = self.inner.sample_rate() as f64;
+                self.envelope = Some(Envelope {
+                    from: request.from.unwrap_or(self.gain),
+                    to: request.to,
+                    curve: request.curve,
+                    length: (request.duration.as_secs_f64() * rate).round() as u64,
+                    elapsed: 0,
+                });
+            }
+        }
+        self.frames += 1;
+        if let Some(envelope) = &mut self.envelope {
+            if envelope.elapsed >= envelope.length {
+                self.gain = envelope.to;
+                self.envelope = None;
+            } else {
+                self.gain = envelope.gain();
+                envelope.elapsed += 1;
+            }
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Fader<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            self.start_frame();
+        }
+        let sample = self.inner.next()?;
+        self.channel = (self.channel + 1) % self.inner.channels().max(1);
+        Some(sample * self.gain)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Fader<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn curves_and_fade_envelope() {
+        for curve in FadeCurve::value_variants() {
+            assert_eq!(curve.gain(0.0), 0.0);
+            assert!((curve.gain(1.0) - 1.0).abs() < 1e-6);
+        }
+        // 等功率曲线淡入和淡出的功率之和为1
+        let (fade_in, fade_out) = (FadeCurve::EqualPower.gain(0.3), FadeCurve::EqualPower.gain(0.7));
+        assert!((fade_in.powi(2) + fade_out.powi(2) - 1.0).abs() < 1e-6);
+
+        // 双声道、1000Hz，100ms线性淡入
+        let fader = Fader::new(SamplesBuffer::new(2, 1000, vec![1.0f32; 400]));
+        fader.handle().fade_in(Duration::from_millis(100), FadeCurve::Linear);
+        let samples: Vec<f32> = fader.collect();
+        assert_eq!(samples.len(), 400);
+        assert_eq!(samples[..2], [0.0, 0.0]);
+        assert_eq!(samples[100..102], [0.5, 0.5]);
+        assert!(samples[200..].iter().all(|&s| s == 1.0));
+    }
+}
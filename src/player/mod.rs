@@ -0,0 +1,320 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::{source::SeekError, Source};
+
+pub mod equalizer;
+pub mod fade;
+pub mod replay_gain;
+
+use crate::{
+    dbg_println,
+    debug::DebugType,
+    edit::DECODER_DELAY,
+    metadata::ReplayGain,
+    mpeg_frame::{frame_at, types::MpegChannelMode, xing::XingHeader},
+    tags::FileTags,
+    DecodeError, Decoder,
+};
+
+const SAMPLES_PER_FRAME: usize = 1152;
+// MPEG-1 Layer III最大的帧（320kbps、32kHz、有填充字节）
+const MAX_FRAME_SIZE: usize = 1441;
+
+// 正在播放的帧的帧头信息，由音频线程在解码每一帧后更新
+#[derive(Clone, Copy, Default)]
+pub struct FrameInfo {
+    pub bit_rate: usize,
+    pub sample_rate: usize,
+    pub channel_mode: Option<MpegChannelMode>,
+}
+
+// 边解码边播放的rodio音源，每次只解码一帧，可以随时跳转
+// 有LAME标签时去掉编码延迟和结尾补充的样本，连续播放的曲目之间没有间隙
+pub struct Mp3Source {
+    decoder: Decoder,
+    reader: BufReader<File>,
+    // 当前帧交错排列的PCM数据和下一个要输出的位置
+    buffer: Vec<f32>,
+    index: usize,
+    // buffer对应的帧序号和下一个要解码的帧序号
+    frame: usize,
+    next_frame: usize,
+    // 有效样本在解码输出中的范围[start, end)，以单个声道的样本序号表示
+    start: u64,
+    end: u64,
+    info: Arc<Mutex<FrameInfo>>,
+    // LAME标签中的ReplayGain字段
+    lame_gain: ReplayGain,
+}
+
+impl Mp3Source {
+    // reader应指向音频数据开头，data_end为音频数据的结尾，0表示到文件末尾
+    pub fn new(mut reader: BufReader<File>, data_end: u64) -> Result<Self, DecodeError> {
+        let mut decoder = Decoder::new();
+        decoder.data_end = data_end;
+        decoder.calculate_mp3_duration(&mut reader)?;
+
+        // 第一帧是Xing/Info帧时跳过，并从其中的LAME标签取得延迟和补充
+        let mut lame = None;
+        if let Some(&first) = decoder.frame_positions.first() {
+            let mut frame = vec![0; MAX_FRAME_SIZE];
+            let len = reader.read(&mut frame).map_err(DecodeError::ReadFileError)?;
+            if let Some((header, size)) = frame_at(&frame[..len], 0) {
+                if let Some(xing) = frame.get(..size).and_then(|frame| XingHeader::parse(frame, &header)) {
+                    lame = xing.lame;
+                    decoder.frame_positions.remove(0);
+                }
+            }
+            let audio_start = decoder.frame_positions.first().copied().unwrap_or(first);
+            reader.seek(SeekFrom::Start(audio_start)).map_err(DecodeError::ReadFileError)?;
+        }
+        let samples = (decoder.frame_positions.len() * SAMPLES_PER_FRAME) as u64;
+        let lame_gain = lame.as_ref().map(ReplayGain::from_lame).unwrap_or_default();
+        let (start, end) = match lame {
+            Some(lame) => {
+                let start = (lame.delay as usize + DECODER_DELAY) as u64;
+                (start, (start + samples).saturating_sub((lame.delay + lame.padding) as u64).min(samples))
+            }
+            None => (0, samples),
+        };
+
+        let mut source = Self {
+            decoder,
+            reader,
+            buffer: Vec::new(),
+            index: 0,
+            frame: 0,
+            next_frame: 0,
+            start,
+            end,
+            info: Arc::default(),
+            lame_gain,
+        };
+        // 先解码第一帧以确定声道数和采样率，第一帧就无法解码时采样率未知，不能作为音源
+        if !source.read_frame()? {
+            return Err(DecodeError::EndOfFile);
+        }
+        Ok(source)
+    }
+
+    // 打开文件并读取标签，只有标签之间的音频数据会被解码
+    pub fn open(path: &Path) -> Result<(Self, FileTags), DecodeError> {
+        let file = File::open(path).map_err(DecodeError::ReadFileError)?;
+        let mut reader = BufReader::new(file);
+        let tags = FileTags::read(&mut reader).map_err(DecodeError::ReadFileError)?;
+        Ok((Self::new(reader, tags.audio_end)?, tags))
+    }
+
+    // 只播放[start, end)之间的部分（如CUE中的一首），之后的时间和跳转都相对于start
+    pub fn trim(&mut self, start: Duration, end: Option<Duration>) -> Result<(), DecodeError> {
+        let rate = self.decoder.sample_rate as f64;
+        let samples = |time: Duration| (time.as_secs_f64() * rate).round() as u64;
+        if let Some(end) = end {
+            self.end = self.end.min(self.start.saturating_add(samples(end)));
+        }
+        self.start = self.start.saturating_add(samples(start)).min(self.end);
+        self.try_seek(Duration::ZERO)
+            .map_err(|e| DecodeError::ReadFileError(io::Error::other(e.to_string())))
+    }
+
+    pub fn lame_gain(&self) -> ReplayGain {
+        self.lame_gain
+    }
+
+    // 去掉编码延迟和结尾补充之后的时长
+    pub fn duration(&self) -> Duration {
+        let samples = self.end.saturating_sub(self.start);
+        Duration::from_secs_f64(samples as f64 / self.decoder.sample_rate.max(1) as f64)
+    }
+
+    // 源被移入rodio的Sink之后通过它读取帧头信息
+    pub fn frame_info(&self) -> Arc<Mutex<FrameInfo>> {
+        self.info.clone()
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f64(SAMPLES_PER_FRAME as f64 / self.decoder.sample_rate.max(1) as f64)
+    }
+
+    // 下一个要输出的样本在解码输出中的序号
+    fn sample_position(&self) -> u64 {
+        (self.frame * SAMPLES_PER_FRAME + self.index / self.decoder.channel_num.max(1)) as u64
+    }
+
+    // 解码下一帧到buffer，没有更多帧或者解码失败时返回false
+    fn decode_frame(&mut self) -> bool {
+        self.read_frame().unwrap_or_else(|e| {
+            dbg_println!(DebugType::Decoder, "解码错误: {:?}", e);
+            false
+        })
+    }
+
+    // 解码下一帧到buffer，没有更多帧时返回Ok(false)
+    fn read_frame(&mut self) -> Result<bool, DecodeError> {
+        self.buffer.clear();
+        self.index = 0;
+        let data_end = self.decoder.data_end;
+        if data_end != 0 && self.reader.stream_position().map_or(true, |pos| pos >= data_end) {
+            return Ok(false);
+        }
+        match self.decoder.decode_mp3(&mut self.reader) {
+            Ok(pcm_data) => {
+                self.buffer = pcm_data;
+                self.frame = self.next_frame;
+                self.next_frame += 1;
+                *self.info.lock().unwrap() = FrameInfo {
+                    bit_rate: self.decoder.bit_rate,
+                    sample_rate: self.decoder.sample_rate,
+                    channel_mode: self.decoder.channel_mode,
+                };
+                Ok(true)
+            }
+            Err(DecodeError::EndOfFile) => {
+                dbg_println!(DebugType::Decoder, "到达文件末尾，解码完成");
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Iterator for Mp3Source {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        loop {
+            while self.index >= self.buffer.len() {
+                if !self.decode_frame() {
+                    return None;
+                }
+            }
+            let position = self.sample_position();
+            if position >= self.end {
+                return None;
+            }
+            if position < self.start {
+                // 跳过开头的编码延迟
+                let skip = (self.start - self.frame as u64 * SAMPLES_PER_FRAME as u64) as usize;
+                self.index = (skip * self.decoder.channel_num).min(self.buffer.len());
+                continue;
+            }
+            self.index += 1;
+            return Some(self.buffer[self.index - 1]);
+        }
+    }
+}
+
+impl Source for Mp3Source {
+    fn current_frame_len(&self) -> Option<usize> {
+        (self.index < self.buffer.len()).then(|| self.buffer.len() - self.index)
+    }
+
+    fn channels(&self) -> u16 {
+        self.decoder.channel_num as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.decoder.sample_rate as u32
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.duration())
+    }
+
+    // 跳转到pos所在的帧，再丢弃帧内pos之前的采样，精确到采样
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let sample = self.start + (pos.as_secs_f64() * self.decoder.sample_rate as f64).round() as u64;
+        let frame = (sample / SAMPLES_PER_FRAME as u64) as usize;
+        if sample >= self.end || frame >= self.decoder.frame_positions.len() {
+            // 跳过结尾时直接结束
+            self.buffer.clear();
+            self.index = 0;
+            let end = match self.decoder.data_end {
+                0 => SeekFrom::End(0),
+                end => SeekFrom::Start(end),
+            };
+            self.reader.seek(end).map_err(|e| SeekError::Other(Box::new(e)))?;
+            return Ok(());
+        }
+        self.decoder
+            .seek_frame(&mut self.reader, frame)
+            .map_err(|e| SeekError::Other(Box::new(e)))?;
+        self.next_frame = frame;
+        if self.decode_frame() {
+            let offset = (sample - frame as u64 * SAMPLES_PER_FRAME as u64) as usize;
+            self.index = (offset * self.decoder.channel_num).min(self.buffer.len());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        edit::Mp3Stream,
+        encode::{Encoder, ENCODER_DELAY},
+    };
+
+    #[test]
+    fn streams_and_seeks_to_sample() {
+        let pcm: Vec<f32> = (0..44100)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let mut encoder = Encoder::new(44100, 1, 128).unwrap();
+        let mut data = encoder.encode(&pcm);
+        data.extend(encoder.flush());
+        let path = std::env::temp_dir().join(format!("tiny_mp3_player_stream_{}.mp3", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let open = || Mp3Source::new(BufReader::new(File::open(&path).unwrap()), 0).unwrap();
+
+        let decoded: Vec<f32> = open().collect();
+        assert_eq!(decoded.len(), encoder.frames() as usize * 1152);
+
+        // 跳转后的输出与从头解码的结果一致
+        let mut source = open();
+        source.try_seek(Duration::from_secs_f64(20000.0 / 44100.0)).unwrap();
+        let after: Vec<f32> = source.take(1000).collect();
+        assert_eq!(after, decoded[20000..21000]);
+
+        let mut source = open();
+        source.try_seek(Duration::from_secs(10)).unwrap();
+        assert_eq!(source.next(), None);
+
+        // 没有可以解码的帧时不能作为音源
+        std::fs::write(&path, b"").unwrap();
+        assert!(Mp3Source::new(BufReader::new(File::open(&path).unwrap()), 0).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn gapless_trimming() {
+        let pcm: Vec<f32> = (0..30000)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let mut encoder = Encoder::new(44100, 1, 128).unwrap();
+        let mut data = encoder.encode(&pcm);
+        data.extend(encoder.flush());
+        let mut stream = Mp3Stream::parse(&data).unwrap();
+        stream.delay = ENCODER_DELAY;
+        stream.padding = stream.frames.len() * SAMPLES_PER_FRAME - ENCODER_DELAY - pcm.len();
+        let path = std::env::temp_dir().join(format!("tiny_mp3_player_gapless_{}.mp3", std::process::id()));
+        std::fs::write(&path, stream.to_bytes().unwrap()).unwrap();
+
+        let (source, _) = Mp3Source::open(&path).unwrap();
+        assert_eq!(source.duration(), Duration::from_secs_f64(30000.0 / 44100.0));
+        let decoded: Vec<f32> = source.collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded.len(), pcm.len());
+        let (signal, noise) = pcm.iter().zip(&decoded).fold((0f64, 0f64), |(s, n), (&a, &b)| {
+            (s + (a as f64).powi(2), n + (a as f64 - b as f64).powi(2))
+        });
+        assert!(10.0 * (signal / noise).log10() > 30.0);
+    }
+}
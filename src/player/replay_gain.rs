@@ -0,0 +1,77 @@
+use clap::ValueEnum;
+
+use crate::metadata::ReplayGain;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    // 单曲增益，没有时使用专辑增益
+    Track,
+    // 专辑增益，保持专辑内曲目之间的音量差别，没有时使用单曲增益
+    Album,
+}
+
+// 播放时的ReplayGain设置，preamp（dB）只加在有增益信息的曲目上
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GainSettings {
+    pub mode: ReplayGainMode,
+    pub preamp: f32,
+}
+
+impl GainSettings {
+    // 施加在PCM上的增益（dB），放大后的峰值不超过满幅；关闭或者没有增益信息时为None
+    pub fn gain(&self, replay_gain: &ReplayGain) -> Option<f32> {
+        let (gain, peak) = match self.mode {
+            ReplayGainMode::Off => return None,
+            ReplayGainMode::Track => (
+                replay_gain.track_gain.or(replay_gain.album_gain)?,
+                replay_gain.track_peak.or(replay_gain.album_peak),
+            ),
+            ReplayGainMode::Album => (
+                replay_gain.album_gain.or(replay_gain.track_gain)?,
+                replay_gain.album_peak.or(replay_gain.track_peak),
+            ),
+        };
+        let gain = gain + self.preamp;
+        // 防止削波
+        Some(match peak.filter(|peak| *peak > 0.0) {
+            Some(peak) => gain.min(-20.0 * peak.log10()),
+            None => gain,
+        })
+    }
+
+    // 线性放大倍数
+    pub fn factor(&self, replay_gain: &ReplayGain) -> f32 {
+        self.gain(replay_gain).map_or(1.0, |gain| 10f32.powf(gain / 20.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mpeg_frame::xing::LameTag;
+
+    #[test]
+    fn lame_fields_and_clipping_prevention() {
+        let mut lame = LameTag::new(576, 1000);
+        // 单曲-6.2dB、专辑+3.5dB，来源为用户设置
+        lame.radio_gain = 1 << 13 | 2 << 10 | 1 << 9 | 62;
+        lame.audiophile_gain = 2 << 13 | 2 << 10 | 35;
+        lame.peak = 0.5;
+        let replay_gain = ReplayGain::from_lame(&lame);
+        assert_eq!(replay_gain.track_gain, Some(-6.2));
+        assert_eq!(replay_gain.album_gain, Some(3.5));
+        assert_eq!(replay_gain.track_peak, Some(0.5));
+        assert!(ReplayGain::from_lame(&LameTag::new(576, 0)).is_empty());
+
+        let track = GainSettings { mode: ReplayGainMode::Track, preamp: 0.0 };
+        assert_eq!(track.gain(&replay_gain), Some(-6.2));
+        // 峰值0.5时最多放大约6.02dB
+        let album = GainSettings { mode: ReplayGainMode::Album, preamp: 6.0 };
+        assert!((album.factor(&replay_gain) - 2.0).abs() < 1e-4);
+        let off = GainSettings::default();
+        assert_eq!(off.gain(&replay_gain), None);
+        assert_eq!(off.factor(&replay_gain), 1.0);
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+
+#[derive(thiserror::Error, Debug)]
+pub enum PlaylistError {
+    #[error("读写播放列表失败: {0}")]
+    Io(#[from] io::Error),
+    #[error("无法识别的播放列表格式: {0}")]
+    UnknownFormat(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PlaylistFormat {
+    // M3U和M3U8只有编码不同，保存时都使用UTF-8
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(Self::M3u),
+            "pls" => Some(Self::Pls),
+            "xspf" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl PlaylistEntry {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            title: None,
+            duration: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Playlist {
+    pub entries: Vec<PlaylistEntry>,
+}
+
+// 不需要百分号编码的字符，路径分隔符保留
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-._~/".contains(&b)
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes().fold(String::new(), |mut out, b| {
+        if is_unreserved(b) {
+            out.push(b as char);
+        } else {
+            let _ = write!(out, "%{:02X}", b);
+        }
+        out
+    })
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn xml_unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let Some(end) = after.find(';') else {
+            break;
+        };
+        let entity = &after[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &after[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// 读取文本文件，非UTF-8的文件（如旧的.m3u和.cue）按Latin-1解码
+pub(crate) fn read_text(path: &Path) -> io::Result<String> {
+    let data = fs::read(path)?;
+    Ok(String::from_utf8(data).unwrap_or_else(|e| e.into_bytes().iter().map(|&b| b as char).collect()))
+}
+
+// 第一个<name>...</name>元素的内容
+fn xml_element<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = text.find(&open)? + open.len();
+    let end = text[start..].find(&close)? + start;
+    Some(text[start..end].trim())
+}
+
+// 解析列表中的位置：file://地址、其他URL原样保留、相对路径相对于列表所在的目录
+fn resolve(base: &Path, location: &str, uri: bool) -> PathBuf {
+    if let Some(path) = location.strip_prefix("file://") {
+        let path = path.strip_prefix("localhost").unwrap_or(path);
+        return PathBuf::from(percent_decode(path));
+    }
+    if location.contains("://") {
+        return PathBuf::from(location);
+    }
+    let location = if uri { percent_decode(location) } else { location.to_string() };
+    // Windows上生成的列表使用反斜杠分隔
+    let location = if cfg!(windows) { location } else { location.replace('\\', "/") };
+    base.join(location)
+}
+
+// 保存时在列表所在目录之下的文件使用相对路径
+fn relative<'a>(base: Option<&Path>, path: &'a Path) -> &'a Path {
+    base.and_then(|base| path.strip_prefix(base).ok()).unwrap_or(path)
+}
+
+impl Playlist {
+    // base为相对路径的起点，通常是列表文件所在的目录
+    pub fn parse(format: PlaylistFormat, text: &str, base: &Path) -> Self {
+        let text = text.trim_start_matches('\u{feff}');
+        match format {
+            PlaylistFormat::M3u => Self::parse_m3u(text, base),
+            PlaylistFormat::Pls => Self::parse_pls(text, base),
+            PlaylistFormat::Xspf => Self::parse_xspf(text, base),
+        }
+    }
+
+    // #EXTINF:时长（秒，-1为未知）,标题
+    fn parse_m3u(text: &str, base: &Path) -> Self {
+        let mut entries = Vec::new();
+        let mut info = None;
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+                let (seconds, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+                // 时长之后可能还有key="value"形式的属性
+                let seconds = seconds.split_whitespace().next().and_then(|s| s.parse::<f64>().ok());
+                // 负数（-1）、inf和过大的值都视为未知
+                let duration = seconds.and_then(|s| Duration::try_from_secs_f64(s).ok());
+                let title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+                info = Some((title, duration));
+            } else if !line.starts_with('#') {
+                let (title, duration) = info.take().unwrap_or_default();
+                entries.push(PlaylistEntry {
+                    path: resolve(base, line, false),
+                    title,
+                    duration,
+                });
+            }
+        }
+        Self { entries }
+    }
+
+    // [playlist]段中的FileN、TitleN和LengthN，N从1开始
+    fn parse_pls(text: &str, base: &Path) -> Self {
+        let mut entries: Vec<(usize, PlaylistEntry)> = Vec::new();
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim();
+            let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+            let Ok(number) = key[split..].parse::<usize>() else {
+                continue;
+            };
+            let index = match entries.iter().position(|(n, _)| *n == number) {
+                Some(index) => index,
+                None => {
+                    entries.push((number, PlaylistEntry::new(PathBuf::new())));
+                    entries.len() - 1
+                }
+            };
+            let entry = &mut entries[index].1;
+            match &key[..split] {
+                "file" => entry.path = resolve(base, value, false),
+                "title" => entry.title = Some(value.to_string()).filter(|t| !t.is_empty()),
+                "length" => {
+                    entry.duration = value.parse::<f64>().ok().and_then(|s| Duration::try_from_secs_f64(s).ok())
+                }
+                _ => {}
+            }
+        }
+        entries.sort_by_key(|(number, _)| *number);
+        Self {
+            entries: entries
+                .into_iter()
+                .map(|(_, entry)| entry)
+                .filter(|entry| !entry.path.as_os_str().is_empty())
+                .collect(),
+        }
+    }
+
+    // trackList中每个track的location、title和duration（毫秒）
+    fn parse_xspf(text: &str, base: &Path) -> Self {
+        let mut entries = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("<track>") {
+            let after = &rest[start + "<track>".len()..];
+            let end = after.find("</track>").unwrap_or(after.len());
+            let track = &after[..end];
+            rest = &after[end..];
+            let Some(location) = xml_element(track, "location") else {
+                continue;
+            };
+            entries.push(PlaylistEntry {
+                path: resolve(base, &xml_unescape(location), true),
+                title: xml_element(track, "title").map(xml_unescape),
+                duration: xml_element(track, "duration")
+                    .and_then(|ms| ms.parse().ok())
+                    .map(Duration::from_millis),
+            });
+        }
+        Self { entries }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PlaylistError> {
+        let format = PlaylistFormat::from_path(path)
+            .ok_or_else(|| PlaylistError::UnknownFormat(path.display().to_string()))?;
+        let text = read_text(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        Ok(Self::parse(format, &text, base))
+    }
+
+    // base不为None时，其下的文件写为相对路径
+    pub fn to_string(&self, format: PlaylistFormat, base: Option<&Path>) -> String {
+        let mut out = String::new();
+        match format {
+            PlaylistFormat::M3u => {
+                out.push_str("#EXTM3U\n");
+                for entry in &self.entries {
+                    if entry.title.is_some() || entry.duration.is_some() {
+                        let seconds = entry.duration.map_or(-1, |d| d.as_secs_f64().round() as i64);
+                        let _ = writeln!(out, "#EXTINF:{},{}", seconds, entry.title.as_deref().unwrap_or_default());
+                    }
+                    let _ = writeln!(out, "{}", relative(base, &entry.path).display());
+                }
+            }
+            PlaylistFormat::Pls => {
+                out.push_str("[playlist]\n");
+                for (i, entry) in self.entries.iter().enumerate() {
+                    let _ = writeln!(out, "File{}={}", i + 1, relative(base, &entry.path).display());
+                    if let Some(title) = &entry.title {
+                        let _ = writeln!(out, "Title{}={}", i + 1, title);
+                    }
+                    let seconds = entry.duration.map_or(-1, |d| d.as_secs_f64().round() as i64);
+                    let _ = writeln!(out, "Length{}={}", i + 1, seconds);
+                }
+                let _ = writeln!(out, "NumberOfEntries={}\nVersion=2", self.entries.len());
+            }
+            PlaylistFormat::Xspf => {
+                out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+                out.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n");
+                for entry in &self.entries {
+                    let path = relative(base, &entry.path);
+                    let location = match (path.is_absolute(), path.to_string_lossy()) {
+                        (_, path) if path.contains("://") => path.into_owned(),
+                        (true, path) => format!("file://{}", percent_encode(&path)),
+                        (false, path) => percent_encode(&path),
+                    };
+                    out.push_str("    <track>\n");
+                    let _ = writeln!(out, "      <location>{}</location>", xml_escape(&location));
+                    if let Some(title) = &entry.title {
+                        let _ = writeln!(out, "      <title>{}</title>", xml_escape(title));
+                    }
+                    if let Some(duration) = entry.duration {
+                        let _ = writeln!(out, "      <duration>{}</duration>", duration.as_millis());
+                    }
+                    out.push_str("    </track>\n");
+                }
+                out.push_str("  </trackList>\n</playlist>\n");
+            }
+        }
+        out
+    }
+
+    // 按扩展名选择格式，列表所在目录下的文件保存为相对路径
+    pub fn save(&self, path: &Path) -> Result<(), PlaylistError> {
+        let format = PlaylistFormat::from_path(path)
+            .ok_or_else(|| PlaylistError::UnknownFormat(path.display().to_string()))?;
+        let base = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let base = fs::canonicalize(base).unwrap_or_else(|_| base.to_path_buf());
+        fs::write(path, self.to_string(format, Some(&base)))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_and_relative_paths() {
+        let base = Path::new("/music/lists");
+        let m3u = "\u{feff}#EXTM3U\n#EXTINF:215,Artist - Song\n../a/01 Song.mp3\n\n# comment\n/abs/02.mp3\nfile:///abs/03%20x.mp3\n";
+        let playlist = Playlist::parse(PlaylistFormat::M3u, m3u, base);
+        let paths: Vec<&Path> = playlist.entries.iter().map(|e| e.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [Path::new("/music/lists/../a/01 Song.mp3"), Path::new("/abs/02.mp3"), Path::new("/abs/03 x.mp3")]
+        );
+        assert_eq!(playlist.entries[0].title.as_deref(), Some("Artist - Song"));
+        assert_eq!(playlist.entries[0].duration, Some(Duration::from_secs(215)));
+        assert_eq!(playlist.entries[1].title, None);
+
+        let m3u = "#EXTINF:inf,A\na.mp3\n#EXTINF:1e30,B\nb.mp3\n#EXTINF:NaN,C\nc.mp3\n#EXTINF:-1,D\nd.mp3\n";
+        let playlist = Playlist::parse(PlaylistFormat::M3u, m3u, base);
+        assert_eq!(playlist.entries.len(), 4);
+        assert!(playlist.entries.iter().all(|e| e.duration.is_none()));
+
+        let pls = "[playlist]\nFile2=b.mp3\nfile1=a.mp3\nTitle1=A\nLength1=-1\nLength2=1e30\nNumberOfEntries=2\n";
+        let playlist = Playlist::parse(PlaylistFormat::Pls, pls, base);
+        assert_eq!(playlist.entries[0].path, Path::new("/music/lists/a.mp3"));
+        assert_eq!(playlist.entries[0].title.as_deref(), Some("A"));
+        assert_eq!(playlist.entries[0].duration, None);
+        assert_eq!(playlist.entries[1].duration, None);
+        assert_eq!(playlist.entries[1].path, Path::new("/music/lists/b.mp3"));
+
+        // 保存后重新读取的结果相同
+        let original = Playlist {
+            entries: vec![
+                PlaylistEntry {
+                    path: PathBuf::from("/music/lists/sub/Tom & Jerry #1.mp3"),
+                    title: Some("<Title>".to_string()),
+                    duration: Some(Duration::from_secs(61)),
+                },
+                PlaylistEntry::new(PathBuf::from("/other/x.mp3")),
+            ],
+        };
+        for format in [PlaylistFormat::M3u, PlaylistFormat::Pls, PlaylistFormat::Xspf] {
+            let text = original.to_string(format, Some(base));
+            assert!(!text.contains("/music/lists/sub"));
+            assert_eq!(Playlist::parse(format, &text, base), original, "{:?}", format);
+        }
+    }
+}
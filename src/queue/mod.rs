@@ -0,0 +1,293 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+
+use crate::cue::CueTrack;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Repeat {
+    #[default]
+    Off,
+    // 重复当前曲目
+    One,
+    // 播放完最后一首后回到第一首
+    All,
+}
+
+impl Repeat {
+    // 按键切换时的下一个模式
+    pub fn cycle(self) -> Self {
+        match self {
+            Self::Off => Self::All,
+            Self::All => Self::One,
+            Self::One => Self::Off,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::One => "one",
+            Self::All => "all",
+        }
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+pub fn is_cue(path: &Path) -> bool {
+    has_extension(path, "cue")
+}
+
+// 把文件和目录展开为文件列表，目录中递归查找.mp3和.cue文件并按路径排序，直接给出的文件不检查扩展名
+pub fn collect_files(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+        let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<_>>()?;
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                walk(&path, files)?;
+            } else if has_extension(&path, "mp3") || is_cue(&path) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            walk(path, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+// 队列中的一首：整个文件，或者CUE中的一段
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub cue: Option<CueTrack>,
+    // CUE中的曲目来自的CUE文件
+    pub sheet: Option<PathBuf>,
+}
+
+impl From<PathBuf> for Entry {
+    fn from(path: PathBuf) -> Self {
+        Self { path, cue: None, sheet: None }
+    }
+}
+
+impl From<CueTrack> for Entry {
+    fn from(track: CueTrack) -> Self {
+        Self {
+            path: track.file.clone(),
+            cue: Some(track),
+            sheet: None,
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.cue {
+            Some(track) => write!(f, "{} #{:02}", self.path.display(), track.number),
+            None => write!(f, "{}", self.path.display()),
+        }
+    }
+}
+
+// 把文件列表中的.cue展开为其中的曲目，已经被CUE引用的文件不再单独播放，无法读取的CUE交给on_error
+pub fn expand_cue_sheets(files: Vec<PathBuf>, mut on_error: impl FnMut(&Path, io::Error)) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for path in files {
+        if !is_cue(&path) {
+            entries.push(Entry::from(path));
+            continue;
+        }
+        match CueTrack::load(&path) {
+            Ok(tracks) => entries.extend(tracks.into_iter().map(|track| Entry {
+                sheet: Some(path.clone()),
+                ..Entry::from(track)
+            })),
+            Err(e) => on_error(&path, e),
+        }
+    }
+    let referenced: Vec<PathBuf> = entries.iter().filter_map(|entry| Some(entry.cue.as_ref()?.file.clone())).collect();
+    entries.retain(|entry| entry.cue.is_some() || !referenced.contains(&entry.path));
+    entries
+}
+
+// 播放队列，position为当前曲目在播放顺序中的位置
+pub struct Queue {
+    tracks: Vec<Entry>,
+    // 播放顺序，打乱时为打乱后的下标
+    order: Vec<usize>,
+    position: usize,
+    shuffle: bool,
+    pub repeat: Repeat,
+    // xorshift随机数状态
+    rng: u64,
+}
+
+impl Queue {
+    pub fn new(tracks: Vec<Entry>) -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        Self {
+            order: (0..tracks.len()).collect(),
+            tracks,
+            position: 0,
+            shuffle: false,
+            repeat: Repeat::Off,
+            rng: seed | 1,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: usize) {
+        self.position = position.min(self.len().saturating_sub(1));
+    }
+
+    // 播放顺序中第position首
+    pub fn track(&self, position: usize) -> &Entry {
+        &self.tracks[self.order[position]]
+    }
+
+    // 按播放顺序列出所有曲目
+    pub fn tracks(&self) -> impl Iterator<Item = &Entry> {
+        self.order.iter().map(|&i| &self.tracks[i])
+    }
+
+    pub fn current(&self) -> Option<&Entry> {
+        (!self.is_empty()).then(|| self.track(self.position))
+    }
+
+    // 下一首的位置，manual为false时表示当前曲目自然结束，此时重复单曲返回当前位置
+    pub fn next_position(&self, manual: bool) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        if !manual && self.repeat == Repeat::One {
+            return Some(self.position);
+        }
+        if self.position + 1 < self.len() {
+            Some(self.position + 1)
+        } else {
+            (self.repeat != Repeat::Off).then_some(0)
+        }
+    }
+
+    pub fn previous_position(&self) -> Option<usize> {
+        if self.position > 0 {
+            Some(self.position - 1)
+        } else {
+            (self.repeat != Repeat::Off && !self.is_empty()).then(|| self.len() - 1)
+        }
+    }
+
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    // 打乱时当前曲目排在第一位，其余曲目随机排列；恢复时回到原来的顺序
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if self.is_empty() {
+            return;
+        }
+        let current = self.order[self.position];
+        self.shuffle = shuffle;
+        self.order = (0..self.len()).collect();
+        if !shuffle {
+            self.position = current;
+            return;
+        }
+        self.order.swap(0, current);
+        for i in (2..self.len()).rev() {
+            let j = 1 + (self.random() % i as u64) as usize;
+            self.order.swap(i, j);
+        }
+        self.position = 0;
+    }
+
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shuffle_and_repeat() {
+        let tracks: Vec<PathBuf> = (0..10).map(|i| PathBuf::from(format!("{}.mp3", i))).collect();
+        let mut queue = Queue::new(tracks.iter().cloned().map(Entry::from).collect());
+        queue.rng = 12345;
+        queue.set_position(3);
+        assert_eq!(queue.next_position(false), Some(4));
+
+        queue.set_shuffle(true);
+        assert_eq!(queue.current().unwrap().path, Path::new("3.mp3"));
+        let mut order: Vec<&Path> = (0..10).map(|i| queue.track(i).path.as_path()).collect();
+        assert_ne!(order, tracks);
+        order.sort();
+        assert_eq!(order, tracks);
+        queue.set_position(5);
+        let current = queue.current().unwrap().path.clone();
+        queue.set_shuffle(false);
+        assert_eq!(queue.current().unwrap().path, current);
+
+        queue.set_position(9);
+        assert_eq!(queue.next_position(false), None);
+        queue.repeat = Repeat::One;
+        assert_eq!(queue.next_position(false), Some(9));
+        assert_eq!(queue.next_position(true), Some(0));
+        queue.repeat = Repeat::All;
+        assert_eq!(queue.next_position(false), Some(0));
+        queue.set_position(0);
+        assert_eq!(queue.previous_position(), Some(9));
+    }
+
+    #[test]
+    fn cue_sheets_replace_referenced_files() {
+        let dir = std::env::temp_dir().join(format!("queue_cue_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let sheet = dir.join("album.cue");
+        fs::write(&sheet, "FILE \"album.mp3\" MP3\nTRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nINDEX 01 01:00:00\n").unwrap();
+        let files = vec![dir.join("album.mp3"), sheet.clone(), dir.join("missing.cue"), dir.join("other.mp3")];
+        let mut errors = 0;
+        let entries = expand_cue_sheets(files, |_, _| errors += 1);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(errors, 1);
+        assert_eq!(entries.len(), 3);
+        assert!(entries[..2].iter().all(|entry| entry.path == dir.join("album.mp3") && entry.sheet.as_ref() == Some(&sheet)));
+        assert_eq!(entries[1].cue.as_ref().map(|cue| cue.number), Some(2));
+        assert_eq!(entries[2].path, dir.join("other.mp3"));
+        assert!(entries[2].sheet.is_none());
+    }
+}
@@ -0,0 +1,133 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{
+    ape::ApeTag,
+    id3::{id3v2_tag_size, lyrics3::Lyrics3, v1::Id3v1, Id3v2},
+    metadata::Metadata,
+};
+
+// 文件中所有标签的位置和内容，以及去掉标签后的音频范围
+// 文件结构为 [ID3v2][音频][Lyrics3/APEv2][ID3v1]，末尾的块可以按任意顺序出现
+pub struct FileTags {
+    pub id3v2: Option<Id3v2>,
+    pub id3v1: Option<Id3v1>,
+    pub ape: Option<ApeTag>,
+    pub lyrics3: Option<Lyrics3>,
+    pub audio_start: u64,
+    pub audio_end: u64,
+}
+
+// 查找end之前的APEv2和Lyrics3标签，返回标签和它们之前的位置，标签不会早于audio_start
+pub fn find_ape_lyrics3(
+    read_at: &mut dyn FnMut(u64, usize) -> Option<Vec<u8>>,
+    audio_start: u64,
+    mut end: u64,
+) -> (Option<ApeTag>, Option<Lyrics3>, u64) {
+    let mut ape = None;
+    let mut lyrics3 = None;
+    loop {
+        if ape.is_none() {
+            if let Some((tag, start)) = ApeTag::find(read_at, end).filter(|(_, start)| *start >= audio_start) {
+                ape = Some(tag);
+                end = start;
+                continue;
+            }
+        }
+        if lyrics3.is_none() {
+            if let Some((tag, start)) = Lyrics3::find(read_at, end).filter(|(_, start)| *start >= audio_start) {
+                lyrics3 = Some(tag);
+                end = start;
+                continue;
+            }
+        }
+        return (ape, lyrics3, end);
+    }
+}
+
+impl FileTags {
+    // 从完整的文件数据解析
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let id3v2 = Id3v2::from_bytes(data);
+        let audio_start = id3v2_tag_size(data) as u64;
+        let mut read_at = |pos: u64, len: usize| data.get(pos as usize..pos as usize + len).map(<[u8]>::to_vec);
+        Self::with_trailing(id3v2, audio_start, data.len() as u64, &mut read_at)
+    }
+
+    // 从文件开头读取，之后reader指向音频数据开头
+    pub fn read<R: Read + Seek>(reader: &mut R) -> std::io::Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let id3v2 = Id3v2::new(reader);
+        let audio_start = if id3v2.is_some() { reader.stream_position()? } else { 0 };
+
+        let mut read_at = |pos: u64, len: usize| {
+            let mut buf = vec![0; len];
+            reader.seek(SeekFrom::Start(pos)).ok()?;
+            reader.read_exact(&mut buf).ok()?;
+            Some(buf)
+        };
+        let tags = Self::with_trailing(id3v2, audio_start, len, &mut read_at);
+        reader.seek(SeekFrom::Start(audio_start))?;
+        Ok(tags)
+    }
+
+    fn with_trailing(
+        id3v2: Option<Id3v2>,
+        audio_start: u64,
+        len: u64,
+        read_at: &mut dyn FnMut(u64, usize) -> Option<Vec<u8>>,
+    ) -> Self {
+        let tail = len.saturating_sub(audio_start).min(355) as usize;
+        let id3v1 = read_at(len - tail as u64, tail).and_then(|data| Id3v1::from_bytes(&data));
+        let end = len - id3v1.as_ref().map_or(0, |v1| v1.size() as u64);
+
+        let (ape, lyrics3, end) = find_ape_lyrics3(read_at, audio_start, end);
+
+        Self {
+            id3v2,
+            id3v1,
+            ape,
+            lyrics3,
+            audio_start,
+            audio_end: end,
+        }
+    }
+
+    // 合并所有标签后的元数据，优先级见Metadata
+    pub fn metadata(&self) -> Metadata {
+        Metadata::from_tags(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_tags_are_excluded() {
+        let audio = [0xffu8, 0xfb, 0x90, 0x00];
+        let mut lyrics3 = b"LYRICSBEGININD00002\x31\x30ETT00005Title".to_vec();
+        lyrics3.extend_from_slice(format!("{:06}LYRICS200", lyrics3.len()).as_bytes());
+        let mut ape = 7u32.to_le_bytes().to_vec();
+        ape.extend_from_slice(&[0, 0, 0, 0]);
+        ape.extend_from_slice(b"Artist\x00Someone");
+        let mut footer = b"APETAGEX".to_vec();
+        for value in [2000, ape.len() as u32 + 32, 1, 0] {
+            footer.extend_from_slice(&value.to_le_bytes());
+        }
+        footer.extend_from_slice(&[0; 8]);
+        let mut v1 = [0u8; 128];
+        v1[..3].copy_from_slice(b"TAG");
+        v1[63..68].copy_from_slice(b"Album");
+        v1[127] = 255;
+
+        let data = [&audio[..], &lyrics3, &ape, &footer, &v1].concat();
+        let tags = FileTags::from_bytes(&data);
+        assert_eq!((tags.audio_start, tags.audio_end), (0, 4));
+        let metadata = tags.metadata();
+        assert_eq!(metadata.title.as_deref(), Some("Title"));
+        assert_eq!(metadata.artists, ["Someone"]);
+        assert_eq!(metadata.album.as_deref(), Some("Album"));
+        assert!(metadata.genres.is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    io::{self, IsTerminal},
+    time::Duration,
+};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    style::Print,
+    terminal::{self, Clear, ClearType},
+};
+use tiny_mp3_player::{mpeg_frame::types::MpegChannelMode, player::FrameInfo};
+
+// 播放时的按键操作
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    TogglePause,
+    // 相对跳转的秒数
+    Seek(i64),
+    Volume(f32),
+    ToggleMute,
+    Next,
+    Previous,
+    // 跳到下一章节，或者回到当前章节开头（刚开始时为上一章节）
+    NextChapter,
+    PreviousChapter,
+    ToggleShuffle,
+    CycleRepeat,
+    // 切换到下一个均衡器预设
+    NextPreset,
+    // 选择图示均衡的频段（从0开始）和调整它的增益（dB）
+    SelectBand(usize),
+    BandGain(f64),
+    Quit,
+}
+
+pub const HELP: &str =
+    "[space] pause  [←/→] ±5s  [↑/↓] ±60s  [+/-] volume  [m] mute  [n/b] next/previous  [./,] chapter  [s] shuffle  [r] repeat  [e] EQ preset  [1-0] EQ band  [[/]] EQ gain  [q] quit";
+
+// 终端的raw模式，离开作用域（包括panic）时恢复
+pub struct RawMode;
+
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn key_action(key: KeyEvent) -> Option<Action> {
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+    let action = match key.code {
+        KeyCode::Char(' ') | KeyCode::Char('p') => Action::TogglePause,
+        KeyCode::Left => Action::Seek(-5),
+        KeyCode::Right => Action::Seek(5),
+        KeyCode::Down => Action::Seek(-60),
+        KeyCode::Up => Action::Seek(60),
+        KeyCode::Char('+') | KeyCode::Char('=') => Action::Volume(0.1),
+        KeyCode::Char('-') => Action::Volume(-0.1),
+        KeyCode::Char('m') => Action::ToggleMute,
+        KeyCode::Char('n') => Action::Next,
+        KeyCode::Char('b') => Action::Previous,
+        KeyCode::Char('.') => Action::NextChapter,
+        KeyCode::Char(',') => Action::PreviousChapter,
+        KeyCode::Char('s') => Action::ToggleShuffle,
+        KeyCode::Char('r') => Action::CycleRepeat,
+        KeyCode::Char('e') => Action::NextPreset,
+        KeyCode::Char(c @ '1'..='9') => Action::SelectBand(c as usize - '1' as usize),
+        KeyCode::Char('0') => Action::SelectBand(9),
+        KeyCode::Char('[') => Action::BandGain(-1.0),
+        KeyCode::Char(']') => Action::BandGain(1.0),
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+        KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+        _ => return None,
+    };
+    Some(action)
+}
+
+// 最多等待timeout，返回期间按下的一个操作
+pub fn poll_action(timeout: Duration) -> io::Result<Option<Action>> {
+    if !event::poll(timeout)? {
+        return Ok(None);
+    }
+    match event::read()? {
+        Event::Key(key) => Ok(key_action(key)),
+        _ => Ok(None),
+    }
+}
+
+const BAR_WIDTH: usize = 30;
+
+// mm:ss，超过一小时时为h:mm:ss
+pub fn format_time(time: Duration) -> String {
+    let second = time.as_secs();
+    match second / 3600 {
+        0 => format!("{:02}:{:02}", second / 60, second % 60),
+        hour => format!("{}:{:02}:{:02}", hour, second / 60 % 60, second % 60),
+    }
+}
+
+// 状态行：播放状态、已播放/总时长、剩余时间、进度条、帧头信息和音量，volume为None表示静音
+pub fn status_text(position: Duration, duration: Duration, info: &FrameInfo, paused: bool, volume: Option<f32>) -> String {
+    let position = position.min(duration);
+    let ratio = if duration.is_zero() {
+        0.0
+    } else {
+        position.as_secs_f64() / duration.as_secs_f64()
+    };
+    let filled = ((ratio * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+    format!(
+        "{} {} / {} (-{}) [{}{}] {} kbps, {} Hz, {}, {}",
+        if paused { "||" } else { "> " },
+        format_time(position),
+        format_time(duration),
+        format_time(duration - position),
+        "=".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        info.bit_rate,
+        info.sample_rate,
+        info.channel_mode.as_ref().map_or("-", MpegChannelMode::to_string),
+        volume.map_or("muted".to_string(), |v| format!("vol {:.0}%", v * 100.0)),
+    )
+}
+
+// 在终端最后一行原地刷新的状态行，输出不是终端时不显示
+pub struct StatusLine {
+    enabled: bool,
+    last: String,
+}
+
+impl StatusLine {
+    pub fn new() -> Self {
+        Self {
+            enabled: io::stdout().is_terminal(),
+            last: String::new(),
+        }
+    }
+
+    // 只在内容变化时重绘，超出终端宽度的部分截断以免换行
+    pub fn update(&mut self, text: String) {
+        if !self.enabled || text == self.last {
+            return;
+        }
+        let width = terminal::size().ok().filter(|(columns, _)| *columns > 0).map_or(80, |(columns, _)| columns as usize);
+        let line: String = text.chars().take(width.saturating_sub(1)).collect();
+        let _ = execute!(io::stdout(), Print('\r'), Clear(ClearType::CurrentLine), Print(line));
+        self.last = text;
+    }
+
+    // 在状态行上方输出一行，状态行随后重绘
+    pub fn print_line(&mut self, line: &str) {
+        if !self.enabled {
+            print!("{}\r\n", line);
+            return;
+        }
+        let _ = execute!(io::stdout(), Print('\r'), Clear(ClearType::CurrentLine), Print(line), Print("\r\n"));
+        let last = std::mem::take(&mut self.last);
+        self.update(last);
+    }
+
+    pub fn finish(&mut self) {
+        if self.enabled && !self.last.is_empty() {
+            print!("\r\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_and_status_line() {
+        assert_eq!(format_time(Duration::ZERO), "00:00");
+        assert_eq!(format_time(Duration::from_millis(3_599_999)), "59:59");
+        assert_eq!(format_time(Duration::from_secs(3600)), "1:00:00");
+        assert_eq!(format_time(Duration::from_secs(36000 + 62)), "10:01:02");
+
+        let info = FrameInfo {
+            bit_rate: 128,
+            sample_rate: 44100,
+            channel_mode: Some(MpegChannelMode::JointStereo),
+        };
+        let half = status_text(Duration::from_secs(30), Duration::from_secs(60), &info, false, Some(0.5));
+        assert_eq!(
+            half,
+            format!(">  00:30 / 01:00 (-00:30) [{}{}] 128 kbps, 44100 Hz, Joint Stereo, vol 50%", "=".repeat(15), "-".repeat(15))
+        );
+
+        // 超过总时长时按总时长显示，暂停和静音
+        let end = status_text(Duration::from_secs(90), Duration::from_secs(60), &FrameInfo::default(), true, None);
+        assert_eq!(end, format!("|| 01:00 / 01:00 (-00:00) [{}] 0 kbps, 0 Hz, -, muted", "=".repeat(BAR_WIDTH)));
+
+        // 总时长为0时进度条为空
+        let empty = status_text(Duration::from_secs(5), Duration::ZERO, &info, false, Some(1.0));
+        assert!(empty.starts_with(&format!(">  00:00 / 00:00 (-00:00) [{}]", "-".repeat(BAR_WIDTH))));
+        assert!(empty.ends_with("vol 100%"));
+    }
+}
@@ -0,0 +1,383 @@
+use std::fmt;
+
+use crate::{
+    bitstream::BitStream,
+    edit::{EditError, Mp3Stream},
+    id3::id3v2_tag_size,
+    tags::find_ape_lyrics3,
+    mpeg_frame::{
+        frame_at, frame_crc, parse_header, parse_side_info,
+        types::{MpegProtection, MpegVersion},
+        xing::{lame_crc16, XingHeader},
+        MpegHeader,
+    },
+};
+
+const ID3V1_SIZE: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IssueKind {
+    SyncLost { skipped: usize },
+    TruncatedFrame { expected: usize, actual: usize },
+    BadCrc { expected: u16, actual: u16 },
+    // main_data_begin指向的数据不在比特池中，或与上一帧的主数据重叠
+    ReservoirUnderflow { main_data_begin: usize, available: usize },
+    MainDataOverflow { needed: usize, available: usize },
+    InconsistentHeader(&'static str),
+    Id3v2Truncated { size: usize },
+    Id3v2BadVersion(u8),
+    Id3v2Duplicate,
+    Id3v1Misplaced,
+    Id3v1Duplicate,
+    XingFrameCount { recorded: u32, actual: u32 },
+    XingByteCount { recorded: u32, actual: u32 },
+    LameTagCrc,
+    LameMusicCrc,
+    TrailingData { len: usize },
+    NoFrames,
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SyncLost { skipped } => write!(f, "帧同步丢失，跳过{}字节", skipped),
+            Self::TruncatedFrame { expected, actual } => {
+                write!(f, "帧不完整，应为{}字节，实际{}字节", expected, actual)
+            }
+            Self::BadCrc { expected, actual } => {
+                write!(f, "CRC错误，记录值{:04x}，计算值{:04x}", expected, actual)
+            }
+            Self::ReservoirUnderflow { main_data_begin, available } => write!(
+                f,
+                "比特池错误，main_data_begin为{}，可用数据只有{}字节",
+                main_data_begin, available
+            ),
+            Self::MainDataOverflow { needed, available } => {
+                write!(f, "主数据超出帧尾，需要{}字节，可用{}字节", needed, available)
+            }
+            Self::InconsistentHeader(field) => write!(f, "帧头的{}与第一帧不一致", field),
+            Self::Id3v2Truncated { size } => write!(f, "ID3v2标签长度{}超出文件", size),
+            Self::Id3v2BadVersion(version) => write!(f, "不支持的ID3v2版本: 2.{}", version),
+            Self::Id3v2Duplicate => write!(f, "重复的ID3v2标签"),
+            Self::Id3v1Misplaced => write!(f, "ID3v1标签不在文件末尾"),
+            Self::Id3v1Duplicate => write!(f, "重复的ID3v1标签"),
+            Self::XingFrameCount { recorded, actual } => {
+                write!(f, "Xing头记录的帧数为{}，实际为{}", recorded, actual)
+            }
+            Self::XingByteCount { recorded, actual } => {
+                write!(f, "Xing头记录的字节数为{}，实际为{}", recorded, actual)
+            }
+            Self::LameTagCrc => write!(f, "LAME标签CRC错误"),
+            Self::LameMusicCrc => write!(f, "LAME标签记录的音频数据CRC错误"),
+            Self::TrailingData { len } => write!(f, "文件末尾有{}字节无法识别的数据", len),
+            Self::NoFrames => write!(f, "没有找到MPEG音频帧"),
+        }
+    }
+}
+
+// 问题及其在文件中的字节位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub offset: usize,
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:08x}: {}", self.offset, self.kind)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub frames: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    fn push(&mut self, offset: usize, kind: IssueKind) {
+        self.issues.push(Issue { offset, kind });
+    }
+}
+
+// 比特池状态，丢失同步后重新开始
+#[derive(Default)]
+struct Reservoir {
+    slots: usize,
+    data_end: usize,
+}
+
+impl Reservoir {
+    fn check(&mut self, report: &mut Report, offset: usize, frame: &[u8], header: &MpegHeader) {
+        if header.version != MpegVersion::V1 {
+            return;
+        }
+        let side_start = header.side_info_offset();
+        let side_end = side_start + header.side_info_size();
+        let mut buf = frame[side_start..side_end].to_vec();
+        let info = parse_side_info(header, &mut BitStream::new(&mut buf));
+        let channels = header.channel.to_channel_num();
+        let bits: usize = info
+            .granule
+            .iter()
+            .flat_map(|granule| granule.channel[..channels].iter())
+            .map(|channel| channel.part2_3_length)
+            .sum();
+
+        let slot_len = frame.len() - side_end;
+        let available = self.slots - self.data_end;
+        if info.main_data_end > available {
+            report.push(
+                offset + side_start,
+                IssueKind::ReservoirUnderflow {
+                    main_data_begin: info.main_data_end,
+                    available,
+                },
+            );
+        }
+        let begin = self.slots.saturating_sub(info.main_data_end);
+        let needed = bits.div_ceil(8);
+        if needed > info.main_data_end + slot_len {
+            report.push(
+                offset + side_start,
+                IssueKind::MainDataOverflow {
+                    needed,
+                    available: info.main_data_end + slot_len,
+                },
+            );
+        }
+        self.slots += slot_len;
+        self.data_end = (begin + needed).min(self.slots);
+    }
+}
+
+// 检查pos处是否是下一帧的合理起点：能解析的帧后面紧跟另一帧、标签或文件结尾
+fn is_resync_point(data: &[u8], pos: usize) -> bool {
+    match frame_at(data, pos) {
+        Some((_, size)) => {
+            let next = pos + size;
+            next == data.len()
+                || frame_at(data, next).is_some()
+                || data[next..].starts_with(b"TAG")
+                || data[next..].starts_with(b"ID3")
+        }
+        None => false,
+    }
+}
+
+fn check_id3v2(report: &mut Report, data: &[u8], pos: usize) -> usize {
+    let size = id3v2_tag_size(&data[pos..]);
+    let version = data[pos + 3];
+    if !(2..=4).contains(&version) {
+        report.push(pos, IssueKind::Id3v2BadVersion(version));
+    }
+    let declared = (data[pos + 6] as usize & 0x7f) << 21
+        | (data[pos + 7] as usize & 0x7f) << 14
+        | (data[pos + 8] as usize & 0x7f) << 7
+        | (data[pos + 9] as usize & 0x7f);
+    if pos + declared + 10 > data.len() {
+        report.push(pos, IssueKind::Id3v2Truncated { size: declared });
+    }
+    size
+}
+
+// offset和size为Xing帧的位置和长度，frames为之后的音频帧数
+fn check_xing(report: &mut Report, data: &[u8], offset: usize, size: usize, xing: &XingHeader, header: &MpegHeader, audio_end: usize) {
+    let frames = report.frames;
+    if let Some(recorded) = xing.frames {
+        if recorded as usize != frames {
+            report.push(
+                offset,
+                IssueKind::XingFrameCount {
+                    recorded,
+                    actual: frames as u32,
+                },
+            );
+        }
+    }
+    let actual = (audio_end - offset) as u32;
+    if let Some(recorded) = xing.bytes {
+        // 有的编码器把ID3v2标签也算在内
+        if recorded != actual && recorded as usize != audio_end {
+            report.push(offset, IssueKind::XingByteCount { recorded, actual });
+        }
+    }
+    if let Some(lame) = &xing.lame {
+        let end = xing.size(header);
+        if lame_crc16(&data[offset..offset + end - 2]) != lame.tag_crc {
+            report.push(offset + end - 2, IssueKind::LameTagCrc);
+        }
+        let music_end = offset + lame.music_length as usize;
+        if music_end >= offset + size
+            && music_end <= data.len()
+            && lame_crc16(&data[offset + size..music_end]) != lame.music_crc
+        {
+            report.push(offset + end - 4, IssueKind::LameMusicCrc);
+        }
+    }
+}
+
+// 逐帧检查整个文件
+pub fn validate(data: &[u8]) -> Report {
+    let mut report = Report::default();
+    let mut pos = 0;
+    if data.starts_with(b"ID3") && data.len() >= 10 {
+        pos = check_id3v2(&mut report, data, 0);
+    }
+
+    // 末尾的ID3v1标签
+    let mut audio_end = data.len();
+    if audio_end >= pos + ID3V1_SIZE && data[audio_end - ID3V1_SIZE..].starts_with(b"TAG") {
+        audio_end -= ID3V1_SIZE;
+        if audio_end >= pos + ID3V1_SIZE && data[audio_end - ID3V1_SIZE..].starts_with(b"TAG") {
+            report.push(audio_end - ID3V1_SIZE, IssueKind::Id3v1Duplicate);
+            audio_end -= ID3V1_SIZE;
+        }
+    }
+    // ID3v1之前的APEv2和Lyrics3标签
+    let mut read_at = |p: u64, len: usize| data.get(p as usize..p as usize + len).map(<[u8]>::to_vec);
+    let audio_end = find_ape_lyrics3(&mut read_at, pos as u64, audio_end as u64).2 as usize;
+    let audio = &data[..audio_end];
+
+    let mut first: Option<MpegHeader> = None;
+    let mut xing: Option<(usize, usize, XingHeader, MpegHeader)> = None;
+    let mut reservoir = Reservoir::default();
+    while pos < audio_end {
+        if audio[pos..].starts_with(b"ID3") && audio_end - pos >= 10 {
+            report.push(pos, IssueKind::Id3v2Duplicate);
+            pos += check_id3v2(&mut report, audio, pos);
+            continue;
+        }
+        if audio[pos..].starts_with(b"TAG")
+            && audio_end - pos >= ID3V1_SIZE
+            && frame_at(audio, pos).is_none()
+        {
+            report.push(pos, IssueKind::Id3v1Misplaced);
+            pos += ID3V1_SIZE;
+            continue;
+        }
+
+        let Some((header, size)) = frame_at(audio, pos) else {
+            // 帧头有效但数据不足一帧
+            let header = audio.get(pos..pos + 4).and_then(|b| parse_header(b.try_into().unwrap()).ok());
+            if let Some(header) = header {
+                let expected = header.frame_size();
+                if expected > 0 && pos + expected > audio_end {
+                    report.push(
+                        pos,
+                        IssueKind::TruncatedFrame {
+                            expected,
+                            actual: audio_end - pos,
+                        },
+                    );
+                    break;
+                }
+            }
+            let next = (pos + 1..audio_end).find(|&p| {
+                is_resync_point(audio, p)
+                    || audio[p..].starts_with(b"ID3")
+                    || audio[p..].starts_with(b"TAG") && audio_end - p == ID3V1_SIZE
+            });
+            match next {
+                Some(next) => {
+                    report.push(pos, IssueKind::SyncLost { skipped: next - pos });
+                    pos = next;
+                    reservoir = Reservoir::default();
+                }
+                None => {
+                    if first.is_some() {
+                        report.push(pos, IssueKind::TrailingData { len: audio_end - pos });
+                    } else {
+                        report.push(pos, IssueKind::SyncLost { skipped: audio_end - pos });
+                    }
+                    pos = audio_end;
+                }
+            }
+            continue;
+        };
+        let frame = &audio[pos..pos + size];
+
+        if first.is_none() && xing.is_none() {
+            if let Some(header_xing) = XingHeader::parse(frame, &header) {
+                xing = Some((pos, size, header_xing, header));
+                pos += size;
+                continue;
+            }
+        }
+
+        if header.protection == MpegProtection::Protected {
+            let expected = u16::from_be_bytes([frame[4], frame[5]]);
+            let actual = frame_crc(frame, header.side_info_size());
+            if expected != actual {
+                report.push(pos + 4, IssueKind::BadCrc { expected, actual });
+            }
+        }
+        reservoir.check(&mut report, pos, frame, &header);
+
+        match &first {
+            None => first = Some(header),
+            Some(first) => {
+                let field = if header.version != first.version {
+                    Some("MPEG版本")
+                } else if header.layer != first.layer {
+                    Some("Layer")
+                } else if header.sample_rate.get_rate() != first.sample_rate.get_rate() {
+                    Some("采样率")
+                } else if header.channel.to_channel_num() != first.channel.to_channel_num() {
+                    Some("声道数")
+                } else {
+                    None
+                };
+                if let Some(field) = field {
+                    report.push(pos, IssueKind::InconsistentHeader(field));
+                }
+            }
+        }
+
+        report.frames += 1;
+        pos += size;
+    }
+
+    if let Some((offset, size, xing, header)) = &xing {
+        check_xing(&mut report, data, *offset, *size, xing, header, audio_end);
+    }
+    if report.frames == 0 {
+        report.push(0, IssueKind::NoFrames);
+    }
+    report.issues.sort_by_key(|issue| issue.offset);
+    report
+}
+
+// 写出修复后的文件：去掉无法识别的数据、不完整的帧和重复的标签，修正比特池并重新生成Xing头
+pub fn repair(data: &[u8]) -> Result<Vec<u8>, EditError> {
+    Mp3Stream::parse(data)?.to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::Encoder;
+
+    #[test]
+    fn garbage_is_reported_and_repaired() {
+        let pcm: Vec<f32> = (0..44100)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let mut encoder = Encoder::new(44100, 1, 128).unwrap();
+        let mut data = encoder.encode(&pcm);
+        data.extend(encoder.flush());
+        assert!(validate(&data).issues.is_empty());
+
+        let (_, size) = frame_at(&data, 0).unwrap();
+        data.splice(size..size, *b"garbage");
+        let report = validate(&data);
+        let expected = Issue {
+            offset: size,
+            kind: IssueKind::SyncLost { skipped: 7 },
+        };
+        assert_eq!(report.issues[0], expected);
+
+        let repaired = validate(&repair(&data).unwrap());
+        assert!(repaired.issues.is_empty());
+    }
+}